@@ -7,6 +7,8 @@ use dashmap::DashMap;
 use expanduser::expanduser;
 use notification::ShowMessage;
 use pancake2viper::ir;
+use pancake2viper::cli::get_cache_dir;
+use pancake2viper::pancake::{Frontend, ParseOptions};
 use pancake2viper::utils::{
     ConstEval, EncodeOptions, Mangleable, Mangler, ProgramToViper, ViperHandle,
 };
@@ -22,7 +24,7 @@ struct Backend {
     viper: Mutex<ViperHandle>,
     client: Client,
     file_map: DashMap<String, ir::Program>,
-    cake_path: String,
+    parse_options: ParseOptions,
     current_file: Mutex<RefCell<Option<Url>>>,
 }
 
@@ -79,7 +81,9 @@ impl LanguageServer for Backend {
 impl Backend {
     async fn update_ast(&self, uri: &Url, program: String) -> anyhow::Result<()> {
         self.current_file.lock().await.replace(Some(uri.clone()));
-        let program = pancake2viper::pancake::Program::parse_str(program, &self.cake_path)?;
+        let path = uri.to_file_path().ok();
+        let path = path.as_ref().and_then(|p| p.to_str());
+        let program = pancake2viper::pancake::Program::parse_str(program, path, &self.parse_options)?;
         let program: ir::Program = program.try_into()?;
         self.file_map.insert(uri.to_string(), program);
         Ok(())
@@ -197,7 +201,15 @@ fn expand_home(s: &str) -> String {
 
 #[tokio::main]
 async fn main() {
-    let cake_path = expand_home(&env::var("CAKE_ML").unwrap_or("cake".into()));
+    // `$CAKE_ML` selects the cake frontend, its output is cached like on the command line
+    let frontend = match env::var("CAKE_ML") {
+        Ok(cake_path) => Frontend::Cake {
+            path: expand_home(&cake_path),
+            cache_dir: Some(PathBuf::from(expand_home(&get_cache_dir()))),
+            revision: None,
+        },
+        Err(_) => Frontend::Native,
+    };
     let viper_home = expand_home(&env::var("VIPER_HOME").unwrap());
     let z3 = expand_home(&env::var("Z3_EXE").unwrap());
 
@@ -210,7 +222,10 @@ async fn main() {
         viper,
         client,
         file_map: DashMap::new(),
-        cake_path,
+        parse_options: ParseOptions {
+            frontend,
+            ..Default::default()
+        },
         current_file: Mutex::new(RefCell::new(None)),
     })
     .custom_method("custom/pancakeVerify", Backend::verify_command)
@@ -3,6 +3,7 @@ mod parser;
 mod tests;
 
 pub use parser::{
-    parse_annot, parse_extern_ffi, parse_extern_field, parse_extern_const, parse_extern_predicate, parse_function,
-    parse_method, parse_model_field, parse_model_predicate, parse_predicate, parse_shared,
+    parse_annot, parse_domain, parse_ghost, parse_extern_ffi, parse_extern_field, parse_extern_const, parse_extern_predicate, parse_fnptr, parse_function,
+    parse_lemma, parse_method, parse_model_field, parse_model_predicate, parse_predicate,
+    parse_shared,
 };
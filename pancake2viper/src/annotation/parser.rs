@@ -5,7 +5,7 @@ use pest::pratt_parser::PrattParser;
 use pest::Parser;
 
 use crate::ir::*;
-use crate::utils::Shape;
+use crate::utils::{Shape, Span};
 
 #[derive(pest_derive::Parser)]
 #[grammar = "annotation/annot.pest"]
@@ -19,6 +19,7 @@ lazy_static::lazy_static! {
         PrattParser::new()
             .op(Op::infix(Rule::imp, Right) | Op::infix(Rule::iff, Left))
             .op(Op::postfix(Rule::ternary))
+            .op(Op::infix(Rule::wand, Right))
             .op(Op::infix(Rule::bool_or, Left))
             .op(Op::infix(Rule::bool_and, Left))
             .op(Op::infix(Rule::bit_or, Left))
@@ -27,13 +28,13 @@ lazy_static::lazy_static! {
             .op(Op::infix(Rule::pancake_eq, Left) | Op::infix(Rule::pancake_neq, Left) | Op::infix(Rule::viper_eq, Left) | Op::infix(Rule::viper_neq, Left))
             .op(Op::infix(Rule::gt, Left) | Op::infix(Rule::gte, Left) | Op::infix(Rule::lt, Left) | Op::infix(Rule::lte, Left) | Op::infix(Rule::signed_gt, Left) | Op::infix(Rule::signed_gte, Left) | Op::infix(Rule::signed_lt, Left) | Op::infix(Rule::signed_lte, Left))
             .op(Op::postfix(Rule::shift))
-            .op(Op::infix(Rule::add, Left) | Op::infix(Rule::sub, Left))
+            .op(Op::infix(Rule::add, Left) | Op::infix(Rule::sub, Left) | Op::infix(Rule::concat, Left))
             .op(Op::infix(Rule::mul, Left) | Op::infix(Rule::div, Left) | Op::infix(Rule::modulo, Left))
             .op(Op::infix(Rule::contains, Left))
-            .op(Op::prefix(Rule::neg) | Op::prefix(Rule::minus))
+            .op(Op::prefix(Rule::neg) | Op::prefix(Rule::minus) | Op::prefix(Rule::bit_not))
             .op(Op::postfix(Rule::field_acc))
             .op(Op::postfix(Rule::viper_field_acc))
-            .op(Op::postfix(Rule::arr_acc))
+            .op(Op::postfix(Rule::arr_acc) | Op::postfix(Rule::arr_slice))
     };
 }
 
@@ -45,15 +46,81 @@ pub fn parse_annot(annot: &str, is_stmt: bool) -> ParseResult<Annotation> {
     };
     Ok(AnnotParser::parse(rule, annot).map(|mut pairs| {
         let mut pair = pairs.next().unwrap().into_inner();
-        let typ = AnnotationType::from_pest(pair.next().unwrap());
+        let keyword = pair.next().unwrap();
+        let typ = AnnotationType::from_pest(keyword.clone());
         let expr = match typ {
             AnnotationType::Trusted => Expr::Const(1),
-            _ => parse_expr(Pairs::single(pair.next().unwrap())),
+            AnnotationType::Implements => {
+                Expr::Var(keyword.into_inner().next().unwrap().as_str().to_owned())
+            }
+            _ => parse_outer_expr(Pairs::single(pair.next().unwrap())),
         };
-        Annotation { typ, expr }
+        Annotation {
+            typ,
+            expr,
+            span: None,
+        }
+    })?)
+}
+
+/// Parses a ghost variable declaration or assignment. A declaration scopes over `skip`,
+/// its scope is set when the enclosing block is translated.
+pub fn parse_ghost(ghost: &str) -> ParseResult<Stmt> {
+    Ok(AnnotParser::parse(Rule::ghost_stmt, ghost).map(|mut pairs| {
+        parse_proof_stmt(pairs.next().unwrap().into_inner().next().unwrap())
     })?)
 }
 
+/// Parses a statement of a lemma body or a ghost statement
+fn parse_proof_stmt(stmt: Pair<'_, Rule>) -> Stmt {
+    let rule = stmt.as_rule();
+    let mut pair = stmt.into_inner();
+    match rule {
+        Rule::ghost_decl => Stmt::Ghost(Ghost {
+            decl: Decl::from_pest(pair.next().unwrap()),
+            rhs: pair.next().map(|e| parse_expr(Pairs::single(e))),
+            scope: Box::new(Stmt::Skip),
+        }),
+        Rule::ghost_assign => Stmt::GhostAssign(GhostAssign {
+            lhs: pair.next().unwrap().as_str().to_owned(),
+            rhs: parse_expr(Pairs::single(pair.next().unwrap())),
+        }),
+        Rule::proof_annot => Stmt::Annotation(Annotation {
+            typ: AnnotationType::from_pest(pair.next().unwrap()),
+            expr: parse_outer_expr(Pairs::single(pair.next().unwrap())),
+            span: None,
+        }),
+        Rule::proof_if => Stmt::If(If {
+            cond: parse_expr(Pairs::single(pair.next().unwrap())),
+            if_branch: Box::new(parse_proof_block(pair.next().unwrap())),
+            else_branch: Box::new(match pair.next() {
+                Some(block) if block.as_rule() == Rule::proof_block => parse_proof_block(block),
+                Some(elif) => parse_proof_stmt(elif),
+                None => Stmt::Skip,
+            }),
+        }),
+        _ => unreachable!(),
+    }
+}
+
+fn parse_proof_block(block: Pair<'_, Rule>) -> Stmt {
+    let stmts = block.into_inner().map(parse_proof_stmt).collect::<Vec<_>>();
+    // Ghost variables scope over the remainder of their block
+    let stmts = stmts.into_iter().rev().fold(vec![], |mut rest, mut stmt| {
+        if let Stmt::Ghost(ghost) = &mut stmt {
+            let stmts = rest.into_iter().rev().collect();
+            *ghost.scope = Stmt::Seq(Seq { stmts });
+            vec![stmt]
+        } else {
+            rest.push(stmt);
+            rest
+        }
+    });
+    Stmt::Seq(Seq {
+        stmts: stmts.into_iter().rev().collect(),
+    })
+}
+
 pub fn parse_shared(shared: &str) -> ParseResult<Shared> {
     Ok(
         AnnotParser::parse(Rule::shared_prototype, shared).map(|mut pairs| {
@@ -118,7 +185,7 @@ pub fn parse_predicate(pred: &str) -> ParseResult<Predicate> {
 
 pub fn parse_model_predicate(s: &str) -> ParseResult<Expr> {
     Ok(AnnotParser::parse(Rule::model_predicate, s)
-        .map(|mut pairs| parse_expr(pairs.next().unwrap().into_inner()))?)
+        .map(|mut pairs| parse_outer_expr(pairs.next().unwrap().into_inner()))?)
 }
 
 pub fn parse_model_field(s: &str) -> ParseResult<String> {
@@ -254,6 +321,96 @@ pub fn parse_method(met: &str) -> ParseResult<AbstractMethod> {
     })
 }
 
+pub fn parse_lemma(lemma: &str) -> ParseResult<Lemma> {
+    let (name, args, mut pair) = parse_toplevel_common(lemma, Rule::lemma)?;
+    let preposts = parse_preposts(&mut pair)?;
+    let (pres, posts, others) = partition_annotation_types(preposts);
+    assert!(others.is_empty());
+
+    let decreases = pair.next().unwrap().into_inner().next();
+    let body = parse_proof_block(pair.next().unwrap());
+
+    Ok(Lemma {
+        name,
+        args: args.into_iter().map(Arg::from).collect(),
+        pres: pres.into_iter().map(|a| a.expr).collect(),
+        posts: posts.into_iter().map(|a| a.expr).collect(),
+        decreases: decreases.map(|e| parse_expr(Pairs::single(e))),
+        body,
+    })
+}
+
+pub fn parse_domain(domain: &str) -> ParseResult<Domain> {
+    let mut pair = AnnotParser::parse(Rule::domain, domain)?
+        .next()
+        .unwrap()
+        .into_inner();
+    let name = pair.next().unwrap().as_str().to_owned();
+    let mut functions = vec![];
+    let mut axioms = vec![];
+    for member in pair {
+        let rule = member.as_rule();
+        let mut inner = member.into_inner();
+        match rule {
+            Rule::domain_function => {
+                let fname = inner.next().unwrap().as_str().to_owned();
+                let args = inner.next().unwrap().into_inner().map(Decl::from_pest);
+                functions.push(DomainFunction {
+                    name: fname,
+                    args: args.map(Arg::from).collect(),
+                    typ: Type::from_pest(inner.next().unwrap()),
+                });
+            }
+            Rule::axiom => {
+                // Viper needs a name for every axiom
+                let axiom_name = match inner.peek().unwrap().as_rule() {
+                    Rule::ident => inner.next().unwrap().as_str().to_owned(),
+                    _ => format!("{}_axiom_{}", name, axioms.len()),
+                };
+                axioms.push(Axiom {
+                    name: axiom_name,
+                    body: parse_expr(Pairs::single(inner.next().unwrap())),
+                });
+            }
+            _ => unreachable!(),
+        }
+    }
+    Ok(Domain {
+        name,
+        functions,
+        axioms,
+    })
+}
+
+pub fn parse_fnptr(fnptr: &str) -> ParseResult<FnPtr> {
+    let mut pair = AnnotParser::parse(Rule::fnptr, fnptr)?
+        .next()
+        .unwrap()
+        .into_inner();
+    let name = pair.next().unwrap().as_str().to_owned();
+    let args = pair
+        .next()
+        .unwrap()
+        .into_inner()
+        .map(|arg| {
+            let mut inner = arg.into_inner();
+            let typ = Type::from_pest(inner.next().unwrap());
+            let name = inner.next().unwrap().as_str().to_owned();
+            Arg { name, typ }
+        })
+        .collect();
+
+    let preposts = parse_preposts(&mut pair)?;
+    let (pres, posts, _) = partition_annotation_types(preposts);
+
+    Ok(FnPtr {
+        name,
+        args,
+        pres: pres.into_iter().map(|a| a.expr).collect(),
+        posts: posts.into_iter().map(|a| a.expr).collect(),
+    })
+}
+
 fn parse_toplevel_common(s: &str, rule: Rule) -> ParseResult<(String, Vec<Decl>, Pairs<Rule>)> {
     Ok(AnnotParser::parse(rule, s).map(|mut pairs| {
         let mut pair = pairs.next().unwrap().into_inner();
@@ -268,6 +425,38 @@ fn parse_toplevel_common(s: &str, rule: Rule) -> ParseResult<(String, Vec<Decl>,
     })?)
 }
 
+/// Location of `pair` relative to the start of the parsed text
+fn span_of(pair: &Pair<'_, Rule>) -> Span {
+    let span = pair.as_span();
+    Span::new(None, span.start_pos().line_col(), span.end_pos().line_col())
+}
+
+fn spanned(expr: Expr, span: Span) -> Expr {
+    Expr::Spanned(SpannedExpr {
+        span,
+        expr: Box::new(expr),
+    })
+}
+
+/// Span of an access to `obj` with the accessor `op`
+fn access_span(obj: &Expr, op: &Pair<'_, Rule>) -> Span {
+    match obj {
+        Expr::Spanned(s) => s.span.join(&span_of(op)),
+        _ => span_of(op),
+    }
+}
+
+/// Parses the outermost expression of an annotation, which is located by the
+/// annotation itself
+fn parse_outer_expr(pairs: Pairs<Rule>) -> Expr {
+    match parse_expr(pairs) {
+        Expr::Spanned(s) => *s.expr,
+        expr => expr,
+    }
+}
+
+/// Parses an expression. Identifiers, calls and accesses carry their location
+/// relative to the parsed text, s.t. errors about them point at the subexpression.
 fn parse_expr(pairs: Pairs<Rule>) -> Expr {
     PRATT_PARSER
         .map_primary(|primary| match primary.as_rule() {
@@ -275,18 +464,32 @@ fn parse_expr(pairs: Pairs<Rule>) -> Expr {
             Rule::int_lit => Expr::Const(i64::from_pest(primary)),
             Rule::quantified => Expr::Quantified(Quantified::from_pest(primary)),
             Rule::expr => parse_expr(primary.into_inner()),
-            Rule::ident => Expr::Var(primary.as_str().to_owned()),
+            Rule::ident => spanned(Expr::Var(primary.as_str().to_owned()), span_of(&primary)),
             Rule::old => Expr::Old(Old {
                 expr: Box::new(parse_expr(primary.into_inner())),
             }),
-            Rule::f_call => Expr::FunctionCall(FunctionCall::from_pest(primary)),
+            Rule::f_call => {
+                let span = span_of(&primary);
+                spanned(Expr::FunctionCall(FunctionCall::from_pest(primary)), span)
+            }
             Rule::acc_slice => Expr::AccessSlice(AccessSlice::from_pest(primary)),
             Rule::acc_pred => Expr::AccessPredicate(AccessPredicate::from_pest(primary)),
             Rule::unfolding => Expr::UnfoldingIn(UnfoldingIn::from_pest(primary)),
+            Rule::let_in => Expr::Let(Let::from_pest(primary)),
             Rule::base => Expr::BaseAddr,
             Rule::biw => Expr::BytesInWord,
             Rule::true_lit => Expr::BoolLit(true),
             Rule::false_lit => Expr::BoolLit(false),
+            Rule::heap_view => {
+                let mut pairs = primary.into_inner();
+                let bits = pairs.next().unwrap().as_str()["heap".len()..]
+                    .parse::<u64>()
+                    .unwrap();
+                Expr::LoadBits(LoadBits {
+                    address: Box::new(parse_expr(Pairs::single(pairs.next().unwrap()))),
+                    size: MemOpBytes::from(bits),
+                })
+            }
             Rule::seq_length => Expr::SeqLength(
                 SeqLength {
                     expr: Box::new(parse_expr(primary.into_inner())),
@@ -305,6 +508,10 @@ fn parse_expr(pairs: Pairs<Rule>) -> Expr {
                     left: Box::new(lhs),
                     right: Box::new(rhs),
                 })},
+                Rule::concat => Expr::Concat(Concat {
+                    left: Box::new(lhs),
+                    right: Box::new(rhs),
+                }),
                 _ => { Expr::BinOp(BinOp {
                     optype: BinOpType::from_pest(op),
                     left: Box::new(lhs),
@@ -313,22 +520,46 @@ fn parse_expr(pairs: Pairs<Rule>) -> Expr {
             }
         })
         .map_postfix(|lhs, op| match op.as_rule() {
-            Rule::field_acc => Expr::Field(Field {
-                obj: Box::new(lhs),
-                field_idx: op.into_inner().next().unwrap().as_str().parse().unwrap(),
-            }),
-            Rule::viper_field_acc => Expr::ViperFieldAccess(ViperFieldAccess {
-                obj: Box::new(lhs),
-                field: op.as_str().trim_start_matches('.').to_string(),
-            }),
+            Rule::field_acc => {
+                let span = access_span(&lhs, &op);
+                let field = Expr::Field(Field {
+                    obj: Box::new(lhs),
+                    field_idx: op.into_inner().next().unwrap().as_str().parse().unwrap(),
+                });
+                spanned(field, span)
+            }
+            Rule::viper_field_acc => {
+                let span = access_span(&lhs, &op);
+                let field = Expr::ViperFieldAccess(ViperFieldAccess {
+                    obj: Box::new(lhs),
+                    field: op.as_str().trim_start_matches('.').to_string(),
+                });
+                spanned(field, span)
+            }
             Rule::arr_acc => {
+                let span = access_span(&lhs, &op);
                 let mem_type = op.as_str().split('.').last().unwrap().to_string();
                 let idx = Box::new(parse_expr(op.into_inner()));
-                Expr::ArrayAccess(ArrayAccess {
-                obj: Box::new(lhs),
-                idx: idx,
-                mem_type: mem_type,
-            })},
+                let access = Expr::ArrayAccess(ArrayAccess {
+                    obj: Box::new(lhs),
+                    idx: idx,
+                    mem_type: mem_type,
+                });
+                spanned(access, span)
+            }
+            Rule::arr_slice => {
+                let mut pairs = op.into_inner();
+                let lower = Box::new(parse_expr(Pairs::single(pairs.next().unwrap())));
+                let typ = SliceType::from_pest(pairs.next().unwrap());
+                let upper = Box::new(parse_expr(Pairs::single(pairs.next().unwrap())));
+                Expr::HeapSlice(HeapSlice {
+                    obj: Box::new(lhs),
+                    typ,
+                    lower,
+                    upper,
+                    mem_type: pairs.next().unwrap().as_str().to_owned(),
+                })
+            }
             Rule::ternary => {
                 let mut pairs = op.into_inner();
                 Expr::Ternary(Ternary {
@@ -342,7 +573,7 @@ fn parse_expr(pairs: Pairs<Rule>) -> Expr {
                 Expr::Shift(Shift {
                     shifttype: ShiftType::from_pest(pairs.next().unwrap()),
                     value: Box::new(lhs),
-                    amount: pairs.next().unwrap().as_str().parse().unwrap(),
+                    amount: Box::new(parse_expr(Pairs::single(pairs.next().unwrap()))),
                 })
             }
             _ => unreachable!(),
@@ -416,6 +647,10 @@ impl FromPestPair for AnnotationType {
             Rule::unfold => Self::Unfold,
             Rule::trusted => Self::Trusted,
             Rule::use_f => Self::Use,
+            Rule::implements => Self::Implements,
+            Rule::apply => Self::Apply,
+            Rule::package => Self::Package,
+            Rule::measure => Self::Decreases,
             _ => unreachable!(),
         }
     }
@@ -426,6 +661,7 @@ impl FromPestPair for UnOpType {
         match pair.as_rule() {
             Rule::neg => Self::Neg,
             Rule::minus => Self::Minus,
+            Rule::bit_not => Self::BitNot,
             _ => unreachable!(),
         }
     }
@@ -440,6 +676,7 @@ impl FromPestPair for BinOpType {
             Rule::div => Self::Div,
             Rule::modulo => Self::Modulo,
             Rule::imp => Self::Imp,
+            Rule::wand => Self::Wand,
             Rule::iff => Self::Iff,
             Rule::viper_eq => Self::ViperEqual,
             Rule::viper_neq => Self::ViperNotEqual,
@@ -469,6 +706,7 @@ impl FromPestPair for Type {
             Rule::bool_t => Self::Bool,
             Rule::int_t => Self::Int,
             Rule::ref_t => Self::Ref,
+            Rule::domain_t => Self::Domain(pair.as_str().to_owned()),
             Rule::map_t => {
                 let mut inner = pair.into_inner();
                 let k = Box::new(Type::from_pest(inner.next().unwrap()));
@@ -618,6 +856,20 @@ impl FromPestPair for FunctionCall {
     }
 }
 
+impl FromPestPair for Let {
+    fn from_pest(pair: Pair<'_, Rule>) -> Self {
+        let mut pairs = pair.into_inner();
+        let var = pairs.next().unwrap().as_str().to_owned();
+        let value = parse_expr(Pairs::single(pairs.next().unwrap()));
+        let body = parse_expr(Pairs::single(pairs.next().unwrap()));
+        Self {
+            var,
+            value: Box::new(value),
+            body: Box::new(body),
+        }
+    }
+}
+
 impl FromPestPair for UnfoldingIn {
     fn from_pest(pair: Pair<'_, Rule>) -> Self {
         let mut pairs = pair.into_inner();
@@ -1,6 +1,6 @@
 use crate::ir::*;
 
-use super::{parse_function, parser::parse_annot};
+use super::{parse_domain, parse_function, parse_ghost, parse_lemma, parser::parse_annot};
 
 #[test]
 fn t() {
@@ -61,6 +61,158 @@ fn precedence() {
     }
 }
 
+#[test]
+fn unsigned_comparisons() {
+    let a = parse_annot("assert x <+ y && x <= y", true).unwrap();
+    match a.expr {
+        Expr::BinOp(BinOp {
+            optype: BinOpType::BoolAnd,
+            left,
+            right,
+        }) => {
+            assert!(matches!(*left, Expr::BinOp(BinOp { optype: BinOpType::Lt, .. })));
+            assert!(matches!(*right, Expr::BinOp(BinOp { optype: BinOpType::SignedLte, .. })));
+        }
+        _ => panic!(),
+    }
+    assert_eq!(BinOpType::Lt.to_string(), "<+");
+    assert_eq!(BinOpType::SignedLt.to_string(), "<");
+}
+
+#[test]
+fn bitwise_not_and_shifts() {
+    let a = parse_annot("assert (~mask & x) << (n + 1) == x >> n", true).unwrap();
+    assert_eq!(a.expr.to_string(), "(((~mask & x) << (n + 1)) == (x >> n))");
+    let a = parse_annot("assert ~x >>> 3 == 0", true).unwrap();
+    assert_eq!(a.expr.to_string(), "((~x >>> 3) == 0)");
+}
+
+#[test]
+fn heap_slices() {
+    let a = parse_annot(
+        "ensures heap[dst..dst + n].pan == old(heap[src..src + n].pan)",
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        a.expr.to_string(),
+        "(heap[dst..(dst + n)].pan == old(heap[src..(src + n)].pan))"
+    );
+    let a = parse_annot("assert heap[0..=1].pan ++ heap[2..4].shared == s", true).unwrap();
+    assert_eq!(a.expr.to_string(), "((heap[0..=1].pan ++ heap[2..4].shared) == s)");
+    let a = parse_annot("assert f(heap[a..b].pan) && heap[a].pan == 0", true).unwrap();
+    assert!(matches!(a.expr, Expr::BinOp(BinOp { optype: BinOpType::BoolAnd, .. })));
+}
+
+#[test]
+fn let_bindings() {
+    let a = parse_annot("ensures let end == (q.0 + q.2 * @biw) in retval < end", false).unwrap();
+    assert_eq!(
+        a.expr.to_string(),
+        "(let end == ((q.0 + (q.2 * @biw))) in (retval < end))"
+    );
+    let a = parse_annot("assert let x == (1) in let y == (x) in x == y", true).unwrap();
+    let Expr::Let(outer) = a.expr else { panic!() };
+    assert!(matches!(*outer.body, Expr::Let(Let { ref var, .. }) if var == "y"));
+    assert!(parse_annot("assert let x == 1 in x", true).is_err());
+}
+
+#[test]
+fn domains() {
+    let d = "/@ domain Pair {
+        function pair(a: Int, b: Int): Pair;
+        function fst(p: Pair): Int
+        axiom fst_pair { forall a: Int, b: Int :: fst(pair(a, b)) == a }
+        axiom { true }
+    } @/";
+    let domain = parse_domain(d).unwrap();
+    assert_eq!(domain.name, "Pair");
+    assert_eq!(domain.functions.len(), 2);
+    assert_eq!(domain.functions[0].typ, Type::Domain("Pair".into()));
+    assert_eq!(domain.functions[1].args[0].typ.to_string(), "Pair");
+    assert_eq!(domain.axioms[0].name, "fst_pair");
+    assert_eq!(domain.axioms[1].name, "Pair_axiom_1");
+    assert!(parse_domain("/@ domain D { axiom a } @/").is_err());
+
+    // Builtin types are not mistaken for domains
+    let g = parse_ghost("ghost var s: Seq[Intervals]").unwrap();
+    assert_eq!(g.to_string(), "/@ ghost var s: Seq[Intervals] @/");
+    let g = parse_ghost("ghost var i: Int").unwrap();
+    assert!(matches!(g, Stmt::Ghost(Ghost { decl, .. }) if decl.typ == Type::Int));
+}
+
+#[test]
+fn magic_wands() {
+    let a = parse_annot("package acc(x.f) --* acc(y.f) && acc(x.f)", true).unwrap();
+    assert!(matches!(a.typ, AnnotationType::Package));
+    let wand = "(acc(x.f, write) --* (acc(y.f, write) && acc(x.f, write)))";
+    assert_eq!(a.expr.to_string(), wand);
+    let a = parse_annot("apply a --* b --* c", true).unwrap();
+    assert!(matches!(a.typ, AnnotationType::Apply));
+    assert_eq!(a.expr.to_string(), "(a --* (b --* c))");
+    let a = parse_annot("assert a --* b ==> c", true).unwrap();
+    assert_eq!(a.expr.to_string(), "((a --* b) ==> c)");
+    let a = parse_annot("assert a - -b", true).unwrap();
+    assert_eq!(a.expr.to_string(), "(a - -b)");
+}
+
+#[test]
+fn heap_views() {
+    let a = parse_annot("ensures heap8[@base + 1] == 42", true).unwrap();
+    assert_eq!(a.expr.to_string(), "((ld8 (@base + 1)) == 42)");
+    let a = parse_annot("requires heap16[a] + heap32[a + 4] < 10", true).unwrap();
+    assert_eq!(a.expr.to_string(), "(((ld16 a) + (ld32 (a + 4))) < 10)");
+    let a = parse_annot("assert heap8(x) == heap64[x].pan", true).unwrap();
+    assert_eq!(a.expr.to_string(), "(heap8(x) == heap64[x])");
+}
+
+#[test]
+fn ghost_statements() {
+    let g = parse_ghost("ghost var s: Seq[Int] := heap[a..b].pan").unwrap();
+    assert_eq!(g.to_string(), "/@ ghost var s: Seq[Int] := heap[a..b].pan @/");
+    assert!(matches!(g, Stmt::Ghost(Ghost { scope, .. }) if matches!(*scope, Stmt::Skip)));
+    let g = parse_ghost(" ghost var n: Int ").unwrap();
+    assert_eq!(g.to_string(), "/@ ghost var n: Int @/");
+    let g = parse_ghost("ghost n := n + 1").unwrap();
+    assert_eq!(g.to_string(), "/@ ghost n := (n + 1) @/");
+    assert!(parse_ghost("ghost var n := 1").is_err());
+    assert!(parse_ghost("ghost assert true").is_err());
+}
+
+#[test]
+fn lemmas() {
+    let l = "/@ lemma pow2(n: Int, m: Int)
+        requires n >= 0
+        ensures 1 << n > 0
+        decreases n + m
+    {
+        var k: Int := n - 1
+        if (n == 0) { assert 1 << n == 1 }
+        else if (k > 3) { apply pow2(k, m); unfold p(k) }
+        else { apply pow2(k, m) }
+        assert true;
+    } @/";
+    let lemma = parse_lemma(l).unwrap();
+    assert_eq!(lemma.name, "pow2");
+    assert_eq!(lemma.args.len(), 2);
+    assert_eq!((lemma.pres.len(), lemma.posts.len()), (1, 1));
+    assert_eq!(lemma.decreases.unwrap().to_string(), "(n + m)");
+    // The ghost variable scopes over the rest of the body
+    let Stmt::Seq(body) = lemma.body else { panic!() };
+    let [Stmt::Ghost(ghost)] = &body.stmts[..] else { panic!() };
+    let Stmt::Seq(scope) = &*ghost.scope else { panic!() };
+    assert_eq!(scope.stmts.len(), 2);
+    let Stmt::If(i) = &scope.stmts[0] else { panic!() };
+    assert!(matches!(*i.else_branch, Stmt::If(_)));
+
+    let a = parse_annot("apply pow2(3, 0)", true).unwrap();
+    assert!(matches!(a.typ, AnnotationType::Apply));
+    let a = parse_annot("decreases b - a", true).unwrap();
+    assert!(matches!(a.typ, AnnotationType::Decreases));
+    assert!(parse_lemma("/@ lemma l() ensures true @/").is_err());
+    assert!(parse_lemma("/@ lemma l() { inhale false } @/").is_err());
+}
+
 #[test]
 fn functions() {
     let f = "/@ function sum(heap: Seq[Ref], base: Int, len: Int): Int 
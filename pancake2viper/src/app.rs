@@ -41,9 +41,9 @@ impl Default for App {
 }
 
 impl App {
-    pub fn new_verification(input: String, print: bool) -> Self {
+    pub fn new_verification(input: String, input_path: Option<String>, print: bool) -> Self {
         let options = cli::CliOptions {
-            cmd: cli::Command::Verify(cli::Verify { input }),
+            cmd: cli::Command::Verify(cli::Verify { input, input_path }),
             ..Default::default()
         };
         Self { options, print }
@@ -200,13 +200,17 @@ impl App {
         let use_viper_cli = true;
         let mut viper_handle = ViperHandle::from_handle(viper, self.options.z3_exe.clone());
 
-        let mut program: ir::Program = run_step!(self, "Parsing S-expr from cake", {
-            pancake::Program::parse_str(self.options.cmd.get_input(), &self.options.cake_path)
+        let mut program: ir::Program = run_step!(self, "Parsing Pancake program", {
+            pancake::Program::parse_str(
+                self.options.cmd.get_input(),
+                self.options.cmd.get_input_path(),
+                &self.options.parse_options(),
+            )
         })?
         .try_into()?;
         let encode_opts = self.options.clone().into();
 
-        let fields_set = program.model.fields.clone().into_iter().collect::<HashSet<String>>();
+        let fields_set = program.model.unmangled_names().collect::<HashSet<String>>();
         let consts_set = program.extern_consts.keys().cloned().collect::<HashSet<String>>();
         let mangler_set = fields_set.union(&consts_set).cloned().collect::<HashSet<String>>();
 
@@ -1,9 +1,12 @@
-use std::env;
+use std::{env, path::PathBuf};
 
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_stdin::FileOrStdin;
 
-use crate::utils::EncodeOptions;
+use crate::{
+    pancake::{ExploreRevision, Frontend, ParseOptions},
+    utils::EncodeOptions,
+};
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum ClapCommand {
@@ -47,6 +50,16 @@ impl Command {
         .clone()
     }
 
+    pub fn get_input_path(&self) -> Option<&str> {
+        match self {
+            Self::Verify(v) => &v.input_path,
+            Self::Transpile(v) => &v.input_path,
+            Self::TranspileVerify(v) => &v.input_path,
+            Self::Generate(v) => &v.input_path,
+        }
+        .as_deref()
+    }
+
     pub fn get_output_path(&self) -> Option<String> {
         match self {
             Self::Verify(_) => None,
@@ -75,12 +88,14 @@ pub struct ClapTranspile {
 #[derive(Debug, Clone)]
 pub struct Transpile {
     pub input: String,
+    pub input_path: Option<String>,
     pub output_path: String,
 }
 
 impl From<ClapTranspile> for Transpile {
     fn from(value: ClapTranspile) -> Self {
         Self {
+            input_path: (!value.input.is_stdin()).then(|| value.input.filename().to_owned()),
             input: value.input.contents().unwrap(),
             output_path: value.output_path,
         }
@@ -96,11 +111,13 @@ pub struct ClapVerify {
 #[derive(Debug, Clone)]
 pub struct Verify {
     pub input: String,
+    pub input_path: Option<String>,
 }
 
 impl From<ClapVerify> for Verify {
     fn from(value: ClapVerify) -> Self {
         Self {
+            input_path: (!value.input.is_stdin()).then(|| value.input.filename().to_owned()),
             input: value.input.contents().unwrap(),
         }
     }
@@ -117,12 +134,14 @@ pub struct ClapTranspileVerify {
 #[derive(Debug, Clone)]
 pub struct TranspileVerify {
     pub input: String,
+    pub input_path: Option<String>,
     pub output_path: String,
 }
 
 impl From<ClapTranspileVerify> for TranspileVerify {
     fn from(value: ClapTranspileVerify) -> Self {
         Self {
+            input_path: (!value.input.is_stdin()).then(|| value.input.filename().to_owned()),
             input: value.input.contents().unwrap(),
             output_path: value.output_path,
         }
@@ -140,12 +159,14 @@ pub struct ClapGenerate {
 #[derive(Debug, Clone)]
 pub struct Generate {
     pub input: String,
+    pub input_path: Option<String>,
     pub output_path: String,
 }
 
 impl From<ClapGenerate> for Generate {
     fn from(value: ClapGenerate) -> Self {
         Self {
+            input_path: (!value.input.is_stdin()).then(|| value.input.filename().to_owned()),
             input: value.input.contents().unwrap(),
             output_path: value.output_path,
         }
@@ -206,6 +227,32 @@ pub struct ClapCliOptions {
     )]
     pub cake_path: String,
 
+    #[arg(
+        global = true,
+        long,
+        help = "Parse Pancake programs using cake's explore output instead of the native parser"
+    )]
+    pub cake_frontend: bool,
+
+    #[arg(
+        global = true,
+        long,
+        default_value_t = get_cache_dir(),
+        help = "Directory for caching cake's explore output, can be set via $PANCAKE2VIPER_CACHE"
+    )]
+    pub cache_dir: String,
+
+    #[arg(global = true, long, help = "Always rerun cake, ignoring cached explore output")]
+    pub no_cache: bool,
+
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        help = "Layout of cake's explore output, probed from `cake --version` if not set"
+    )]
+    pub cake_revision: Option<ExploreRevision>,
+
     #[arg(
         global = true,
         long = "viper",
@@ -245,10 +292,24 @@ pub struct ClapCliOptions {
     #[arg(
         global = true,
         long,
-        help = "Ignore warnings related to the shared memory model"
+        help = "Ignore warnings related to the shared memory model and unchecked termination"
     )]
     pub ignore_warnings: bool,
 
+    #[arg(
+        global = true,
+        long,
+        help = "Reject loops and recursive functions without a `decreases` measure"
+    )]
+    pub require_termination: bool,
+
+    #[arg(
+        global = true,
+        long,
+        help = "Compare the operands of signed comparisons (`<`, `<=`, `>`, `>=`) in annotations as unbounded integers, e.g. to bound quantified variables"
+    )]
+    pub unbounded_annot_compare: bool,
+
     #[arg(
         global = true,
         value_delimiter = ' ',
@@ -271,6 +332,23 @@ pub struct ClapCliOptions {
     #[arg(global = true, long, short = 'I', help = "Include Viper files")]
     pub include: Option<Vec<String>>,
 
+    #[arg(
+        global = true,
+        long = "define",
+        short = 'D',
+        value_name = "NAME[=VALUE]",
+        help = "Define a preprocessor macro, also expanded inside annotations"
+    )]
+    pub defines: Vec<String>,
+
+    #[arg(
+        global = true,
+        long = "cpp-include",
+        value_name = "DIR",
+        help = "Add a directory to the preprocessor's #include search path"
+    )]
+    pub cpp_include_dirs: Vec<String>,
+
     #[arg(global = true, long, help = "Generate a counter example")]
     pub counter_example: bool,
 }
@@ -286,6 +364,9 @@ pub struct CliOptions {
     pub heap_base: u64,
     pub heap_top: u64,
     pub cake_path: String,
+    pub cake_frontend: bool,
+    pub cake_revision: Option<ExploreRevision>,
+    pub cache_dir: Option<String>,
     pub viper_path: String,
     pub z3_exe: String,
     pub debug_comments: bool,
@@ -293,11 +374,15 @@ pub struct CliOptions {
     pub model: Option<String>,
     pub allow_undefined_shared: bool,
     pub ignore_warnings: bool,
+    pub require_termination: bool,
+    pub unbounded_annot_compare: bool,
     pub only: Option<Vec<String>>,
     pub incremental: bool,
     pub trust_model: bool,
     pub include: Vec<String>,
     pub counter_example: bool,
+    pub defines: Vec<String>,
+    pub cpp_include_dirs: Vec<String>,
 }
 
 impl From<ClapCliOptions> for CliOptions {
@@ -313,6 +398,9 @@ impl From<ClapCliOptions> for CliOptions {
             heap_base: value.heap_base,
             heap_top: value.heap_top,
             cake_path: value.cake_path,
+            cake_frontend: value.cake_frontend,
+            cake_revision: value.cake_revision,
+            cache_dir: (!value.no_cache).then_some(value.cache_dir),
             viper_path: value.viper_path,
             z3_exe: value.z3_exe,
             debug_comments: value.debug_comments,
@@ -320,11 +408,15 @@ impl From<ClapCliOptions> for CliOptions {
             model: value.model.map(|f| f.contents().unwrap()),
             allow_undefined_shared: value.allow_undefined_shared,
             ignore_warnings: value.ignore_warnings,
+            require_termination: value.require_termination,
+            unbounded_annot_compare: value.unbounded_annot_compare,
             only: value.only,
             incremental: value.incremental,
             trust_model: value.trust_model,
             include: value.include.unwrap_or_default(),
             counter_example: value.counter_example,
+            defines: value.defines,
+            cpp_include_dirs: value.cpp_include_dirs,
         }
     }
 }
@@ -332,7 +424,10 @@ impl From<ClapCliOptions> for CliOptions {
 impl Default for CliOptions {
     fn default() -> Self {
         Self {
-            cmd: Command::Verify(Verify { input: "".into() }),
+            cmd: Command::Verify(Verify {
+                input: "".into(),
+                input_path: None,
+            }),
             word_size: WordSize::Bits64,
             disable_overflow_checks: false,
             bounded_arithmetic: false,
@@ -340,6 +435,9 @@ impl Default for CliOptions {
             heap_base: 0x20000000,
             heap_top: 0x40000000,
             cake_path: get_cake_path(),
+            cake_frontend: false,
+            cake_revision: None,
+            cache_dir: None,
             viper_path: get_viper_path(),
             z3_exe: get_z3_path(),
             debug_comments: false,
@@ -347,11 +445,34 @@ impl Default for CliOptions {
             model: None,
             allow_undefined_shared: false,
             ignore_warnings: false,
+            require_termination: false,
+            unbounded_annot_compare: false,
             only: None,
             incremental: false,
             trust_model: false,
             include: vec![],
             counter_example: false,
+            defines: vec![],
+            cpp_include_dirs: vec![],
+        }
+    }
+}
+
+impl CliOptions {
+    pub fn parse_options(&self) -> ParseOptions {
+        let frontend = if self.cake_frontend {
+            Frontend::Cake {
+                path: self.cake_path.clone(),
+                cache_dir: self.cache_dir.as_ref().map(PathBuf::from),
+                revision: self.cake_revision,
+            }
+        } else {
+            Frontend::Native
+        };
+        ParseOptions {
+            frontend,
+            defines: self.defines.clone(),
+            include_dirs: self.cpp_include_dirs.clone(),
         }
     }
 }
@@ -371,6 +492,8 @@ impl From<CliOptions> for EncodeOptions {
             include_prelude: !value.disable_prelude,
             allow_undefined_shared: value.allow_undefined_shared,
             ignore_warnings: value.ignore_warnings,
+            require_termination: value.require_termination,
+            unbounded_annot_compare: value.unbounded_annot_compare,
         }
     }
 }
@@ -382,8 +505,15 @@ pub fn get_viper_path() -> String {
 }
 
 pub fn get_cake_path() -> String {
-    env::var("CAKE_ML")
-        .expect("Path to CakeML compiler is not provided, try setting it via $CAKE_ML or --cake")
+    // Only needed with `--cake-frontend`, so fall back to looking up `cake` in $PATH
+    env::var("CAKE_ML").unwrap_or("cake".into())
+}
+
+pub fn get_cache_dir() -> String {
+    env::var("PANCAKE2VIPER_CACHE")
+        .or_else(|_| env::var("XDG_CACHE_HOME").map(|dir| format!("{dir}/pancake2viper")))
+        .or_else(|_| env::var("HOME").map(|dir| format!("{dir}/.cache/pancake2viper")))
+        .unwrap_or(".pancake2viper-cache".into())
 }
 
 pub fn get_z3_path() -> String {
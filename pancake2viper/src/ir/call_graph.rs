@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Calls between named declarations, e.g. lemmas applying each other
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    pub fn add_node(&mut self, name: &str) {
+        self.calls.entry(name.to_owned()).or_default();
+    }
+
+    pub fn add_call(&mut self, caller: &str, callee: &str) {
+        self.add_node(callee);
+        self.calls
+            .entry(caller.to_owned())
+            .or_default()
+            .insert(callee.to_owned());
+    }
+
+    /// Groups of declarations that can call themselves, directly or through each other,
+    /// i.e. the strongly connected components that contain a cycle
+    pub fn recursive_components(&self) -> Vec<BTreeSet<String>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: HashMap::new(),
+            low: HashMap::new(),
+            stack: vec![],
+            on_stack: HashSet::new(),
+            components: vec![],
+        };
+        for node in self.calls.keys() {
+            if !tarjan.index.contains_key(node.as_str()) {
+                tarjan.visit(node);
+            }
+        }
+        tarjan
+            .components
+            .into_iter()
+            .filter(|c| c.len() > 1 || c.iter().all(|n| self.calls[n].contains(n)))
+            .collect()
+    }
+}
+
+/// State of Tarjan's strongly connected components algorithm
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    index: HashMap<&'a str, usize>,
+    low: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: HashSet<&'a str>,
+    components: Vec<BTreeSet<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, node: &'a str) {
+        let index = self.index.len();
+        self.index.insert(node, index);
+        self.low.insert(node, index);
+        self.stack.push(node);
+        self.on_stack.insert(node);
+
+        let graph = self.graph;
+        for callee in &graph.calls[node] {
+            let callee = callee.as_str();
+            let low = if !self.index.contains_key(callee) {
+                self.visit(callee);
+                self.low[callee]
+            } else if self.on_stack.contains(callee) {
+                self.index[callee]
+            } else {
+                continue;
+            };
+            if low < self.low[node] {
+                self.low.insert(node, low);
+            }
+        }
+
+        if self.low[node] == index {
+            let mut component = BTreeSet::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.insert(member.to_owned());
+                if member == node {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CallGraph;
+
+    #[test]
+    fn recursive_components() {
+        let mut graph = CallGraph::default();
+        for (caller, callee) in [("a", "b"), ("b", "c"), ("c", "a"), ("c", "d"), ("e", "e")] {
+            graph.add_call(caller, callee);
+        }
+        graph.add_node("f");
+        let components = graph
+            .recursive_components()
+            .into_iter()
+            .map(|c| c.into_iter().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(components.len(), 2);
+        assert!(components.contains(&vec!["a".to_owned(), "b".to_owned(), "c".to_owned()]));
+        assert!(components.contains(&vec!["e".to_owned()]));
+    }
+}
@@ -1,11 +1,11 @@
 use crate::{
-    ir::{self, ShiftType},
+    ir,
     utils::{ConstEval, ConstEvalExpr, EncodeOptions},
 };
 
 use super::{
-    AbstractMethod, BinOp, BinOpType, Expr, FnDec, Function, Model, Predicate, Program, Shared,
-    Shift, Stmt, UnOp, UnOpType,
+    AbstractMethod, Axiom, BinOp, BinOpType, Domain, Expr, FnDec, Function, Global, Lemma, Model,
+    Predicate, Program, Shared, Shift, Stmt, UnOp, UnOpType,
 };
 
 impl ConstEvalExpr for Expr {
@@ -69,6 +69,11 @@ impl ConstEvalExpr for Expr {
                 pred: Box::new(u.pred.const_eval(options)),
                 expr: Box::new(u.expr.const_eval(options)),
             }),
+            Let(l) => Let(ir::Let {
+                var: l.var,
+                value: Box::new(l.value.const_eval(options)),
+                body: Box::new(l.body.const_eval(options)),
+            }),
             Ternary(t) => Ternary(ir::Ternary {
                 cond: Box::new(t.cond.const_eval(options)),
                 left: Box::new(t.left.const_eval(options)),
@@ -92,6 +97,18 @@ impl ConstEvalExpr for Expr {
             SeqLength(s) => SeqLength(ir::SeqLength {
                 expr: Box::new(s.expr.const_eval(options)),
             }),
+            HeapSlice(s) => HeapSlice(ir::HeapSlice {
+                obj: Box::new(s.obj.const_eval(options)),
+                typ: s.typ,
+                lower: Box::new(s.lower.const_eval(options)),
+                upper: Box::new(s.upper.const_eval(options)),
+                mem_type: s.mem_type,
+            }),
+            Concat(c) => Concat(ir::Concat {
+                left: Box::new(c.left.const_eval(options)),
+                right: Box::new(c.right.const_eval(options)),
+            }),
+            Spanned(s) => s.expr.const_eval(options),
         }
     }
 }
@@ -103,12 +120,14 @@ impl ConstEvalExpr for BinOp {
         // XXX: only do const evaluation of arithmetic s.t. we don't break annotations
         match (left, right) {
             (Expr::Const(l), Expr::Const(r))
-                if self.optype.is_arithmetic() || self.optype.is_bitwise() =>
+                if (self.optype.is_arithmetic() || self.optype.is_bitwise())
+                    && self.optype.eval(l, r).is_some() =>
             {
-                Expr::Const(self.optype.eval(l, r))
+                Expr::Const(self.optype.eval(l, r).unwrap())
             }
             (l, Expr::Const(r))
-                if self.optype == BinOpType::BitAnd && (r + 1).count_ones() == 1 =>
+                if self.optype == BinOpType::BitAnd
+                    && r.checked_add(1).is_some_and(|m| m.count_ones() == 1) =>
             {
                 Expr::BinOp(BinOp {
                     optype: BinOpType::Modulo,
@@ -129,8 +148,11 @@ impl ConstEvalExpr for UnOp {
     fn const_eval(self, options: &EncodeOptions) -> Expr {
         match *self.right {
             // XXX: only do const evaluation of arithmetic s.t. we don't break annotations
-            Expr::Const(i) if matches!(self.optype, UnOpType::Minus) => {
-                Expr::Const(self.optype.eval(i))
+            Expr::Const(i)
+                if matches!(self.optype, UnOpType::Minus | UnOpType::BitNot)
+                    && self.optype.eval(i).is_some() =>
+            {
+                Expr::Const(self.optype.eval(i).unwrap())
             }
             _ => Expr::UnOp(UnOp {
                 optype: self.optype,
@@ -145,19 +167,15 @@ impl ConstEvalExpr for UnOp {
 impl ConstEvalExpr for Shift {
     fn const_eval(self, options: &EncodeOptions) -> Expr {
         let value = self.value.const_eval(options);
-        match value {
-            Expr::Const(v) => {
-                let const_value = match self.shifttype {
-                    ShiftType::Asr => v >> self.amount,
-                    ShiftType::Lsl => v << self.amount,
-                    ShiftType::Lsr => ((v as u64) >> self.amount) as i64,
-                };
-                Expr::Const(const_value)
+        let amount = self.amount.const_eval(options);
+        match (value, amount) {
+            (Expr::Const(v), Expr::Const(a)) if (0..64).contains(&a) => {
+                Expr::Const(self.shifttype.eval(v, a as u64))
             }
-            _ => Expr::Shift(Shift {
+            (value, amount) => Expr::Shift(Shift {
                 shifttype: self.shifttype,
                 value: Box::new(value),
-                amount: self.amount,
+                amount: Box::new(amount),
             }),
         }
     }
@@ -171,10 +189,11 @@ impl ConstEval for Stmt {
     fn const_eval(self, options: &EncodeOptions) -> Self {
         use Stmt::*;
         match self {
-            x @ (Skip | Break | Continue | Return) => x,
+            x @ (Skip | Break | Continue | Return | Raise(_)) => x,
             Annotation(annot) => Annotation(ir::Annotation {
                 typ: annot.typ,
                 expr: annot.expr.const_eval(options),
+                span: annot.span,
             }),
             Definition(def) => Definition(ir::Definition {
                 lhs: def.lhs,
@@ -231,6 +250,24 @@ impl ConstEval for Stmt {
                 fname: c.fname,
                 args: const_eval_vec(c.args, options),
             }),
+            Handle(h) => Handle(ir::Handle {
+                call: Box::new(h.call.const_eval(options)),
+                exception: h.exception,
+                handler: Box::new(h.handler.const_eval(options)),
+            }),
+            Ghost(g) => Ghost(ir::Ghost {
+                decl: g.decl,
+                rhs: g.rhs.map(|e| e.const_eval(options)),
+                scope: Box::new(g.scope.const_eval(options)),
+            }),
+            GhostAssign(ass) => GhostAssign(ir::GhostAssign {
+                lhs: ass.lhs,
+                rhs: ass.rhs.const_eval(options),
+            }),
+            Spanned(s) => Spanned(ir::SpannedStmt {
+                span: s.span,
+                stmt: Box::new(s.stmt.const_eval(options)),
+            }),
         }
     }
 }
@@ -252,6 +289,7 @@ impl ConstEval for FnDec {
         Self {
             pres: self.pres.const_eval(options),
             posts: self.posts.const_eval(options),
+            decreases: self.decreases.map(|d| d.const_eval(options)),
             body: self.body.const_eval(options),
             ..self
         }
@@ -269,6 +307,22 @@ impl ConstEval for Function {
     }
 }
 
+impl ConstEval for Domain {
+    fn const_eval(self, options: &EncodeOptions) -> Self {
+        Self {
+            axioms: self
+                .axioms
+                .into_iter()
+                .map(|a| Axiom {
+                    body: a.body.const_eval(options),
+                    ..a
+                })
+                .collect(),
+            ..self
+        }
+    }
+}
+
 impl ConstEval for Predicate {
     fn const_eval(self, options: &EncodeOptions) -> Self {
         Self {
@@ -288,6 +342,18 @@ impl ConstEval for AbstractMethod {
     }
 }
 
+impl ConstEval for Lemma {
+    fn const_eval(self, options: &EncodeOptions) -> Self {
+        Self {
+            pres: self.pres.const_eval(options),
+            posts: self.posts.const_eval(options),
+            decreases: self.decreases.map(|d| d.const_eval(options)),
+            body: self.body.const_eval(options),
+            ..self
+        }
+    }
+}
+
 impl ConstEval for Shared {
     fn const_eval(self, options: &EncodeOptions) -> Self {
         Self {
@@ -304,6 +370,16 @@ impl ConstEval for Model {
         Self {
             fields: self.fields,
             predicates: self.predicates.const_eval(options),
+            globals: self.globals.const_eval(options),
+        }
+    }
+}
+
+impl ConstEval for Global {
+    fn const_eval(self, options: &EncodeOptions) -> Self {
+        Self {
+            value: self.value.const_eval(options),
+            ..self
         }
     }
 }
@@ -313,6 +389,8 @@ impl ConstEval for Program {
         Self {
             functions: self.functions.const_eval(options),
             methods: self.methods.const_eval(options),
+            lemmas: self.lemmas.const_eval(options),
+            domains: self.domains.const_eval(options),
             predicates: self.predicates.const_eval(options),
             viper_functions: self.viper_functions.const_eval(options),
             shared: self.shared.const_eval(options),
@@ -324,3 +402,51 @@ impl ConstEval for Program {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::{BinOp, BinOpType, Expr, UnOp, UnOpType};
+    use crate::utils::{ConstEvalExpr, EncodeOptions};
+
+    fn binop(optype: BinOpType, left: i64, right: i64) -> Expr {
+        Expr::BinOp(BinOp {
+            optype,
+            left: Box::new(Expr::Const(left)),
+            right: Box::new(Expr::Const(right)),
+        })
+    }
+
+    #[test]
+    fn overflow_is_not_folded() {
+        let options = EncodeOptions::default();
+        let mul = binop(BinOpType::Mul, 4, 0x4000000000000000);
+        assert_eq!(mul.clone().const_eval(&options), mul);
+        let add = binop(BinOpType::Add, i64::MAX, 1);
+        assert_eq!(add.clone().const_eval(&options), add);
+        let div = binop(BinOpType::Div, 1, 0);
+        assert_eq!(div.clone().const_eval(&options), div);
+        let mask = binop(BinOpType::BitAnd, 1, i64::MAX);
+        assert_eq!(mask.const_eval(&options), Expr::Const(1));
+        let mul = binop(BinOpType::Mul, 4, 1 << 60);
+        assert_eq!(mul.const_eval(&options), Expr::Const(1 << 62));
+    }
+
+    #[test]
+    fn bit_not_matches_bitvectors() {
+        let options = EncodeOptions::default();
+        let not = |value| {
+            Expr::UnOp(UnOp {
+                optype: UnOpType::BitNot,
+                right: Box::new(Expr::Const(value)),
+            })
+        };
+        // ~5 is 2^64 - 6, which is not an `i64`
+        assert_eq!(not(5).const_eval(&options), not(5));
+        assert_eq!(not(-6).const_eval(&options), Expr::Const(5));
+        let minus = Expr::UnOp(UnOp {
+            optype: UnOpType::Minus,
+            right: Box::new(Expr::Const(i64::MIN)),
+        });
+        assert_eq!(minus.clone().const_eval(&options), minus);
+    }
+}
@@ -42,6 +42,14 @@ impl Display for Stmt {
             }
             Self::ExtCall(call) => write!(f, "@{}({})", call.fname, exprs_to_string(&call.args)),
             Self::Call(call) => write!(f, "{}", call.call),
+            Self::Raise(raise) => write!(f, "raise {};", raise.exception),
+            Self::Handle(h) => write!(f, "{} handle {} ...", h.call, h.exception),
+            Self::Ghost(ghost) => match &ghost.rhs {
+                Some(rhs) => write!(f, "/@ ghost var {} := {} @/", ghost.decl, rhs),
+                None => write!(f, "/@ ghost var {} @/", ghost.decl),
+            },
+            Self::GhostAssign(ass) => write!(f, "/@ ghost {} := {} @/", ass.lhs, ass.rhs),
+            Self::Spanned(s) => write!(f, "{}", s.stmt),
         }
     }
 }
@@ -58,7 +66,7 @@ impl Display for Expr {
             Self::UnOp(op) => write!(f, "{}{}", op.optype, op.right),
             Self::BinOp(op) => write!(f, "({} {} {})", op.left, op.optype, op.right),
             Self::Shift(shift) => {
-                write!(f, "(){} {} {})", shift.value, shift.shifttype, shift.amount)
+                write!(f, "({} {} {})", shift.value, shift.shifttype, shift.amount)
             }
             Self::Load(load) => write!(f, "(lds {} {})", load.shape, load.address),
             Self::LoadBits(load) => write!(f, "(ld{} {})", load.size.bits(), load.address),
@@ -84,10 +92,18 @@ impl Display for Expr {
                 acc.field, acc.lower, acc.typ, acc.upper, acc.perm
             ),
             Self::UnfoldingIn(fold) => write!(f, "(unfolding {} in {})", fold.pred, fold.expr),
+            Self::Let(l) => write!(f, "(let {} == ({}) in {})", l.var, l.value, l.body),
             Self::Ternary(t) => write!(f, "(({}) ? {} : {})", t.cond, t.left, t.right),
             Self::ViperFieldAccess(acc) => write!(f, "{}.{}", acc.obj, acc.field),
             Self::SeqLength(seq) => write!(f, "|{}|", seq.expr),
             Self::Contains(c) => write!(f, "{} in {}", c.left, c.right),
+            Self::HeapSlice(slice) => write!(
+                f,
+                "{}[{}{}{}].{}",
+                slice.obj, slice.lower, slice.typ, slice.upper, slice.mem_type
+            ),
+            Self::Concat(c) => write!(f, "({} ++ {})", c.left, c.right),
+            Self::Spanned(s) => write!(f, "{}", s.expr),
         }
     }
 }
@@ -118,6 +134,7 @@ impl Display for UnOpType {
         match self {
             Self::Neg => write!(f, "!"),
             Self::Minus => write!(f, "-"),
+            Self::BitNot => write!(f, "~"),
         }
     }
 }
@@ -133,15 +150,16 @@ impl Display for BinOpType {
                 Self::Mul => "*",
                 Self::Div => "/",
                 Self::Imp => "==>",
+                Self::Wand => "--*",
                 Self::Iff => "<==>",
-                Self::Gt => ">",
-                Self::Gte => ">=",
-                Self::Lt => "<",
-                Self::Lte => "<=",
-                Self::SignedGt => ">+",
-                Self::SignedGte => ">=+",
-                Self::SignedLt => "<+",
-                Self::SignedLte => "<=+",
+                Self::Gt => ">+",
+                Self::Gte => ">=+",
+                Self::Lt => "<+",
+                Self::Lte => "<=+",
+                Self::SignedGt => ">",
+                Self::SignedGte => ">=",
+                Self::SignedLt => "<",
+                Self::SignedLte => "<=",
                 Self::BitOr => "|",
                 Self::BitAnd => "&",
                 Self::BitXor => "^",
@@ -184,6 +202,10 @@ impl Display for AnnotationType {
                 Self::Unfold => "unfold",
                 Self::Trusted => "trusted",
                 Self::Use => "use",
+                Self::Implements => "implements",
+                Self::Apply => "apply",
+                Self::Package => "package",
+                Self::Decreases => "decreases",
             }
         )
     }
@@ -201,6 +223,7 @@ impl Display for Type {
             Self::Map(k, v) => write!(f, "Map[{}, {}]", *k, *v),
             Self::Seq(i) => write!(f, "Seq[{}]", *i),
             Self::Set(i) => write!(f, "Set[{}]", *i),
+            Self::Domain(name) => write!(f, "{}", name),
         }
     }
 }
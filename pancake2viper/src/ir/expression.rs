@@ -1,6 +1,6 @@
 use strum::EnumString;
 
-use crate::utils::Shape;
+use crate::utils::{Shape, Span};
 
 use super::{MemOpBytes, Type};
 
@@ -25,12 +25,24 @@ pub enum Expr {
     ArrayAccess(ArrayAccess),
     AccessPredicate(AccessPredicate),
     UnfoldingIn(UnfoldingIn),
+    Let(Let),
     Ternary(Ternary),
     AccessSlice(AccessSlice),
     Old(Old),
     ViperFieldAccess(ViperFieldAccess),
     SeqLength(SeqLength),
     Contains(Contains),
+    HeapSlice(HeapSlice),
+    Concat(Concat),
+    Spanned(SpannedExpr),
+}
+
+/// Subexpression annotated with its location in the source. Locations are only
+/// used to report errors, they are dropped when evaluating constant expressions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpannedExpr {
+    pub span: Span,
+    pub expr: Box<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -96,10 +108,13 @@ pub enum BinOpType {
     ViperNotEqual,
     PancakeEqual,
     PancakeNotEqual,
+    /// Unsigned comparisons (`>+`, `>=+`, `<+`, `<=+`), operands are taken modulo 2^word_size
     Gt,
     Gte,
     Lt,
     Lte,
+    /// Signed comparisons (`>`, `>=`, `<`, `<=`). In Pancake code operands are read as
+    /// two's complement words, in annotations they are mathematical integers.
     SignedGt,
     SignedGte,
     SignedLt,
@@ -109,6 +124,8 @@ pub enum BinOpType {
     BitXor,
     BoolAnd,
     BoolOr,
+    /// Magic wand (`--*`), only allowed in annotations
+    Wand,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -128,6 +145,8 @@ pub struct Contains {
 pub enum UnOpType {
     Neg,
     Minus,
+    /// Bitwise complement (`~`) of a 64-bit word
+    BitNot,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -147,7 +166,7 @@ pub enum ShiftType {
 pub struct Shift {
     pub shifttype: ShiftType,
     pub value: Box<Expr>,
-    pub amount: u64,
+    pub amount: Box<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -189,6 +208,14 @@ pub struct UnfoldingIn {
     pub expr: Box<Expr>,
 }
 
+/// Binding of `var` to `value` in `body` (`let var == (value) in body`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Let {
+    pub var: String,
+    pub value: Box<Expr>,
+    pub body: Box<Expr>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Ternary {
     pub cond: Box<Expr>,
@@ -212,6 +239,23 @@ pub struct AccessSlice {
     pub mem: String,    
 }
 
+/// Values of a region of the heap as a `Seq[Int]`, e.g. `heap[a..b].pan`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HeapSlice {
+    pub obj: Box<Expr>,
+    pub typ: SliceType,
+    pub lower: Box<Expr>,
+    pub upper: Box<Expr>,
+    pub mem_type: String,
+}
+
+/// Sequence concatenation (`++`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Concat {
+    pub left: Box<Expr>,
+    pub right: Box<Expr>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Old {
     pub expr: Box<Expr>,
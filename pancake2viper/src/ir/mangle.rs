@@ -1,6 +1,6 @@
 use crate::{
     ir,
-    utils::{Mangleable, Mangler, TranslationError, TranslationMode, VariableType},
+    utils::{Mangleable, Mangler, TranslationError, TranslationMode, VariableType, WithSpan},
 };
 
 impl<T: Mangleable> Mangleable for Vec<T> {
@@ -45,7 +45,10 @@ impl Mangleable for ir::Expr {
                 c.left.mangle(mangler)?;
                 c.right.mangle(mangler)?
             }
-            Shift(shift) => shift.value.mangle(mangler)?,
+            Shift(shift) => {
+                shift.value.mangle(mangler)?;
+                shift.amount.mangle(mangler)?
+            }
             MethodCall(call) => {
                 call.fname = Mangler::mangle_fn(&call.fname);
                 call.args.mangle(mangler)?
@@ -68,6 +71,15 @@ impl Mangleable for ir::Expr {
                 fold.expr.mangle(mangler)?;
                 fold.pred.mangle(mangler)?
             }
+            Let(l) => {
+                // The value can't see the binding, only the body
+                l.value.mangle(mangler)?;
+                let name = l.var.clone();
+                let shadowed;
+                (l.var, shadowed) = mangler.new_let_var(&name)?;
+                l.body.mangle(mangler)?;
+                mangler.drop_let_var(&name, shadowed)
+            }
             Ternary(tern) => {
                 tern.cond.mangle(mangler)?;
                 tern.left.mangle(mangler)?;
@@ -82,6 +94,16 @@ impl Mangleable for ir::Expr {
             Old(old) => old.expr.mangle(mangler)?,
             ViperFieldAccess(field) => field.obj.mangle(mangler)?,
             SeqLength(seq) => seq.expr.mangle(mangler)?,
+            HeapSlice(slice) => {
+                slice.obj.mangle(mangler)?;
+                slice.lower.mangle(mangler)?;
+                slice.upper.mangle(mangler)?;
+            }
+            Concat(c) => {
+                c.left.mangle(mangler)?;
+                c.right.mangle(mangler)?
+            }
+            Spanned(s) => s.expr.mangle(mangler).with_span(&s.span)?,
         }
         Ok(())
     }
@@ -89,11 +111,12 @@ impl Mangleable for ir::Expr {
 
 impl Mangleable for ir::Annotation {
     fn mangle(&mut self, mangler: &mut Mangler) -> Result<(), TranslationError> {
+        let mode = mangler.get_mode();
         mangler.mangle_mode(self.typ.into());
-        if !matches!(self.typ, ir::AnnotationType::Use) {
+        if !matches!(self.typ, ir::AnnotationType::Use | ir::AnnotationType::Implements) {
             self.expr.mangle(mangler)?;
         }
-        mangler.mangle_mode(TranslationMode::Normal);
+        mangler.mangle_mode(mode);
         mangler.clear_annot_var();
         Ok(())
     }
@@ -103,8 +126,10 @@ impl Mangleable for ir::Stmt {
     fn mangle(&mut self, mangler: &mut Mangler) -> Result<(), TranslationError> {
         use ir::Stmt::*;
         match self {
-            Skip | Break | Continue | Return => (),
-            Annotation(annot) => annot.mangle(mangler)?,
+            Skip | Break | Continue | Return | Raise(_) => (),
+            Annotation(annot) => annot
+                .mangle(mangler)
+                .with_opt_span(annot.span.as_ref())?,
             Definition(def) => {
                 def.rhs.mangle(mangler)?;
                 def.lhs = mangler.new_mangled_var(def.lhs.clone(), VariableType::Variable)?;
@@ -153,6 +178,30 @@ impl Mangleable for ir::Stmt {
                 call.args.mangle(mangler)?;
                 call.fname = Mangler::mangle_fn(&call.fname)
             }
+            Handle(h) => {
+                h.call.mangle(mangler)?;
+                h.handler.mangle(mangler)?
+            }
+            Ghost(ghost) => {
+                let mode = mangler.get_mode();
+                mangler.mangle_mode(TranslationMode::Assertion);
+                ghost.rhs.mangle(mangler)?;
+                let name = ghost.decl.name.clone();
+                ghost.decl.name = mangler.new_mangled_var(name.clone(), VariableType::Ghost)?;
+                mangler.mangle_mode(mode);
+                mangler.clear_annot_var();
+                ghost.scope.mangle(mangler)?;
+                mangler.drop_ghost(&name)
+            }
+            GhostAssign(ass) => {
+                ass.lhs = mangler.mangle_ghost(&ass.lhs)?.to_owned();
+                let mode = mangler.get_mode();
+                mangler.mangle_mode(TranslationMode::Assertion);
+                ass.rhs.mangle(mangler)?;
+                mangler.mangle_mode(mode);
+                mangler.clear_annot_var()
+            }
+            Spanned(s) => s.stmt.mangle(mangler).with_span(&s.span)?,
         };
         Ok(())
     }
@@ -173,6 +222,7 @@ impl Mangleable for ir::FnDec {
         self.args.mangle(mangler)?;
         self.pres.mangle(mangler)?;
         self.posts.mangle(mangler)?;
+        self.decreases.mangle(mangler)?;
         self.body.mangle(mangler)
     }
 }
@@ -202,11 +252,45 @@ impl Mangleable for ir::AbstractMethod {
         mangler.switch_ctx(self.name.clone());
         self.name = Mangler::mangle_fn(&self.name);
         self.args.mangle(mangler)?;
+        // Postconditions can mention the returns like arguments
+        for ret in self.rettyps.iter_mut() {
+            ret.name = mangler.new_mangled_var(ret.name.clone(), VariableType::Argument)?;
+        }
         self.pres.mangle(mangler)?;
         self.posts.mangle(mangler)
     }
 }
 
+impl Mangleable for ir::Lemma {
+    fn mangle(&mut self, mangler: &mut Mangler) -> Result<(), TranslationError> {
+        mangler.switch_ctx(self.name.clone());
+        self.name = Mangler::mangle_fn(&self.name);
+        self.args.mangle(mangler)?;
+        self.pres.mangle(mangler)?;
+        self.posts.mangle(mangler)?;
+        self.decreases.mangle(mangler)?;
+        // The body is ghost code, so conditions may mention ghost variables
+        mangler.mangle_mode(TranslationMode::Assertion);
+        self.body.mangle(mangler)?;
+        mangler.mangle_mode(TranslationMode::Normal);
+        Ok(())
+    }
+}
+
+impl Mangleable for ir::Domain {
+    fn mangle(&mut self, mangler: &mut Mangler) -> Result<(), TranslationError> {
+        // Domain functions are called like any other function in annotations
+        for f in self.functions.iter_mut() {
+            f.name = Mangler::mangle_fn(&f.name);
+        }
+        for axiom in self.axioms.iter_mut() {
+            mangler.switch_ctx(axiom.name.clone());
+            axiom.body.mangle(mangler)?;
+        }
+        Ok(())
+    }
+}
+
 impl Mangleable for ir::Model {
     fn mangle(&mut self, mangler: &mut Mangler) -> Result<(), TranslationError> {
         self.predicates.mangle(mangler)
@@ -218,6 +302,8 @@ impl Mangleable for ir::Program {
         self.viper_functions.mangle(mangler)?;
         self.predicates.mangle(mangler)?;
         self.methods.mangle(mangler)?;
+        self.lemmas.mangle(mangler)?;
+        self.domains.mangle(mangler)?;
         self.functions
             .iter_mut()
             .try_for_each(|e| e.mangle(&mut mangler.clone()))?;
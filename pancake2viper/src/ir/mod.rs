@@ -1,3 +1,4 @@
+pub mod call_graph;
 mod const_eval;
 mod display;
 mod expression;
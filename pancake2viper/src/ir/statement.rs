@@ -1,4 +1,6 @@
-use super::expression::Expr;
+use crate::utils::Span;
+
+use super::expression::{Decl, Expr};
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
@@ -20,6 +22,18 @@ pub enum Stmt {
     Call(Call),
     ExtCall(ExtCall),
     Return,
+    Raise(Raise),
+    Handle(Handle),
+    Ghost(Ghost),
+    GhostAssign(GhostAssign),
+    Spanned(SpannedStmt),
+}
+
+/// Statement annotated with its location in the Pancake source
+#[derive(Debug, Clone)]
+pub struct SpannedStmt {
+    pub span: Span,
+    pub stmt: Box<Stmt>,
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +49,20 @@ pub struct Assign {
     pub rhs: Expr,
 }
 
+/// Ghost variable, only visible to annotations in `scope`
+#[derive(Debug, Clone)]
+pub struct Ghost {
+    pub decl: Decl,
+    pub rhs: Option<Expr>,
+    pub scope: Box<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GhostAssign {
+    pub lhs: String,
+    pub rhs: Expr,
+}
+
 // Stores
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -120,6 +148,19 @@ pub struct Return {
     pub value: Expr,
 }
 
+#[derive(Debug, Clone)]
+pub struct Raise {
+    pub exception: String,
+}
+
+/// Call whose exception `exception` is caught by `handler`
+#[derive(Debug, Clone)]
+pub struct Handle {
+    pub call: Box<Stmt>,
+    pub exception: String,
+    pub handler: Box<Stmt>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum AnnotationType {
     Precondition,
@@ -134,10 +175,15 @@ pub enum AnnotationType {
     Unfold,
     Trusted,
     Use,
+    Implements,
+    Apply,
+    Package,
+    Decreases,
 }
 
 #[derive(Debug, Clone)]
 pub struct Annotation {
     pub typ: AnnotationType,
     pub expr: Expr,
+    pub span: Option<Span>,
 }
@@ -1,6 +1,6 @@
 use crate::{
     ir,
-    utils::{Shape, ShapeError, TranslationError, TryToShape, TypeContext},
+    utils::{Shape, ShapeError, TranslationError, TryToShape, TypeContext, WithSpan},
 };
 
 impl TryToShape for ir::Type {
@@ -46,13 +46,15 @@ impl TryToShape for ir::Expr {
             Field(field) => field.to_shape(ctx),
             Struct(struc) => struc.to_shape(ctx),
             UnfoldingIn(unfold) => unfold.expr.to_shape(ctx),
+            Let(l) => l.body.to_shape(ctx),
             MethodCall(call) => ctx.get_function_type(&call.fname)?.to_shape(ctx),
             FunctionCall(call) => ctx.get_function_type(&call.fname)?.to_shape(ctx),
+            Spanned(s) => s.expr.to_shape(ctx).with_span(&s.span),
             x => Ok(match x {
-                Const(_) | UnOp(_) | BinOp(_) | Shift(_) | LoadBits(_) | Quantified(_)
-                | ArrayAccess(_) | AccessPredicate(_) | BaseAddr | BytesInWord => Shape::Simple,
+                Const(_) | Label(_) | UnOp(_) | BinOp(_) | Shift(_) | LoadBits(_) | Quantified(_)
+                | ArrayAccess(_) | AccessPredicate(_) | BaseAddr | BytesInWord | HeapSlice(_)
+                | Concat(_) => Shape::Simple,
                 Var(var) => ctx.get_type_no_mangle(var)?.to_shape(ctx)?,
-                Label(_) => unreachable!("ToShape for Expr::Label"),
                 Load(load) => load.shape.clone(),
                 _ => unreachable!(),
             }),
@@ -10,7 +10,16 @@ pub struct FnDec {
     pub posts: Vec<Expr>,
     pub body: Stmt,
     pub retvar: String,
+    /// Declared return type, otherwise inferred from the returned values
+    pub rettyp: Option<Type>,
     pub trusted: bool,
+    pub implements: Vec<String>,
+    /// Termination measure, required if the function is recursive
+    pub decreases: Option<Expr>,
+    /// Whether the function can call itself, directly or through other functions
+    pub recursive: bool,
+    /// Whether the method reports raised exceptions in the `exception` return variable
+    pub raises: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -45,6 +54,48 @@ pub struct AbstractMethod {
     pub rettyps: Vec<Decl>,
 }
 
+/// Annotation-level method whose body proves its postconditions
+#[derive(Debug, Clone)]
+pub struct Lemma {
+    pub name: String,
+    pub args: Vec<Arg>,
+    pub pres: Vec<Expr>,
+    pub posts: Vec<Expr>,
+    /// Termination measure, required if the lemma is recursive
+    pub decreases: Option<Expr>,
+    pub body: Stmt,
+}
+
+/// Abstract Viper type with uninterpreted functions and axioms about them
+#[derive(Debug, Clone)]
+pub struct Domain {
+    pub name: String,
+    pub functions: Vec<DomainFunction>,
+    pub axioms: Vec<Axiom>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DomainFunction {
+    pub name: String,
+    pub args: Vec<Arg>,
+    pub typ: Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct Axiom {
+    pub name: String,
+    pub body: Expr,
+}
+
+/// Contract shared by all functions that can be called through a function pointer
+#[derive(Debug, Clone)]
+pub struct FnPtr {
+    pub name: String,
+    pub args: Vec<Arg>,
+    pub pres: Vec<Expr>,
+    pub posts: Vec<Expr>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum SharedPerm {
     ReadWrite,
@@ -62,10 +113,21 @@ pub struct Shared {
     pub stride: Expr,
 }
 
+/// Pancake global, stored in a field of the `globals` reference passed to every method
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: String,
+    pub typ: Type,
+    pub value: Expr,
+    /// Never assigned, i.e. static data that keeps its initial value
+    pub constant: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Model {
     pub fields: Vec<String>,
     pub predicates: Vec<Expr>,
+    pub globals: Vec<Global>,
 }
 
 #[derive(Debug, Clone)]
@@ -74,6 +136,8 @@ pub struct Program {
     pub predicates: Vec<Predicate>,
     pub viper_functions: Vec<Function>,
     pub methods: Vec<AbstractMethod>,
+    pub lemmas: Vec<Lemma>,
+    pub domains: Vec<Domain>,
     pub shared: Vec<Shared>,
     pub model: Model,
     pub extern_predicates: Vec<String>,
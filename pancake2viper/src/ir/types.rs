@@ -1,10 +1,10 @@
 use std::rc::Rc;
 
 use crate::{
-    ir::{self, Expr},
+    ir::{self, call_graph::CallGraph, Expr},
     utils::{
-        ExprTypeResolution, Shape, ShapeError::IRSimpleShapeFieldAccess, ToType, TranslationError,
-        TryToShape, TypeContext, TypeResolution,
+        ExprTypeResolution, Shape, ShapeError::IRSimpleShapeFieldAccess, Span, ToType,
+        TranslationError, TryToShape, TypeContext, TypeResolution, WithSpan,
     },
 };
 
@@ -19,6 +19,8 @@ pub enum Type {
     Set(Box<Self>),
     Seq(Box<Self>),
     Map(Box<Self>, Box<Self>),
+    /// User-defined Viper domain
+    Domain(String),
 }
 
 impl ExprTypeResolution for ir::Expr {
@@ -29,7 +31,7 @@ impl ExprTypeResolution for ir::Expr {
     ) -> Result<Type, TranslationError> {
         use ir::Expr::*;
         match self {
-            Const(_) | LoadBits(_) | Shift(_) | BaseAddr | BytesInWord => Ok(Type::Int),
+            Const(_) | Label(_) | LoadBits(_) | Shift(_) | BaseAddr | BytesInWord => Ok(Type::Int),
             BoolLit(_) if is_annot => Ok(Type::Bool),
             BoolLit(_) => Ok(Type::Int),
             ArrayAccess(acc) => acc.resolve_expr_type(is_annot, ctx),
@@ -37,22 +39,35 @@ impl ExprTypeResolution for ir::Expr {
             UnOp(op) => op.resolve_expr_type(is_annot, ctx),
             AccessPredicate(_) | AccessSlice(_) => Ok(Type::Bool),
             Var(name) => ctx.get_type_no_mangle(name),
-            Label(_) => unreachable!(),
             Struct(struc) => Ok(struc.to_shape(ctx)?.to_type(is_annot)),
             Field(field) => Ok(field.to_shape(ctx)?.to_type(is_annot)),
             Load(load) => Ok(load.shape.to_type(is_annot)),
             MethodCall(call) => ctx.get_function_type(&call.fname),
             FunctionCall(call) => ctx.get_function_type(&call.fname),
             UnfoldingIn(fold) => fold.expr.resolve_expr_type(is_annot, ctx),
-            Ternary(tern) => tern.left.resolve_expr_type(is_annot, ctx),
+            Let(l) => {
+                let typ = l.value.resolve_expr_type(is_annot, ctx)?;
+                ctx.set_type(l.var.clone(), typ);
+                l.body.resolve_expr_type(is_annot, ctx)
+            }
+            Ternary(tern) => {
+                tern.cond.resolve_expr_type(is_annot, ctx)?;
+                tern.right.resolve_expr_type(is_annot, ctx)?;
+                tern.left.resolve_expr_type(is_annot, ctx)
+            }
             Quantified(quant) => {
                         quant.decls.resolve_type(is_annot, ctx)?;
+                        // Resolves the `let` bindings of the body
+                        quant.body.resolve_expr_type(is_annot, ctx)?;
                         Ok(Type::Bool)
                     }
             Old(old) => old.expr.resolve_expr_type(is_annot, ctx),
             SeqLength(_) => Ok(Type::Int),
             ViperFieldAccess(acc) => ctx.get_field_type(&acc.field),
             Contains(_) => Ok(Type::Bool),
+            HeapSlice(_) => Ok(Type::Seq(Box::new(Type::Int))),
+            Concat(c) => c.left.resolve_expr_type(is_annot, ctx),
+            Spanned(s) => s.expr.resolve_expr_type(is_annot, ctx).with_span(&s.span),
         }
     }
 }
@@ -136,9 +151,21 @@ impl TypeResolution for ir::Stmt {
                 ctx.set_type(ass.lhs.clone(), rhs_type);
                 Ok(())
             }
-            ir::Stmt::Annotation(annot) => annot.resolve_type(true, ctx),
+            ir::Stmt::Annotation(annot) => annot
+                .resolve_type(true, ctx)
+                .with_opt_span(annot.span.as_ref()),
+            ir::Stmt::Ghost(ghost) => {
+                ghost.decl.resolve_type(true, ctx)?;
+                ghost.rhs.resolve_expr_type(true, ctx)?;
+                ghost.scope.resolve_type(is_annot, ctx)
+            }
+            ir::Stmt::GhostAssign(ass) => {
+                ass.rhs.resolve_expr_type(true, ctx)?;
+                Ok(())
+            }
             ir::Stmt::While(w) => w.body.resolve_type(is_annot, ctx),
             ir::Stmt::Seq(seq) => seq.stmts.resolve_type(is_annot, ctx),
+            ir::Stmt::Spanned(s) => s.stmt.resolve_type(is_annot, ctx).with_span(&s.span),
             _ => Ok(()),
         }
     }
@@ -207,21 +234,76 @@ impl TypeResolution for ir::FnDec {
         self.args.resolve_type(false, ctx)?;
         self.pres.resolve_expr_type(true, ctx)?;
         self.posts.resolve_expr_type(true, ctx)?;
+        self.decreases.resolve_expr_type(true, ctx)?;
         self.body.resolve_type(false, ctx)?;
-        let ret_type = ctx.get_type_no_mangle(&self.retvar);
-        match ret_type {
-            Ok(typ) => {
+
+        // Every `return` has to agree with the declared shape or the first known one
+        let mut returns = vec![];
+        returned_values(&self.body, &self.retvar, None, &mut returns);
+        let mut ret_shape = self.rettyp.as_ref().map(|t| t.to_shape(ctx)).transpose()?;
+        for (span, value) in returns {
+            let shape = match value.to_shape(ctx) {
+                Ok(shape) => shape,
+                Err(e) if e.is_unknown() => continue,
+                Err(e) => return Err(e).with_opt_span(span),
+            };
+            match &ret_shape {
+                Some(expected) if *expected != shape => {
+                    let fname = self.fname.strip_prefix("f_").unwrap_or(&self.fname);
+                    return Err(TranslationError::ReturnShapeMismatch(
+                        fname.to_owned(),
+                        expected.clone(),
+                        shape,
+                    ))
+                    .with_opt_span(span);
+                }
+                Some(_) => (),
+                None => ret_shape = Some(shape),
+            }
+        }
+
+        match ret_shape {
+            Some(shape) => {
+                let typ = shape.to_type(false);
+                ctx.set_type(self.retvar.clone(), typ.clone());
                 ctx.set_type(self.fname.clone(), typ);
                 Ok(())
             }
-            Err(TranslationError::UnknownShape(_)) => {
-                Err(TranslationError::UnknownReturnType(self.fname.clone()))
-            }
-            Err(e) => Err(e),
+            None => Err(TranslationError::UnknownReturnType(self.fname.clone())),
         }
     }
 }
 
+/// Values assigned to the return variable `retvar`, i.e. the values of all `return`s
+fn returned_values<'a>(
+    stmt: &'a ir::Stmt,
+    retvar: &str,
+    span: Option<&'a Span>,
+    values: &mut Vec<(Option<&'a Span>, &'a Expr)>,
+) {
+    use ir::Stmt::*;
+    match stmt {
+        Assign(ass) if ass.lhs == retvar => values.push((span, &ass.rhs)),
+        Definition(def) => returned_values(&def.scope, retvar, span, values),
+        Ghost(ghost) => returned_values(&ghost.scope, retvar, span, values),
+        Seq(seq) => seq
+            .stmts
+            .iter()
+            .for_each(|s| returned_values(s, retvar, span, values)),
+        If(i) => {
+            returned_values(&i.if_branch, retvar, span, values);
+            returned_values(&i.else_branch, retvar, span, values);
+        }
+        While(w) => returned_values(&w.body, retvar, span, values),
+        Handle(h) => {
+            returned_values(&h.call, retvar, span, values);
+            returned_values(&h.handler, retvar, span, values);
+        }
+        Spanned(s) => returned_values(&s.stmt, retvar, Some(&s.span), values),
+        _ => (),
+    }
+}
+
 impl TypeResolution for ir::Decl {
     fn resolve_type(&self, _is_annot: bool, ctx: &mut TypeContext) -> Result<(), TranslationError> {
         ctx.set_type(self.name.clone(), self.typ.clone());
@@ -255,13 +337,54 @@ impl TypeResolution for ir::AbstractMethod {
         self.pres.resolve_expr_type(true, ctx)?;
         self.posts.resolve_expr_type(true, ctx)?;
         self.rettyps.resolve_type(true, ctx)?;
-        assert!(self.rettyps.len() <= 1); // TODO: add support for multiple returns
-        let shape = if self.rettyps.is_empty() {
-            Type::Void
-        } else {
-            ctx.get_type_no_mangle(&self.rettyps[0].name).unwrap()
+        let typ = match &self.rettyps[..] {
+            [] => Type::Void,
+            [ret] => ret.typ.clone(),
+            // Several returns are bundled into a struct with one field per return
+            rets => {
+                let fields = rets
+                    .iter()
+                    .map(|ret| match &ret.typ {
+                        Type::Int => Ok(Shape::Simple),
+                        Type::Struct(shape) => Ok(Shape::Nested(shape.clone())),
+                        typ => Err(TranslationError::NonShapeReturn(
+                            self.name.trim_start_matches("f_").to_owned(),
+                            typ.clone(),
+                        )),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                ctx.set_method_returns(
+                    self.name.clone(),
+                    rets.iter().map(|ret| ret.typ.clone()).collect(),
+                );
+                Type::Struct(fields)
+            }
         };
-        ctx.set_type(self.name.clone(), shape);
+        ctx.set_type(self.name.clone(), typ);
+        Ok(())
+    }
+}
+
+impl TypeResolution for ir::Lemma {
+    fn resolve_type(&self, _is_annot: bool, ctx: &mut TypeContext) -> Result<(), TranslationError> {
+        ctx.set_type(self.name.clone(), Type::Void);
+        self.args.resolve_type(true, ctx)?;
+        self.pres.resolve_expr_type(true, ctx)?;
+        self.posts.resolve_expr_type(true, ctx)?;
+        self.decreases.resolve_expr_type(true, ctx)?;
+        self.body.resolve_type(true, ctx)
+    }
+}
+
+impl TypeResolution for ir::Domain {
+    fn resolve_type(&self, _is_annot: bool, ctx: &mut TypeContext) -> Result<(), TranslationError> {
+        for f in &self.functions {
+            ctx.set_type(f.name.clone(), f.typ.clone());
+            ctx.set_domain_function(f.name.clone(), self.name.clone());
+        }
+        for axiom in &self.axioms {
+            axiom.body.resolve_expr_type(true, ctx)?;
+        }
         Ok(())
     }
 }
@@ -275,7 +398,6 @@ impl TypeResolution for ir::Model {
 impl ir::Program {
     pub fn resolve_types(&self) -> Result<TypeContext, TranslationError> {
         let mut ctx = TypeContext::new(Rc::new(self.extern_fields.clone()));
-        let mut prev_size = ctx.size();
 
         for ffi in &self.extern_methods {
             ctx.set_type(ffi.clone(), Type::Int);
@@ -294,27 +416,88 @@ impl ir::Program {
         for (k, v) in &self.extern_consts {
             ctx.set_type(k.clone(),v.clone());
         }
+        for global in &self.model.globals {
+            let expected = global.typ.to_shape(&ctx)?;
+            let found = global.value.to_shape(&ctx)?;
+            if expected != found {
+                return Err(TranslationError::GlobalShapeMismatch(
+                    global.name.clone(),
+                    expected,
+                    found,
+                ));
+            }
+            ctx.set_type(global.name.clone(), global.typ.clone());
+        }
+        self.resolve_fixpoint(&mut ctx)?;
+
+        // Recursive functions that only return the results of recursive calls never get a
+        // known shape and are assumed to return a single word. Others stay unknown.
+        let unresolved = self
+            .recursive_only_functions()
+            .into_iter()
+            .filter(|f| ctx.get_type_no_mangle(&f.fname).is_err())
+            .collect::<Vec<_>>();
+        if !unresolved.is_empty() {
+            for f in unresolved {
+                ctx.set_type(f.retvar.clone(), Type::Int);
+                ctx.set_type(f.fname.clone(), Type::Int);
+            }
+            self.resolve_fixpoint(&mut ctx)?;
+        }
+        Ok(ctx)
+    }
+
+    /// Functions in a recursive component whose `return`s all call a function of it
+    fn recursive_only_functions(&self) -> Vec<&ir::FnDec> {
+        let mut graph = CallGraph::default();
+        for f in &self.functions {
+            graph.add_node(&f.fname);
+            f.body.visit_exprs(&mut |e| {
+                if let Expr::MethodCall(call) = e {
+                    graph.add_call(&f.fname, &call.fname);
+                }
+            });
+        }
+        let components = graph.recursive_components();
+        self.functions
+            .iter()
+            .filter(|f| {
+                let Some(component) = components.iter().find(|c| c.contains(&f.fname)) else {
+                    return false;
+                };
+                let mut returns = vec![];
+                returned_values(&f.body, &f.retvar, None, &mut returns);
+                returns.iter().all(|(_, value)| match value.unspanned() {
+                    Expr::MethodCall(call) => component.contains(&call.fname),
+                    _ => false,
+                })
+            })
+            .collect()
+    }
+
+    /// Resolves types until no new ones are found
+    fn resolve_fixpoint(&self, ctx: &mut TypeContext) -> Result<(), TranslationError> {
+        let mut prev_size = ctx.size();
         loop {
-            ignore_unknown(self.viper_functions.resolve_type(true, &mut ctx))?;
-            ignore_unknown(self.predicates.resolve_type(true, &mut ctx))?;
-            ignore_unknown(self.methods.resolve_type(true, &mut ctx))?;
-            ignore_unknown(self.functions.resolve_type(false, &mut ctx))?;
-            ignore_unknown(self.model.resolve_type(true, &mut ctx))?;
+            ignore_unknown(self.domains.resolve_type(true, ctx))?;
+            ignore_unknown(self.viper_functions.resolve_type(true, ctx))?;
+            ignore_unknown(self.predicates.resolve_type(true, ctx))?;
+            ignore_unknown(self.methods.resolve_type(true, ctx))?;
+            ignore_unknown(self.lemmas.resolve_type(true, ctx))?;
+            ignore_unknown(self.functions.resolve_type(false, ctx))?;
+            ignore_unknown(self.model.resolve_type(true, ctx))?;
             let new_size = ctx.size();
             if new_size == prev_size {
-                break;
+                return Ok(());
             }
             prev_size = new_size;
         }
-        Ok(ctx)
     }
 }
 
 fn ignore_unknown(result: Result<(), TranslationError>) -> Result<(), TranslationError> {
     match result {
-        Ok(_)
-        | Err(TranslationError::UnknownReturnType(_))
-        | Err(TranslationError::UnknownShape(_)) => Ok(()),
-        Err(x) => Err(x),
+        Err(x) if !x.is_unknown() => Err(x),
+        _ => Ok(()),
     }
 }
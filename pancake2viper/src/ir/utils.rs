@@ -10,8 +10,8 @@ use crate::{
 use super::{
     expression::{Expr, Struct},
     shared::SharedOpType,
-    statement::MemOpBytes,
-    Arg, BinOp, BinOpType, Decl, Model, Program, SharedPerm, ShiftType, Type, UnOpType,
+    statement::{Ghost, MemOpBytes, Stmt},
+    Arg, BinOp, BinOpType, Decl, Global, Model, Program, SharedPerm, ShiftType, Type, UnOpType,
 };
 
 impl Expr {
@@ -35,6 +35,243 @@ impl Expr {
             }),
         }
     }
+
+    /// Calls `f` on the expression and all of its subexpressions
+    pub fn visit(&self, f: &mut impl FnMut(&Expr)) {
+        f(self);
+        let children: Vec<&Expr> = match self {
+            Self::Struct(s) => s.elements.iter().collect(),
+            Self::Field(field) => vec![&field.obj],
+            Self::Load(load) => vec![&load.address],
+            Self::LoadBits(load) => vec![&load.address],
+            Self::BinOp(op) => vec![&op.left, &op.right],
+            Self::UnOp(op) => vec![&op.right],
+            Self::Shift(shift) => vec![&shift.value, &shift.amount],
+            Self::MethodCall(call) => call.args.iter().collect(),
+            Self::FunctionCall(call) => call.args.iter().collect(),
+            Self::Quantified(quant) => quant.triggers.iter().chain([&*quant.body]).collect(),
+            Self::ArrayAccess(acc) => vec![&acc.obj, &acc.idx],
+            Self::AccessPredicate(acc) => vec![&acc.field],
+            Self::UnfoldingIn(fold) => vec![&fold.pred, &fold.expr],
+            Self::Let(l) => vec![&l.value, &l.body],
+            Self::Ternary(tern) => vec![&tern.cond, &tern.left, &tern.right],
+            Self::AccessSlice(acc) => vec![&acc.field, &acc.lower, &acc.upper],
+            Self::Old(old) => vec![&old.expr],
+            Self::ViperFieldAccess(acc) => vec![&acc.obj],
+            Self::SeqLength(s) => vec![&s.expr],
+            Self::Contains(c) => vec![&c.left, &c.right],
+            Self::HeapSlice(slice) => vec![&slice.obj, &slice.lower, &slice.upper],
+            Self::Concat(c) => vec![&c.left, &c.right],
+            Self::Spanned(s) => vec![&s.expr],
+            Self::Const(_)
+            | Self::BoolLit(_)
+            | Self::Var(_)
+            | Self::Label(_)
+            | Self::BaseAddr
+            | Self::BytesInWord => vec![],
+        };
+        for child in children {
+            child.visit(f);
+        }
+    }
+
+    /// Calls `f` on the expression and then on the subexpressions of its result
+    pub fn visit_mut(&mut self, f: &mut impl FnMut(&mut Expr)) {
+        f(self);
+        let children: Vec<&mut Expr> = match self {
+            Self::Struct(s) => s.elements.iter_mut().collect(),
+            Self::Field(field) => vec![&mut field.obj],
+            Self::Load(load) => vec![&mut load.address],
+            Self::LoadBits(load) => vec![&mut load.address],
+            Self::BinOp(op) => vec![&mut op.left, &mut op.right],
+            Self::UnOp(op) => vec![&mut op.right],
+            Self::Shift(shift) => vec![&mut shift.value, &mut shift.amount],
+            Self::MethodCall(call) => call.args.iter_mut().collect(),
+            Self::FunctionCall(call) => call.args.iter_mut().collect(),
+            Self::Quantified(quant) => quant
+                .triggers
+                .iter_mut()
+                .chain([&mut *quant.body])
+                .collect(),
+            Self::ArrayAccess(acc) => vec![&mut acc.obj, &mut acc.idx],
+            Self::AccessPredicate(acc) => vec![&mut acc.field],
+            Self::UnfoldingIn(fold) => vec![&mut fold.pred, &mut fold.expr],
+            Self::Let(l) => vec![&mut l.value, &mut l.body],
+            Self::Ternary(tern) => vec![&mut tern.cond, &mut tern.left, &mut tern.right],
+            Self::AccessSlice(acc) => vec![&mut acc.field, &mut acc.lower, &mut acc.upper],
+            Self::Old(old) => vec![&mut old.expr],
+            Self::ViperFieldAccess(acc) => vec![&mut acc.obj],
+            Self::SeqLength(s) => vec![&mut s.expr],
+            Self::Contains(c) => vec![&mut c.left, &mut c.right],
+            Self::HeapSlice(slice) => vec![&mut slice.obj, &mut slice.lower, &mut slice.upper],
+            Self::Concat(c) => vec![&mut c.left, &mut c.right],
+            Self::Spanned(s) => vec![&mut s.expr],
+            Self::Const(_)
+            | Self::BoolLit(_)
+            | Self::Var(_)
+            | Self::Label(_)
+            | Self::BaseAddr
+            | Self::BytesInWord => vec![],
+        };
+        for child in children {
+            child.visit_mut(f);
+        }
+    }
+
+    /// The expression without the locations attached to it
+    pub fn unspanned(&self) -> &Expr {
+        match self {
+            Self::Spanned(s) => s.expr.unspanned(),
+            x => x,
+        }
+    }
+}
+
+impl Stmt {
+    /// Calls `f` on all expressions of the statement, including nested statements and annotations
+    pub fn visit_exprs(&self, f: &mut impl FnMut(&Expr)) {
+        match self {
+            Self::Annotation(annot) => annot.expr.visit(f),
+            Self::Definition(def) => {
+                def.rhs.visit(f);
+                def.scope.visit_exprs(f);
+            }
+            Self::Assign(ass) => ass.rhs.visit(f),
+            Self::Store(store) => {
+                store.address.visit(f);
+                store.value.visit(f);
+            }
+            Self::StoreBits(store) => {
+                store.address.visit(f);
+                store.value.visit(f);
+            }
+            Self::SharedStore(store) => {
+                store.address.visit(f);
+                store.value.visit(f);
+            }
+            Self::SharedStoreBits(store) => {
+                store.address.visit(f);
+                store.value.visit(f);
+            }
+            Self::SharedLoad(load) => {
+                load.address.visit(f);
+                load.dst.visit(f);
+            }
+            Self::SharedLoadBits(load) => {
+                load.address.visit(f);
+                load.dst.visit(f);
+            }
+            Self::Seq(seq) => seq.stmts.iter().for_each(|s| s.visit_exprs(f)),
+            Self::If(i) => {
+                i.cond.visit(f);
+                i.if_branch.visit_exprs(f);
+                i.else_branch.visit_exprs(f);
+            }
+            Self::While(w) => {
+                w.cond.visit(f);
+                w.body.visit_exprs(f);
+            }
+            Self::Call(call) => call.call.visit(f),
+            Self::ExtCall(call) => call.args.iter().for_each(|e| e.visit(f)),
+            Self::Handle(h) => {
+                h.call.visit_exprs(f);
+                h.handler.visit_exprs(f);
+            }
+            Self::Ghost(ghost) => {
+                ghost.rhs.iter().for_each(|e| e.visit(f));
+                ghost.scope.visit_exprs(f);
+            }
+            Self::GhostAssign(ass) => ass.rhs.visit(f),
+            Self::Spanned(s) => s.stmt.visit_exprs(f),
+            Self::Skip | Self::Break | Self::Continue | Self::Return | Self::Raise(_) => (),
+        }
+    }
+
+    /// Calls `f` on all expressions of the statement, see [`Stmt::visit_exprs`]
+    pub fn visit_exprs_mut(&mut self, f: &mut impl FnMut(&mut Expr)) {
+        match self {
+            Self::Annotation(annot) => annot.expr.visit_mut(f),
+            Self::Definition(def) => {
+                def.rhs.visit_mut(f);
+                def.scope.visit_exprs_mut(f);
+            }
+            Self::Assign(ass) => ass.rhs.visit_mut(f),
+            Self::Store(store) => {
+                store.address.visit_mut(f);
+                store.value.visit_mut(f);
+            }
+            Self::StoreBits(store) => {
+                store.address.visit_mut(f);
+                store.value.visit_mut(f);
+            }
+            Self::SharedStore(store) => {
+                store.address.visit_mut(f);
+                store.value.visit_mut(f);
+            }
+            Self::SharedStoreBits(store) => {
+                store.address.visit_mut(f);
+                store.value.visit_mut(f);
+            }
+            Self::SharedLoad(load) => {
+                load.address.visit_mut(f);
+                load.dst.visit_mut(f);
+            }
+            Self::SharedLoadBits(load) => {
+                load.address.visit_mut(f);
+                load.dst.visit_mut(f);
+            }
+            Self::Seq(seq) => seq.stmts.iter_mut().for_each(|s| s.visit_exprs_mut(f)),
+            Self::If(i) => {
+                i.cond.visit_mut(f);
+                i.if_branch.visit_exprs_mut(f);
+                i.else_branch.visit_exprs_mut(f);
+            }
+            Self::While(w) => {
+                w.cond.visit_mut(f);
+                w.body.visit_exprs_mut(f);
+            }
+            Self::Call(call) => call.call.visit_mut(f),
+            Self::ExtCall(call) => call.args.iter_mut().for_each(|e| e.visit_mut(f)),
+            Self::Handle(h) => {
+                h.call.visit_exprs_mut(f);
+                h.handler.visit_exprs_mut(f);
+            }
+            Self::Ghost(ghost) => {
+                ghost.rhs.iter_mut().for_each(|e| e.visit_mut(f));
+                ghost.scope.visit_exprs_mut(f);
+            }
+            Self::GhostAssign(ass) => ass.rhs.visit_mut(f),
+            Self::Spanned(s) => s.stmt.visit_exprs_mut(f),
+            Self::Skip | Self::Break | Self::Continue | Self::Return | Self::Raise(_) => (),
+        }
+    }
+
+    /// Whether the statement writes to the variable `var`
+    pub fn assigns(&self, var: &str) -> bool {
+        let is_var = |e: &Expr| matches!(e, Expr::Var(v) if v == var);
+        match self {
+            Self::Assign(ass) => ass.lhs == var,
+            Self::SharedLoad(load) => is_var(&load.dst),
+            Self::SharedLoadBits(load) => is_var(&load.dst),
+            Self::Definition(def) => def.scope.assigns(var),
+            Self::Ghost(ghost) => ghost.scope.assigns(var),
+            Self::Seq(seq) => seq.stmts.iter().any(|s| s.assigns(var)),
+            Self::If(i) => i.if_branch.assigns(var) || i.else_branch.assigns(var),
+            Self::While(w) => w.body.assigns(var),
+            Self::Handle(h) => h.call.assigns(var) || h.handler.assigns(var),
+            Self::Spanned(s) => s.stmt.assigns(var),
+            _ => false,
+        }
+    }
+
+    /// The ghost variable declared by the statement, if any
+    pub fn as_ghost_mut(&mut self) -> Option<&mut Ghost> {
+        match self {
+            Self::Ghost(ghost) => Some(ghost),
+            Self::Spanned(s) => s.stmt.as_ghost_mut(),
+            _ => None,
+        }
+    }
 }
 
 impl Struct {
@@ -109,7 +346,7 @@ impl ToType for BinOpType {
     fn to_type(&self, is_annot: bool) -> super::Type {
         use BinOpType::*;
         match self {
-            ViperEqual | ViperNotEqual | Iff | Imp => Type::Bool,
+            ViperEqual | ViperNotEqual | Iff | Imp | Wand => Type::Bool,
             Gt | Gte | Lt | Lte | SignedGt | SignedGte | SignedLt | SignedLte | BoolAnd
             | BoolOr | PancakeEqual | PancakeNotEqual => {
                 if is_annot {
@@ -126,7 +363,7 @@ impl ToType for BinOpType {
 impl ToType for UnOpType {
     fn to_type(&self, is_annot: bool) -> super::Type {
         match self {
-            UnOpType::Minus => Type::Int,
+            UnOpType::Minus | UnOpType::BitNot => Type::Int,
             UnOpType::Neg => {
                 if is_annot {
                     Type::Bool
@@ -151,16 +388,13 @@ impl Add<i64> for Expr {
 }
 
 impl UnOpType {
-    pub fn eval(&self, value: i64) -> i64 {
+    /// Evaluates the operator, `None` if the result does not fit into an `i64`.
+    /// `~` is taken on 64-bit words, like in its bitvector encoding.
+    pub fn eval(&self, value: i64) -> Option<i64> {
         match self {
-            Self::Minus => -value,
-            Self::Neg => {
-                if value == 0 {
-                    1
-                } else {
-                    0
-                }
-            }
+            Self::Minus => value.checked_neg(),
+            Self::BitNot => i64::try_from(!(value as u64)).ok(),
+            Self::Neg => Some(if value == 0 { 1 } else { 0 }),
         }
     }
 }
@@ -181,16 +415,17 @@ impl BinOpType {
         !(self.is_bitwise() || self.is_bitwise())
     }
 
-    pub fn eval(&self, lhs: i64, rhs: i64) -> i64 {
-        match self {
-            Self::Add => lhs + rhs,
+    /// Evaluates the operator, `None` if the result overflows an `i64` or is undefined
+    pub fn eval(&self, lhs: i64, rhs: i64) -> Option<i64> {
+        Some(match self {
+            Self::Add => lhs.checked_add(rhs)?,
             Self::BitAnd => lhs & rhs,
             Self::BitOr => lhs | rhs,
             Self::BitXor => lhs ^ rhs,
-            Self::Div => lhs / rhs,
-            Self::Modulo => lhs % rhs,
-            Self::Mul => lhs * rhs,
-            Self::Sub => lhs - rhs,
+            Self::Div => lhs.checked_div(rhs)?,
+            Self::Modulo => lhs.checked_rem(rhs)?,
+            Self::Mul => lhs.checked_mul(rhs)?,
+            Self::Sub => lhs.checked_sub(rhs)?,
             x => {
                 let b = match x {
                     Self::BoolAnd => (lhs != 0) && (rhs != 0),
@@ -211,7 +446,7 @@ impl BinOpType {
                     0
                 }
             }
-        }
+        })
     }
 }
 
@@ -254,13 +489,28 @@ impl ExprSubstitution for Expr {
                 let b = c.right.substitute(old, new);
                 a || b
             }
+            Self::HeapSlice(slice) => {
+                let a = slice.obj.substitute(old, new);
+                let b = slice.lower.substitute(old, new);
+                let c = slice.upper.substitute(old, new);
+                a || b || c
+            }
+            Self::Concat(c) => {
+                let a = c.left.substitute(old, new);
+                let b = c.right.substitute(old, new);
+                a || b
+            }
             Self::UnOp(op) => op.right.substitute(old, new),
             Self::Field(field) => field.obj.substitute(old, new),
             Self::Load(load) => load.address.substitute(old, new),
             Self::LoadBits(load) => load.address.substitute(old, new),
             Self::Old(o) => o.expr.substitute(old, new),
             Self::SeqLength(s) => s.expr.substitute(old, new),
-            Self::Shift(shift) => shift.value.substitute(old, new),
+            Self::Shift(shift) => {
+                let a = shift.value.substitute(old, new);
+                let b = shift.amount.substitute(old, new);
+                a || b
+            }
             Self::Struct(s) => s.elements.substitute(old, new),
             Self::Ternary(tern) => {
                 let a = tern.cond.substitute(old, new);
@@ -276,7 +526,14 @@ impl ExprSubstitution for Expr {
                 let b = fold.expr.substitute(old, new);
                 a || b
             }
+            Self::Let(l) => {
+                let a = l.value.substitute(old, new);
+                // The binding shadows `old` in the body
+                let b = !matches!(old, Self::Var(v) if *v == l.var) && l.body.substitute(old, new);
+                a || b
+            }
             Self::ViperFieldAccess(acc) => acc.obj.substitute(old, new),
+            Self::Spanned(s) => s.expr.substitute(old, new),
             Self::BaseAddr
             | Self::BoolLit(_)
             | Self::Const(_)
@@ -291,7 +548,7 @@ impl ExprSubstitution for Vec<Expr> {
     fn substitute(&mut self, old: &ir::Expr, new: &ir::Expr) -> bool {
         let mut acc = false;
         self.iter_mut()
-            .for_each(|e| acc = acc || e.substitute(old, new));
+            .for_each(|e| acc |= e.substitute(old, new));
         acc
     }
 }
@@ -363,6 +620,32 @@ impl Model {
                     .iter()
                     .map(|arg| ast.new_var(arg, ast.ref_type())),
             )
+            .chain(
+                self.globals
+                    .first()
+                    .map(|_| ast.new_var(Global::REF, ast.ref_type())),
+            )
             .unzip()
     }
+
+    pub fn global(&self, name: &str) -> Option<&Global> {
+        self.globals.iter().find(|g| g.name == name)
+    }
+
+    /// Names that are not mangled, as they refer to the model's state
+    pub fn unmangled_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.fields
+            .iter()
+            .chain(self.globals.iter().map(|g| &g.name))
+            .cloned()
+    }
+}
+
+impl Global {
+    /// Name of the reference holding all globals
+    pub const REF: &'static str = "globals";
+
+    pub fn field_name(&self) -> String {
+        format!("g_{}", self.name)
+    }
 }
@@ -1,9 +1,9 @@
-use viper::BinOpBv;
 use viper::BvSize::BV64;
+use viper::{BinOpBv, UnOpBv};
 
 use crate::utils::{
     ExprTypeResolution, ForceToBool, Mangler, Shape, ToType, ToViper, ToViperError, ToViperType,
-    TranslationMode, TryToShape, TryToViper, ViperEncodeCtx, ViperUtils,
+    TranslationMode, TryToShape, TryToViper, ViperEncodeCtx, ViperUtils, WithSpan,
 };
 
 use crate::ir::{self, BinOpType, Type};
@@ -24,19 +24,6 @@ impl<'a> ForceToBool<'a> for ir::Expr {
     }
 }
 
-impl ir::Expr {
-    // TODO: this could well be a function pointer. If we stick to only using
-    // valid function addresses (no unholy pointer arithmetic) we can encode
-    // this as a switch statement checking the expression against all possible
-    // function addresses.
-    pub fn label_to_viper(&self) -> String {
-        match self {
-            Self::Label(label) => label.to_owned(),
-            _ => todo!("Probably calling a function pointer. These are not yet implemented."),
-        }
-    }
-}
-
 impl<'a> ForceToBool<'a> for Vec<ir::Expr> {
     type Output = Vec<viper::Expr<'a>>;
 
@@ -53,6 +40,10 @@ impl<'a> TryToViper<'a> for ir::UnOp {
         Ok(match self.optype {
             Minus => ast.minus(self.right.to_viper(ctx)?),
             Neg => ast.not(self.right.force_to_bool(ctx)?),
+            BitNot => {
+                let value = ast.int_to_backend_bv(BV64, self.right.to_viper(ctx)?);
+                ast.backend_bv_to_int(BV64, ast.bv_unnop(UnOpBv::Not, BV64, value))
+            }
         })
     }
 }
@@ -71,12 +62,13 @@ fn translate_op<'a>(
         Div => ast.div(left, right),
         Modulo => ast.module(left, right),
         Imp => ast.implies(left, right),
+        Wand => ast.magic_wand(left, right),
         Iff => ast.eq_cmp(left, right),
         BoolAnd => ast.and(left, right),
         BoolOr => ast.or(left, right),
         ViperNotEqual | PancakeNotEqual => ast.ne_cmp(left, right),
         ViperEqual | PancakeEqual => ast.eq_cmp(left, right),
-        Lt | SignedLt => ast.lt_cmp(left, right), // FIXME: correctly handle signed vs unsigned
+        Lt | SignedLt => ast.lt_cmp(left, right),
         Lte | SignedLte => ast.le_cmp(left, right),
         Gt | SignedGt => ast.gt_cmp(left, right),
         Gte | SignedGte => ast.ge_cmp(left, right),
@@ -94,6 +86,61 @@ fn translate_op<'a>(
     }
 }
 
+/// Interpretation of the operands of a comparison
+#[derive(Clone, Copy)]
+enum Word {
+    Unsigned,
+    /// Two's complement
+    Signed,
+    Unbounded,
+}
+
+impl Word {
+    /// Encodes `expr` read as a word of `word_size` bits. Constants are converted statically.
+    fn encode<'a>(
+        self,
+        expr: ir::Expr,
+        ctx: &mut ViperEncodeCtx<'a>,
+    ) -> Result<viper::Expr<'a>, ToViperError> {
+        let ast = ctx.ast;
+        let bits = ctx.options.word_size as u32;
+        let modulus = 1i128 << bits;
+        if let Self::Unbounded = self {
+            return expr.to_viper(ctx);
+        }
+        if let ir::Expr::Const(c) = expr {
+            let unsigned = (c as i128).rem_euclid(modulus);
+            return Ok(match self {
+                Self::Signed if unsigned >= modulus / 2 => wide_int_lit(ast, unsigned - modulus),
+                _ => wide_int_lit(ast, unsigned),
+            });
+        }
+        let unsigned = ast.module(expr.to_viper(ctx)?, ctx.word_values());
+        Ok(match self {
+            Self::Signed => ast.cond_exp(
+                ast.lt_cmp(unsigned, wide_int_lit(ast, modulus / 2)),
+                unsigned,
+                ast.sub(unsigned, ctx.word_values()),
+            ),
+            _ => unsigned,
+        })
+    }
+}
+
+/// Integer literal, values that do not fit into an `i64` are built from smaller literals
+fn wide_int_lit(ast: viper::AstFactory<'_>, value: i128) -> viper::Expr<'_> {
+    match i64::try_from(value) {
+        Ok(value) => ast.int_lit(value),
+        Err(_) => {
+            let base = 1i128 << 62;
+            ast.add(
+                ast.mul(ast.int_lit((value / base) as i64), ast.int_lit(base as i64)),
+                ast.int_lit((value % base) as i64),
+            )
+        }
+    }
+}
+
 impl<'a> TryToViper<'a> for ir::BinOp {
     type Output = viper::Expr<'a>;
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
@@ -105,7 +152,7 @@ impl<'a> TryToViper<'a> for ir::BinOp {
 
         use BinOpType::*;
         let (left, right) = match self.optype {
-            BoolOr | BoolAnd => (
+            BoolOr | BoolAnd | Wand => (
                 self.left.force_to_bool(ctx)?,
                 self.right.force_to_bool(ctx)?,
             ),
@@ -113,6 +160,19 @@ impl<'a> TryToViper<'a> for ir::BinOp {
                 self.left.force_to_bool(ctx)?,
                 self.right.force_to_bool(ctx)?,
             ),
+            optype @ (Lt | Lte | Gt | Gte | SignedLt | SignedLte | SignedGt | SignedGte) => {
+                let word = match optype {
+                    Lt | Lte | Gt | Gte => Word::Unsigned,
+                    // Opting out makes signed comparisons in annotations Viper's, s.t. guards
+                    // like `0 <= i && i < n` bound quantified variables
+                    _ if is_annot && ctx.options.unbounded_annot_compare => Word::Unbounded,
+                    _ => Word::Signed,
+                };
+                (
+                    word.encode(*self.left, ctx)?,
+                    word.encode(*self.right, ctx)?,
+                )
+            }
             _ => (self.left.to_viper(ctx)?, self.right.to_viper(ctx)?),
         };
         let binop = translate_op(ast, self.optype, left, right);
@@ -181,7 +241,7 @@ impl<'a> TryToViper<'a> for ir::Shift {
             Lsr => BinOpBv::BvLShr,
         };
         let value = ast.int_to_backend_bv(BV64, self.value.to_viper(ctx)?);
-        let shift_amount = ast.int_to_backend_bv(BV64, ast.int_lit(self.amount as i64));
+        let shift_amount = ast.int_to_backend_bv(BV64, self.amount.to_viper(ctx)?);
         let shift = ast.bv_binop(shift_type, BV64, value, shift_amount);
         Ok(ast.backend_bv_to_int(BV64, shift))
     }
@@ -282,12 +342,22 @@ impl<'a> TryToViper<'a> for ir::FunctionCall {
             "f_bounded32" => ctx.utils.bounded_f(args[0], 32),
             "f_bounded64" => ctx.utils.bounded_f(args[0], 64),
             fname => {
-                base_args.extend(args);
-
                 let ret_type = ctx
                     .typectx_get()
                     .get_function_type(fname)?
                     .to_viper_type(ctx);
+                // Domain functions can't depend on the heap
+                if let Some(domain) = ctx.typectx_get().get_function_domain(fname) {
+                    return Ok(ast.domain_func_app2(
+                        fname,
+                        &args,
+                        &[],
+                        ret_type,
+                        domain,
+                        ast.no_position(),
+                    ));
+                }
+                base_args.extend(args);
                 ast.func_app(fname, &base_args, ret_type, ast.no_position())
             }
         })
@@ -298,10 +368,14 @@ impl<'a> TryToViper<'a> for ir::MethodCall {
     type Output = viper::Expr<'a>;
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
         let ast = ctx.ast;
-        let ret = ast.new_var(
-            &Mangler::fresh_varname(),
-            ctx.get_type(&self.fname)?.to_viper_type(ctx),
-        );
+        let typs = match ctx.typectx_get().get_method_returns(&self.fname) {
+            Some(typs) => typs.to_vec(),
+            None => vec![ctx.get_type(&self.fname)?],
+        };
+        let rets = typs
+            .iter()
+            .map(|typ| ast.new_var(&Mangler::fresh_varname(), typ.to_viper_type(ctx)))
+            .collect::<Vec<_>>();
         ctx.consume_stack = false;
 
         // Transpiled arguments
@@ -309,12 +383,29 @@ impl<'a> TryToViper<'a> for ir::MethodCall {
         let mut base_args = ctx.get_default_args().1;
         base_args.extend(args);
 
-        let call = ast.method_call(&self.fname, &base_args, &[ret.1]);
-        ctx.declarations.push(ret.0);
+        let mut targets = rets.iter().map(|ret| ret.1).collect::<Vec<_>>();
+        // Exceptions raised by the callee are stored in the caller's `exception` variable
+        if ctx.method.raises(&self.fname) {
+            targets.push(ast.local_var(ctx.exception_var_name(), ast.int_type()));
+        }
+        let call = ast.method_call(&self.fname, &base_args, &targets);
+        ctx.declarations.extend(rets.iter().map(|ret| ret.0));
         ctx.stack.push(call);
         ctx.consume_stack = true;
 
-        Ok(ret.1)
+        Ok(match &rets[..] {
+            [ret] => ret.1,
+            // Several returns are concatenated into one struct
+            _ => typs
+                .iter()
+                .zip(&rets)
+                .map(|(typ, ret)| match typ {
+                    Type::Struct(_) => ret.1,
+                    _ => ast.explicit_seq(&[ret.1]),
+                })
+                .reduce(|acc, field| ast.seq_append(acc, field))
+                .unwrap(),
+        })
     }
 }
 
@@ -369,6 +460,17 @@ impl<'a> TryToViper<'a> for ir::UnfoldingIn {
     }
 }
 
+impl<'a> TryToViper<'a> for ir::Let {
+    type Output = viper::Expr<'a>;
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        let var = ast.local_var_decl(&self.var, ctx.get_type(&self.var)?.to_viper_type(ctx));
+        let value = self.value.to_viper(ctx)?;
+        let body = self.body.to_viper(ctx)?;
+        Ok(ast.let_expr(var, value, body))
+    }
+}
+
 impl<'a> TryToViper<'a> for ir::Ternary {
     type Output = viper::Expr<'a>;
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
@@ -392,6 +494,38 @@ impl<'a> TryToViper<'a> for ir::AccessSlice {
     }
 }
 
+impl<'a> TryToViper<'a> for ir::HeapSlice {
+    type Output = viper::Expr<'a>;
+
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        use crate::viper_prelude::heap::MemType;
+        let mem = match self.mem_type.as_str() {
+            "pan" => MemType::Pancake,
+            "shared" => MemType::Shared,
+            x => unreachable!("Expected memory cell type: pan and shared, got {:?}", x),
+        };
+        let obj = self.obj.to_viper(ctx)?;
+        let lower = self.lower.to_viper(ctx)?;
+        let upper = self.upper.to_viper(ctx)?;
+        let upper = match self.typ {
+            ir::SliceType::Exclusive => upper,
+            ir::SliceType::Inclusive => ast.add(upper, ast.one()),
+        };
+        Ok(ctx.heap.slice(obj, lower, upper, mem))
+    }
+}
+
+impl<'a> TryToViper<'a> for ir::Concat {
+    type Output = viper::Expr<'a>;
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        let left = self.left.to_viper(ctx)?;
+        let right = self.right.to_viper(ctx)?;
+        Ok(ast.seq_append(left, right))
+    }
+}
+
 impl<'a> TryToViper<'a> for ir::ViperFieldAccess {
     type Output = viper::Expr<'a>;
 
@@ -423,11 +557,15 @@ impl<'a> TryToViper<'a> for ir::Expr {
             Quantified(quant) => quant.to_viper(ctx),
             AccessPredicate(acc) => acc.to_viper(ctx),
             UnfoldingIn(u) => u.to_viper(ctx),
+            Let(l) => l.to_viper(ctx),
             Load(load) => load.to_viper(ctx),
             LoadBits(load) => load.to_viper(ctx),
             Ternary(ternary) => ternary.to_viper(ctx),
             AccessSlice(slice) => slice.to_viper(ctx),
             ViperFieldAccess(acc) => acc.to_viper(ctx),
+            HeapSlice(slice) => slice.to_viper(ctx),
+            Concat(c) => c.to_viper(ctx),
+            Spanned(s) => s.expr.to_viper(ctx).with_span(&s.span),
             x => Ok(match x {
                 Const(c) => ast.int_lit(c),
                 BoolLit(b) if b => ast.true_lit(),
@@ -436,8 +574,17 @@ impl<'a> TryToViper<'a> for ir::Expr {
                     ctx.get_type("result")?.to_viper_type(ctx),
                     ast.no_position(),
                 ),
-                Var(name) => ast.local_var(&name, ctx.get_type(&name)?.to_viper_type(ctx)),
-                Label(_) => todo!(), // XXX: not sure if we need this
+                Var(name) => match ctx.model.global(&name) {
+                    Some(global) => global.access(ctx),
+                    None => ast.local_var(&name, ctx.get_type(&name)?.to_viper_type(ctx)),
+                },
+                // Abstract address of the function, see `pancake_to_ir::fnptr`
+                Label(label) => ast.func_app(
+                    &format!("{}_addr", label),
+                    &ctx.get_default_args().1,
+                    ast.int_type(),
+                    ast.no_position(),
+                ),
                 BaseAddr => ast.zero(),
                 BytesInWord => ast.int_lit(ctx.options.word_size as i64 / 8),
                 Old(old) => ast.old(old.expr.to_viper(ctx)?),
@@ -3,7 +3,7 @@ use viper::{BinOpBv, BvSize::BV64, UnOpBv};
 use crate::ir;
 
 use crate::ir::shared::SharedOpType::{Load, Store};
-use crate::utils::{Shape, ToViperError, TryToShape, TryToViper, ViperEncodeCtx, ViperUtils};
+use crate::utils::{ToViperError, TryToShape, TryToViper, ViperEncodeCtx, ViperUtils};
 use crate::viper_prelude::heap::MemType;
 
 impl<'a> TryToViper<'a> for ir::Load {
@@ -42,19 +42,33 @@ impl<'a> TryToViper<'a> for ir::LoadBits {
     type Output = viper::Expr<'a>;
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
         let ast = ctx.ast;
+        let heap = ctx.heap;
         let bytes_in_word = ast.int_lit(ctx.options.word_size as i64 / 8);
+        let byte_address = self.address.to_viper(ctx)?;
 
-        let byte_address = self.address.clone().to_viper(ctx)?;
+        // Heap views in annotations (`heap8[addr]` etc.) are not checked for alignment
+        let is_annot = ctx.get_mode().is_annot();
+        if ctx.options.assert_aligned_accesses && self.size.bits() != 8 && !is_annot {
+            // assert addr % size == 0
+            let assertion = ast.assert(
+                ast.eq_cmp(
+                    ast.module(byte_address, ast.int_lit(self.size.bytes() as i64)),
+                    ast.zero(),
+                ),
+                ast.no_position(),
+            );
+            ctx.stack.push(assertion);
+        }
+
+        // Little-endian: the value is stored in the word containing `addr`, offset by its
+        // position in the word
         let word_offset = ast.module(byte_address, bytes_in_word);
+        let word_address = ast.sub(byte_address, word_offset);
         let byte_mask = ast.backend_bv64_lit(2u64.pow(self.size.bits()) - 1);
-        let shift_amount = ast.int_to_backend_bv(BV64, ast.mul(bytes_in_word, word_offset));
+        let shift_amount = ast.int_to_backend_bv(BV64, ast.mul(ast.int_lit(8), word_offset));
 
-        let load = ir::Expr::Load(ir::Load {
-            shape: Shape::Simple,
-            address: self.address,
-            assert: false,
-        })
-        .to_viper(ctx)?;
+        let heap_var = ctx.heap_var().1;
+        let load = heap.access(heap_var, word_address, MemType::Pancake);
 
         Ok(ast.backend_bv_to_int(
             BV64,
@@ -142,7 +156,7 @@ impl<'a> TryToViper<'a> for ir::StoreBits {
         let word_offset = ast.module(byte_address, bytes_in_word);
         let word_address = ast.sub(byte_address, word_offset);
         let bit_mask = ast.backend_bv64_lit(2_u64.pow(self.size.bits()) - 1);
-        let shift_amount = ast.int_to_backend_bv(BV64, ast.mul(bytes_in_word, word_offset));
+        let shift_amount = ast.int_to_backend_bv(BV64, ast.mul(ast.int_lit(8), word_offset));
         let mask = ast.bv_binop(BinOpBv::BvShl, BV64, bit_mask, shift_amount);
         let inv_mask = ast.bv_unnop(UnOpBv::Not, BV64, mask);
         let value = ast.bv_binop(
@@ -2,7 +2,7 @@ use crate::{
     ir,
     utils::{
         ForceToBool, Mangler, ToViperError, ToViperType, TranslationMode, TryToShape, TryToViper,
-        ViperEncodeCtx, ViperUtils,
+        ViperEncodeCtx, ViperUtils, WithSpan,
     },
 };
 
@@ -11,6 +11,9 @@ impl<'a> TryToViper<'a> for ir::Stmt {
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
         let ast = ctx.ast;
         use ir::Stmt::*;
+        if let Spanned(s) = self {
+            return s.stmt.to_viper(ctx).with_span(&s.span);
+        }
         if ctx.options.debug_comments {
             ctx.stack.push(ast.comment(&format!("Stmt: {}", self)));
         }
@@ -20,8 +23,13 @@ impl<'a> TryToViper<'a> for ir::Stmt {
             Continue => ast.goto(&ctx.outer_continue_label()),
             Return => ast.goto(ctx.return_label()),
             x => match x {
-                Annotation(annot) => annot.to_viper(ctx),
+                Annotation(annot) => {
+                    let span = annot.span.clone();
+                    annot.to_viper(ctx).with_opt_span(span.as_ref())
+                }
                 Definition(def) => def.to_viper(ctx),
+                Ghost(ghost) => ghost.to_viper(ctx),
+                GhostAssign(ass) => ass.to_viper(ctx),
                 Assign(ass) => ass.to_viper(ctx),
                 If(ifs) => ifs.to_viper(ctx),
                 While(whiles) => whiles.to_viper(ctx),
@@ -82,20 +90,44 @@ impl<'a> TryToViper<'a> for ir::While {
         body_ctx.enter_new_loop();
         let body = self.body.to_viper(&mut body_ctx)?;
 
+        // The measure is saved at the start of each iteration and has to decrease until
+        // the next one
+        let measure = match body_ctx.decreases.as_slice() {
+            [] if ctx.options.require_termination => return Err(ToViperError::MissingLoopMeasure),
+            [] => None,
+            [measure] => {
+                let entry = ast.new_var(&Mangler::fresh_varname(), ast.int_type());
+                ctx.declarations.push(entry.0);
+                Some((entry.1, *measure))
+            }
+            _ => return Err(ToViperError::DuplicateLoopMeasure),
+        };
+
         let decls = ctx.pop_decls();
 
-        let mut body_seq = ctx.while_stack.clone();
+        let mut body_seq = vec![];
+        if let Some((entry, measure)) = measure {
+            body_seq.push(ast.local_var_assign(entry, measure));
+        }
+        body_seq.extend(ctx.while_stack.clone());
         body_seq.push(body);
         body_seq.push(ast.label(&ctx.current_continue_label(), &[]));
+        if let Some((entry, measure)) = measure {
+            let decreased = ast.and(ast.le_cmp(ast.zero(), entry), ast.lt_cmp(measure, entry));
+            body_seq.push(ast.assert(decreased, ast.no_position()));
+        }
         body_seq.extend(ctx.stack.clone());
 
         let body = ast.seqn(&body_seq, &[]);
 
-        ctx.stack.push(ast.while_stmt(
-            cond,
-            &body_ctx.invariants.drain(..).collect::<Vec<_>>(),
-            body,
-        ));
+        // Globals are not framed around the loop, the permissions to them are passed through
+        let mut invariants = vec![];
+        for global in ctx.model.globals.clone() {
+            invariants.extend(global.contract(ctx)?);
+        }
+        invariants.append(&mut body_ctx.invariants);
+
+        ctx.stack.push(ast.while_stmt(cond, &invariants, body));
         ctx.stack.push(ast.label(&ctx.current_break_label(), &[]));
         ctx.stack.append(&mut ctx.while_stack);
         let seq = ast.seqn(&ctx.stack, &decls);
@@ -137,6 +169,50 @@ impl<'a> TryToViper<'a> for ir::Definition {
     }
 }
 
+impl<'a> TryToViper<'a> for ir::Ghost {
+    type Output = viper::Stmt<'a>;
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        let var = ast.new_var(&self.decl.name, self.decl.typ.to_viper_type(ctx));
+        ctx.declarations.push(var.0);
+
+        if let Some(rhs) = self.rhs {
+            let mode = ctx.get_mode();
+            ctx.set_mode(TranslationMode::Assertion);
+            let rhs = rhs.to_viper(ctx)?;
+            ctx.set_mode(mode);
+            ctx.mangler.clear_annot_var();
+            ctx.stack.push(ast.local_var_assign(var.1, rhs));
+        }
+        let mut scope_ctx = ctx.child();
+        let scope = self.scope.to_viper(&mut scope_ctx)?;
+        ctx.invariants.append(&mut scope_ctx.invariants);
+
+        let decls = ctx.pop_decls();
+
+        ctx.stack.push(scope);
+        let seq = ast.seqn(&ctx.stack, &decls);
+        ctx.stack.clear();
+        Ok(seq)
+    }
+}
+
+impl<'a> TryToViper<'a> for ir::GhostAssign {
+    type Output = viper::Stmt<'a>;
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        let typ = ctx.get_type(&self.lhs)?;
+        let var = ast.new_var(&self.lhs, typ.to_viper_type(ctx));
+
+        let mode = ctx.get_mode();
+        ctx.set_mode(TranslationMode::Assertion);
+        let rhs = self.rhs.to_viper(ctx)?;
+        ctx.set_mode(mode);
+        ctx.mangler.clear_annot_var();
+        Ok(ast.local_var_assign(var.1, rhs))
+    }
+}
+
 impl<'a> TryToViper<'a> for ir::Assign {
     type Output = viper::Stmt<'a>;
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
@@ -147,9 +223,12 @@ impl<'a> TryToViper<'a> for ir::Assign {
         // if lhs_shape != rhs_shape {
         //     return Err(ToViperError::MismatchedShapes(lhs_shape, rhs_shape));
         // }
-        let var = ast.new_var(&self.lhs, lhs_shape.to_viper_type(ctx));
+        let var = match ctx.model.global(&self.lhs) {
+            Some(global) => global.access(ctx),
+            None => ast.new_var(&self.lhs, lhs_shape.to_viper_type(ctx)).1,
+        };
 
-        let ass = ast.local_var_assign(var.1, self.rhs.to_viper(ctx)?);
+        let ass = ast.local_var_assign(var, self.rhs.to_viper(ctx)?);
         let decls = ctx.pop_decls();
 
         ctx.stack.push(ass);
@@ -213,12 +292,49 @@ impl<'a> TryToViper<'a> for ir::Annotation {
                 }
                 _ => Err(ToViperError::InvalidFold(self.expr)),
             },
+            Apply => match self.expr {
+                ir::Expr::FunctionCall(call) => {
+                    let mode = ctx.get_mode();
+                    ctx.set_mode(TranslationMode::Assertion);
+                    let args = call.args.to_viper(ctx)?;
+                    ctx.set_mode(mode);
+                    ctx.mangler.clear_annot_var();
+                    let mut base_args = ctx.get_default_args().1;
+                    base_args.extend(args);
+                    Ok(ast.method_call(&call.fname, &base_args, &[]))
+                }
+                wand @ ir::Expr::BinOp(ir::BinOp {
+                    optype: ir::BinOpType::Wand,
+                    ..
+                }) => Ok(ast.apply(wand_to_viper(wand, ctx)?)),
+                _ => Err(ToViperError::InvalidApply(self.expr)),
+            },
+            Package => match self.expr {
+                wand @ ir::Expr::BinOp(ir::BinOp {
+                    optype: ir::BinOpType::Wand,
+                    ..
+                }) => {
+                    let wand = wand_to_viper(wand, ctx)?;
+                    Ok(ast.package(wand, ast.seqn(&[], &[])))
+                }
+                _ => Err(ToViperError::InvalidPackage(self.expr)),
+            },
+            Decreases => {
+                let mode = ctx.get_mode();
+                ctx.set_mode(TranslationMode::Assertion);
+                let measure = self.expr.to_viper(ctx)?;
+                ctx.set_mode(mode);
+                ctx.mangler.clear_annot_var();
+                ctx.decreases.push(measure);
+                Ok(ast.comment("decreases pushed"))
+            }
             x => {
                 let no_pos = ast.no_position();
 
+                let mode = ctx.get_mode();
                 ctx.set_mode(self.typ.into());
                 let body = self.expr.force_to_bool(ctx)?;
-                ctx.set_mode(TranslationMode::Normal);
+                ctx.set_mode(mode);
                 ctx.mangler.clear_annot_var();
                 match x {
                     Assertion => Ok(ast.assert(body, no_pos)),
@@ -235,3 +351,16 @@ impl<'a> TryToViper<'a> for ir::Annotation {
         }
     }
 }
+
+/// Encodes the magic wand of a `package` or `apply` statement
+fn wand_to_viper<'a>(
+    wand: ir::Expr,
+    ctx: &mut ViperEncodeCtx<'a>,
+) -> Result<viper::Expr<'a>, ToViperError> {
+    let mode = ctx.get_mode();
+    ctx.set_mode(TranslationMode::Assertion);
+    let wand = wand.to_viper(ctx)?;
+    ctx.set_mode(mode);
+    ctx.mangler.clear_annot_var();
+    Ok(wand)
+}
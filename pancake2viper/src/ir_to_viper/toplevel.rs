@@ -6,7 +6,7 @@ use viper::AstFactory;
 
 use crate::utils::{
     EncodeOptions, ForceToBool, MethodContext, ProgramToViper, ToViper, ToViperError, ToViperType,
-    TranslationMode, TryToViper, TypeContext, ViperEncodeCtx,
+    TranslationError, TranslationMode, TryToViper, TypeContext, ViperEncodeCtx, ViperUtils,
 };
 use crate::viper_prelude::create_viper_prelude;
 
@@ -25,6 +25,16 @@ impl<'a> TryToViper<'a> for FnDec {
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
         let ast = ctx.ast;
 
+        if self.recursive && self.decreases.is_none() {
+            let fname = self.fname.strip_prefix("f_").unwrap_or(&self.fname);
+            if ctx.options.require_termination {
+                return Err(TranslationError::MissingMeasure(fname.to_owned()).into());
+            }
+            if !ctx.options.ignore_warnings {
+                println!(" - WARNING! Termination of recursive function '{fname}' is not checked");
+            }
+        }
+
         // add access permissions to arguments if structs
         let mut pres = self
             .args
@@ -39,10 +49,18 @@ impl<'a> TryToViper<'a> for FnDec {
             .map(|p| p.to_viper(ctx))
             .collect::<Result<Vec<_>, _>>()?;
         pres.extend(pred_pres);
+        for global in ctx.model.globals.clone() {
+            pres.extend(global.contract(ctx)?);
+        }
         let mut posts = pres.clone();
 
-        // Add postcondition (bounds of integers)
-        posts.push(self.postcondition(ctx));
+        // Add postcondition (bounds of integers), the return value is only set on a normal return
+        let exception = ast.local_var(ctx.exception_var_name(), ast.int_type());
+        posts.push(if self.raises {
+            ast.implies(ast.eq_cmp(exception, ast.zero()), self.postcondition(ctx))
+        } else {
+            self.postcondition(ctx)
+        });
 
         let args_local_decls = self.args.to_viper(ctx);
 
@@ -77,10 +95,16 @@ impl<'a> TryToViper<'a> for FnDec {
         let mut base_args_local_decls = ctx.get_default_args().0;
         base_args_local_decls.extend(args_local_decls);
 
+        let mut rets =
+            vec![ast.local_var_decl(&self.retvar, ctx.get_type(&self.retvar)?.to_viper_type(ctx))];
+        if self.raises {
+            rets.push(ast.local_var_decl(ctx.exception_var_name(), ast.int_type()));
+        }
+
         Ok(ast.method(
             &self.fname,
             &base_args_local_decls,
-            &[ast.local_var_decl(&self.retvar, ctx.get_type(&self.retvar)?.to_viper_type(ctx))],
+            &rets,
             &pres,
             &posts,
             if self.trusted { None } else { Some(body) },
@@ -158,6 +182,30 @@ impl<'a> TryToViper<'a> for Function {
     }
 }
 
+impl<'a> TryToViper<'a> for Domain {
+    type Output = viper::Domain<'a>;
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        let functions = self
+            .functions
+            .into_iter()
+            .map(|f| {
+                let args = f.args.to_viper(ctx);
+                ast.domain_func(&f.name, &args, f.typ.to_viper_type(ctx), false, &self.name)
+            })
+            .collect::<Vec<_>>();
+        let axioms = self
+            .axioms
+            .into_iter()
+            .map(|a| {
+                let body = a.body.force_to_bool(ctx)?;
+                Ok(ast.named_domain_axiom(&a.name, body, &self.name))
+            })
+            .collect::<Result<Vec<_>, ToViperError>>()?;
+        Ok(ast.domain(&self.name, &functions, &axioms, &[]))
+    }
+}
+
 impl<'a> TryToViper<'a> for AbstractMethod {
     type Output = viper::Method<'a>;
     fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
@@ -179,6 +227,35 @@ impl<'a> TryToViper<'a> for AbstractMethod {
     }
 }
 
+impl<'a> TryToViper<'a> for Lemma {
+    type Output = viper::Method<'a>;
+    fn to_viper(self, ctx: &mut ViperEncodeCtx<'a>) -> Result<Self::Output, ToViperError> {
+        let ast = ctx.ast;
+        let heap_var = ctx.heap_var().1;
+        let mut pres = vec![
+            ast.eq_cmp(
+                ast.seq_length(heap_var),
+                ast.int_lit(ctx.options.heap_top as i64),
+            ),
+            ctx.heap.heap_injective(heap_var),
+        ];
+        pres.extend(self.args.iter().filter_map(|a| a.precondition(true, ctx)));
+        pres.extend(self.pres.force_to_bool(ctx)?);
+        let posts = self.posts.force_to_bool(ctx)?;
+
+        // The body only consists of annotations, so conditions are assertions as well
+        ctx.set_mode(TranslationMode::Assertion);
+        let body = self.body.to_viper(ctx)?;
+        ctx.set_mode(TranslationMode::PrePost);
+
+        let args = self.args.to_viper(ctx);
+        let mut base_args = ctx.get_default_args().0;
+        base_args.extend(args);
+
+        Ok(ast.method(&self.name, &base_args, &[], &pres, &posts, Some(body)))
+    }
+}
+
 impl<'a> ProgramToViper<'a> for Program {
     fn to_viper(
         self,
@@ -270,6 +347,46 @@ impl<'a> ProgramToViper<'a> for Program {
             })
             .collect::<Result<Vec<_>, _>>()?;
 
+        let lemmas = self
+            .lemmas
+            .into_iter()
+            .map(|l| {
+                let mut ctx = ViperEncodeCtx::new(
+                    types.clone(),
+                    predicate_names.clone(),
+                    ast,
+                    options,
+                    shared.clone(),
+                    method_ctx.clone(),
+                    model.clone(),
+                    extern_methods.clone(),
+                    extern_consts.clone(),
+                );
+                ctx.set_mode(TranslationMode::PrePost);
+                l.to_viper(&mut ctx)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let user_domains = self
+            .domains
+            .into_iter()
+            .map(|d| {
+                let mut ctx = ViperEncodeCtx::new(
+                    types.clone(),
+                    predicate_names.clone(),
+                    ast,
+                    options,
+                    shared.clone(),
+                    method_ctx.clone(),
+                    model.clone(),
+                    extern_methods.clone(),
+                    extern_consts.clone(),
+                );
+                ctx.set_mode(TranslationMode::PrePost);
+                d.to_viper(&mut ctx)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
         let program_methods = self
             .functions
             .into_iter()
@@ -288,8 +405,28 @@ impl<'a> ProgramToViper<'a> for Program {
                 f.to_viper(&mut ctx)
             })
             .collect::<Result<Vec<_>, _>>()?;
-        let (domains, fields, mut methods, fs) = create_viper_prelude(ast, self.model, options);
+        let ctx = ViperEncodeCtx::new(
+            types,
+            predicate_names,
+            ast,
+            options,
+            shared,
+            method_ctx,
+            model.clone(),
+            extern_methods,
+            extern_consts,
+        );
+        let global_fields = model
+            .globals
+            .iter()
+            .map(|g| g.field(&ctx))
+            .collect::<Vec<_>>();
+        let (mut domains, mut fields, mut methods, fs) =
+            create_viper_prelude(ast, self.model, options);
+        domains.extend(user_domains);
+        fields.extend(global_fields);
         methods.extend(abstract_methods.iter());
+        methods.extend(lemmas.iter());
         methods.extend(program_methods.iter());
         functions.extend(fs.iter());
         Ok(ast.program(&domains, &fields, &functions, &predicates, &methods))
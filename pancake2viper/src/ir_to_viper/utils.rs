@@ -1,7 +1,7 @@
 use viper::Expr;
 
 use crate::{
-    ir::{self, Arg, FnDec, Type},
+    ir::{self, Arg, FnDec, Global, Type},
     utils::{ToViper, ToViperError, ToViperType, TryToViper, ViperEncodeCtx, ViperUtils},
 };
 
@@ -32,34 +32,63 @@ impl Arg {
         is_predicate: bool,
         ctx: &ViperEncodeCtx<'a>,
     ) -> Option<Expr<'a>> {
-        let ast = ctx.ast;
         let arg_var = ctx.ast.new_var(&self.name, self.typ.to_viper_type(ctx)).1;
-
         match &self.typ {
-            ir::Type::Struct(_) => {
-                let length = ast.int_lit(self.typ.len() as i64);
-                let length_pre = ast.eq_cmp(ast.seq_length(arg_var), length);
-                let i = ast.new_var("i", ast.int_type());
-                let bound_pre = ast.forall(
-                    &[i.0],
-                    &[],
-                    ast.implies(
-                        ast.and(ast.le_cmp(ast.zero(), i.1), ast.lt_cmp(i.1, length)),
-                        ctx.utils
-                            .bounded_f(ast.seq_index(arg_var, i.1), ctx.options.word_size),
-                    ),
-                );
-                Some(ast.and(length_pre, bound_pre))
-            }
-            ir::Type::Int => {
-                if is_predicate {
-                    None
-                } else {
-                    Some(ctx.utils.bounded_f(arg_var, ctx.options.word_size))
-                }
-            }
-            _ => None,
+            ir::Type::Int if is_predicate => None,
+            typ => bounds(arg_var, typ, ctx),
+        }
+    }
+}
+
+impl Global {
+    pub fn field<'a>(&self, ctx: &ViperEncodeCtx<'a>) -> viper::Field<'a> {
+        ctx.ast.field(&self.field_name(), self.typ.to_viper_type(ctx))
+    }
+
+    /// Access to the global, i.e. `globals.g_<name>`
+    pub fn access<'a>(&self, ctx: &ViperEncodeCtx<'a>) -> Expr<'a> {
+        let ast = ctx.ast;
+        let globals = ast.local_var(Global::REF, ast.ref_type());
+        ast.field_access(globals, self.field(ctx))
+    }
+
+    /// Permission to the global and the bounds of its value, which is fixed for static data
+    pub fn contract<'a>(
+        self,
+        ctx: &mut ViperEncodeCtx<'a>,
+    ) -> Result<Vec<Expr<'a>>, ToViperError> {
+        let ast = ctx.ast;
+        let access = self.access(ctx);
+        let mut contract = vec![ast.field_access_predicate(access, ast.full_perm())];
+        contract.extend(bounds(access, &self.typ, ctx));
+        if self.constant {
+            contract.push(ast.eq_cmp(access, self.value.to_viper(ctx)?));
+        }
+        Ok(contract)
+    }
+}
+
+/// Bounds of a value of the given type. Structs are encoded as a `Seq[Int]` of known length.
+fn bounds<'a>(value: Expr<'a>, typ: &ir::Type, ctx: &ViperEncodeCtx<'a>) -> Option<Expr<'a>> {
+    let ast = ctx.ast;
+    match typ {
+        ir::Type::Struct(_) => {
+            let length = ast.int_lit(typ.len() as i64);
+            let length_pre = ast.eq_cmp(ast.seq_length(value), length);
+            let i = ast.new_var("i", ast.int_type());
+            let bound_pre = ast.forall(
+                &[i.0],
+                &[],
+                ast.implies(
+                    ast.and(ast.le_cmp(ast.zero(), i.1), ast.lt_cmp(i.1, length)),
+                    ctx.utils
+                        .bounded_f(ast.seq_index(value, i.1), ctx.options.word_size),
+                ),
+            );
+            Some(ast.and(length_pre, bound_pre))
         }
+        ir::Type::Int => Some(ctx.utils.bounded_f(value, ctx.options.word_size)),
+        _ => None,
     }
 }
 
@@ -74,6 +103,7 @@ impl<'a> ToViperType<'a> for ir::Type {
             ir::Type::Map(k, v) => ast.map_type(k.to_viper_type(ctx), v.to_viper_type(ctx)),
             ir::Type::Set(i) => ast.set_type(i.to_viper_type(ctx)),
             ir::Type::Seq(i) => ast.seq_type(i.to_viper_type(ctx)),
+            ir::Type::Domain(name) => ast.domain_type(name, &[], &[]),
             x => panic!("Want type of {:?}", x),
         }
     }
@@ -0,0 +1,80 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+use sha2::{Digest, Sha256};
+
+lazy_static::lazy_static! {
+    static ref CAKE_VERSIONS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+/// Content-addressed cache of `cake --pancake --explore` output. Entries are keyed
+/// by the preprocessed source, the path of the `cake` binary and its version.
+pub(super) struct ExploreCache<'a> {
+    dir: &'a Path,
+    key: String,
+}
+
+impl<'a> ExploreCache<'a> {
+    pub fn new(dir: &'a Path, cake_path: &str, source: &str) -> Self {
+        let mut hasher = Sha256::new();
+        for part in [cake_path, &cake_version(cake_path), source] {
+            hasher.update(part.len().to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Self { dir, key }
+    }
+
+    fn entry(&self) -> PathBuf {
+        self.dir.join(format!("{}.sexp", self.key))
+    }
+
+    pub fn get(&self) -> Option<Vec<String>> {
+        let contents = fs::read_to_string(self.entry()).ok()?;
+        Some(split_sexprs(&contents))
+    }
+
+    /// Stores the explore output. The cache is best effort, so failing to write
+    /// it is not an error.
+    pub fn insert(&self, sexprs: &[String]) {
+        let write = || -> std::io::Result<()> {
+            fs::create_dir_all(self.dir)?;
+            // Write to a temporary file first, so concurrent runs never observe partial entries
+            let mut file = tempfile::NamedTempFile::new_in(self.dir)?;
+            file.write_all(sexprs.join("\n\n").as_bytes())?;
+            file.persist(self.entry())?;
+            Ok(())
+        };
+        let _ = write();
+    }
+}
+
+/// Splits the explore output into the S-expressions of the individual functions
+pub(super) fn split_sexprs(s: &str) -> Vec<String> {
+    s.split("\n\n").map(|s| s.to_owned()).collect()
+}
+
+/// Version string reported by `cake`, queried once per binary
+pub(super) fn cake_version(cake_path: &str) -> String {
+    let mut versions = CAKE_VERSIONS.lock().unwrap();
+    versions
+        .entry(cake_path.to_owned())
+        .or_insert_with(|| {
+            Command::new(cake_path)
+                .arg("--version")
+                .output()
+                .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+                .unwrap_or_default()
+        })
+        .clone()
+}
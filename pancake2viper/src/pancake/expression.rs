@@ -1,6 +1,6 @@
 use strum::EnumString;
 
-use crate::utils::Shape;
+use crate::utils::{Shape, Span};
 
 use super::MemOpBytes;
 
@@ -18,6 +18,14 @@ pub enum Expr {
     BaseAddr,
     BytesInWord,
     Call(ExprCall),
+    Spanned(SpannedExpr),
+}
+
+/// Subexpression annotated with its location in the source
+#[derive(Debug, Clone)]
+pub struct SpannedExpr {
+    pub span: Span,
+    pub expr: Box<Expr>,
 }
 
 #[derive(Debug, Clone)]
@@ -58,6 +66,8 @@ pub enum OpType {
     And,
     Or,
     Xor,
+    /// Bitwise complement, only produced by the native frontend
+    BitNot,
 }
 
 #[derive(Debug, Clone)]
@@ -77,7 +87,7 @@ pub enum ShiftType {
 pub struct Shift {
     pub shifttype: ShiftType,
     pub value: Box<Expr>,
-    pub amount: u64,
+    pub amount: Box<Expr>,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use super::{
+    lexer::{lex_annotations, AnnotationKind},
+    ParseOptions, Program,
+};
+use crate::utils::{ImportError, LexError};
+
+/// Resolves `/@ import "file.pnk" @/` directives into a module graph. Every file is
+/// loaded once, its definitions are merged into the importing program.
+pub(super) struct Importer<'a> {
+    options: &'a ParseOptions,
+    loaded: HashSet<PathBuf>,
+    stack: Vec<PathBuf>,
+}
+
+impl<'a> Importer<'a> {
+    pub fn new(options: &'a ParseOptions) -> Self {
+        Self {
+            options,
+            loaded: HashSet::new(),
+            stack: vec![],
+        }
+    }
+
+    pub fn load(&mut self, s: String, path: Option<&str>) -> anyhow::Result<Program> {
+        if let Some(canonical) = path.and_then(|p| fs::canonicalize(p).ok()) {
+            self.loaded.insert(canonical.clone());
+            self.stack.push(canonical);
+        }
+        self.load_module(s, path)
+    }
+
+    fn load_module(&mut self, s: String, path: Option<&str>) -> anyhow::Result<Program> {
+        let imports = get_imports(&s, path)?;
+        let mut program = Program::parse_module(s, path, self.options)?;
+        let dir = path
+            .and_then(|p| Path::new(p).parent())
+            .unwrap_or(Path::new(""));
+        for import in imports {
+            let file = dir.join(&import);
+            let canonical = fs::canonicalize(&file)
+                .map_err(|e| ImportError::NotFound(file.display().to_string(), e))?;
+            if let Some(pos) = self.stack.iter().position(|p| p == &canonical) {
+                let cycle = self.stack[pos..]
+                    .iter()
+                    .chain([&canonical])
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(ImportError::Cycle(cycle).into());
+            }
+            if !self.loaded.insert(canonical.clone()) {
+                continue;
+            }
+            let source = fs::read_to_string(&file)
+                .map_err(|e| ImportError::NotFound(file.display().to_string(), e))?;
+            self.stack.push(canonical);
+            let module = self.load_module(source, file.to_str())?;
+            self.stack.pop();
+            program.merge(module);
+        }
+        Ok(program)
+    }
+}
+
+fn get_imports(s: &str, path: Option<&str>) -> Result<Vec<String>, LexError> {
+    let re = Regex::new(r#"^/@\s*import\s+"([^"]*)""#).unwrap();
+    Ok(lex_annotations(s, path)?
+        .into_iter()
+        .filter(|annot| annot.kind == AnnotationKind::Import)
+        .filter_map(|annot| Some(re.captures(&annot.text)?[1].to_owned()))
+        .collect())
+}
+
+impl Program {
+    /// Adds the definitions of an imported module to this program
+    fn merge(&mut self, other: Self) {
+        self.functions.extend(other.functions);
+        self.globals.extend(other.globals);
+        self.predicates.extend(other.predicates);
+        self.viper_functions.extend(other.viper_functions);
+        self.methods.extend(other.methods);
+        self.lemmas.extend(other.lemmas);
+        self.domains.extend(other.domains);
+        self.shared.extend(other.shared);
+        self.fnptrs.extend(other.fnptrs);
+        self.model_predicates.extend(other.model_predicates);
+        self.model_fields.extend(other.model_fields);
+        self.extern_predicates.extend(other.extern_predicates);
+        self.extern_fields.extend(other.extern_fields);
+        self.extern_consts.extend(other.extern_consts);
+        self.extern_methods.extend(other.extern_methods);
+    }
+}
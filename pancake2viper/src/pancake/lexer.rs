@@ -0,0 +1,203 @@
+use std::{iter::Peekable, ops::Range, str::CharIndices, sync::Arc};
+
+use crate::utils::{LexError, Span};
+
+/// Kind of a `/@ ... @/` annotation, given by its leading keywords
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum AnnotationKind {
+    Predicate,
+    Function,
+    Method,
+    Lemma,
+    Domain,
+    Shared,
+    FnPtr,
+    ModelPredicate,
+    ModelField,
+    ExternPredicate,
+    ExternField,
+    ExternConst,
+    Ffi,
+    Import,
+    /// `requires`, `ensures`, `trusted`, `implements` or `decreases`, either before the
+    /// `fun` header or at the start of the body
+    Contract,
+    /// `ghost` variable declaration or assignment, only allowed inside a function body
+    Ghost,
+    /// Any other annotation, only allowed inside a function body
+    Statement,
+}
+
+impl AnnotationKind {
+    /// Classifies an annotation by its text, without the `/@` and `@/` delimiters
+    pub fn classify(body: &str) -> Self {
+        let mut words = body
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .filter(|w| !w.is_empty());
+        match (words.next(), words.next()) {
+            (Some("predicate"), _) => Self::Predicate,
+            (Some("function"), _) => Self::Function,
+            (Some("method"), _) => Self::Method,
+            (Some("lemma"), _) => Self::Lemma,
+            (Some("domain"), _) => Self::Domain,
+            (Some("shared"), _) => Self::Shared,
+            (Some("fnptr"), _) => Self::FnPtr,
+            (Some("ffi"), _) => Self::Ffi,
+            (Some("import"), _) => Self::Import,
+            (Some("model"), Some("predicate")) => Self::ModelPredicate,
+            (Some("model"), Some("field")) => Self::ModelField,
+            (Some("extern"), Some("predicate")) => Self::ExternPredicate,
+            (Some("extern"), Some("field")) => Self::ExternField,
+            (Some("extern"), Some("const")) => Self::ExternConst,
+            (Some("requires" | "ensures" | "trusted" | "implements" | "decreases"), _) => {
+                Self::Contract
+            }
+            (Some("ghost"), _) => Self::Ghost,
+            _ => Self::Statement,
+        }
+    }
+
+    fn is_toplevel(self) -> bool {
+        !matches!(self, Self::Contract | Self::Ghost | Self::Statement)
+    }
+}
+
+/// Annotation found in the source
+#[derive(Debug, Clone)]
+pub(super) struct LexedAnnotation {
+    pub kind: AnnotationKind,
+    /// Text of the annotation, including the delimiters
+    pub text: String,
+    /// Byte range of the annotation in the source
+    pub range: Range<usize>,
+    pub span: Span,
+    /// Whether the annotation is outside of all function bodies
+    pub toplevel: bool,
+}
+
+/// Position in the source
+#[derive(Clone, Copy)]
+struct Pos {
+    offset: usize,
+    line: usize,
+    col: usize,
+}
+
+struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    pos: Pos,
+}
+
+impl Cursor<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (offset, c) = self.chars.next()?;
+        self.pos.offset = offset + c.len_utf8();
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.col = 1;
+        } else {
+            self.pos.col += 1;
+        }
+        Some(c)
+    }
+
+    /// Consumes characters up to and including the first one closing the pair `end`
+    fn skip_past(&mut self, mut end: impl FnMut(char, char) -> bool) -> bool {
+        let mut prev = ' ';
+        while let Some(c) = self.bump() {
+            if end(prev, c) {
+                return true;
+            }
+            prev = c;
+        }
+        false
+    }
+}
+
+/// Finds all annotations in a Pancake source file. Annotations in comments are skipped.
+/// Top-level annotations of an unknown kind and top-level declarations inside a function
+/// body are reported as errors.
+pub(super) fn lex_annotations(
+    s: &str,
+    path: Option<&str>,
+) -> Result<Vec<LexedAnnotation>, LexError> {
+    let file: Option<Arc<str>> = path.map(Arc::from);
+    let span = |start: Pos, end: Pos| {
+        Span::new(file.clone(), (start.line, start.col), (end.line, end.col))
+    };
+    let mut cursor = Cursor {
+        chars: s.char_indices().peekable(),
+        pos: Pos {
+            offset: 0,
+            line: 1,
+            col: 1,
+        },
+    };
+
+    let mut annotations = vec![];
+    let mut depth = 0usize;
+    loop {
+        let start = cursor.pos;
+        let Some(c) = cursor.bump() else {
+            break;
+        };
+        match (c, cursor.peek()) {
+            ('/', Some('/')) => {
+                while cursor.peek().is_some_and(|c| c != '\n') {
+                    cursor.bump();
+                }
+            }
+            ('/', Some('*')) => {
+                cursor.bump();
+                cursor.skip_past(|prev, c| prev == '*' && c == '/');
+            }
+            ('"', _) => {
+                let mut escaped = false;
+                cursor.skip_past(|_, c| {
+                    let end = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    end
+                });
+            }
+            ('/', Some('@')) => {
+                cursor.bump();
+                let body_start = cursor.pos.offset;
+                // Like cake, also accept annotations closed by `*/`
+                if !cursor.skip_past(|prev, c| (prev == '@' || prev == '*') && c == '/') {
+                    return Err(LexError::Unterminated(span(start, cursor.pos)));
+                }
+                let end = cursor.pos;
+                let body = &s[body_start..end.offset - 2];
+                let kind = AnnotationKind::classify(body);
+                let keyword = || {
+                    body.split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_owned()
+                };
+                let toplevel = depth == 0;
+                if toplevel && matches!(kind, AnnotationKind::Statement | AnnotationKind::Ghost) {
+                    return Err(LexError::UnknownKind(span(start, end), keyword()));
+                }
+                if !toplevel && kind.is_toplevel() {
+                    return Err(LexError::NotToplevel(span(start, end), keyword()));
+                }
+                annotations.push(LexedAnnotation {
+                    kind,
+                    text: s[start.offset..end.offset].to_owned(),
+                    range: start.offset..end.offset,
+                    span: span(start, end),
+                    toplevel,
+                });
+            }
+            ('{', _) => depth += 1,
+            ('}', _) => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    Ok(annotations)
+}
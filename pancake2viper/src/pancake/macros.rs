@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+};
+
+use anyhow::anyhow;
+use regex::Regex;
+
+use super::ParseOptions;
+
+/// Preprocessor macro, possibly taking arguments
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Option<Vec<String>>,
+    body: String,
+}
+
+/// Macros defined by the Pancake source, its includes and `-D` options. `cpp` expands
+/// them in annotations too, but the result cannot be mapped back to the annotation as
+/// written, so annotations are taken from the source and expanded separately.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Macros(HashMap<String, Macro>);
+
+/// Maps positions in an annotation after macro expansion back to the annotation as written
+#[derive(Debug, Clone, Default)]
+pub struct MacroMap {
+    segments: Vec<Segment>,
+}
+
+/// Part of the expanded text, starting at the `expanded` char index. Verbatim segments
+/// are copied from the original text, the others are the result of a macro expansion.
+#[derive(Debug, Clone)]
+struct Segment {
+    expanded: usize,
+    original: (usize, usize),
+    verbatim: bool,
+}
+
+impl Macros {
+    /// Collects the macros defined by the source using `cpp -dM`, with the same arguments
+    /// as the preprocessing of the source
+    pub fn from_cpp(source: &str, options: &ParseOptions) -> anyhow::Result<Self> {
+        let mut cpp = Command::new("cpp")
+            .arg("-dM")
+            .args(options.cpp_args())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let mut stdin = cpp.stdin.take().ok_or(anyhow!("Could not take stdin"))?;
+        let source = source.to_owned();
+        std::thread::spawn(move || std::io::Write::write_all(&mut stdin, source.as_bytes()));
+
+        Ok(Self::parse(&String::from_utf8(
+            cpp.wait_with_output()?.stdout,
+        )?))
+    }
+
+    fn parse(dump: &str) -> Self {
+        let re = Regex::new(r"^#define\s+([A-Za-z_]\w*)(\(([^)]*)\))?\s*(.*)$").unwrap();
+        let macros = dump
+            .lines()
+            .filter_map(|line| re.captures(line))
+            // Builtins like `__STDC__` are of no use in annotations
+            .filter(|capt| !capt[1].starts_with("__"))
+            .map(|capt| {
+                let params = capt.get(3).map(|params| {
+                    params
+                        .as_str()
+                        .split(',')
+                        .map(|p| p.trim().to_owned())
+                        .filter(|p| !p.is_empty())
+                        .collect()
+                });
+                let body = capt[4].trim().to_owned();
+                (capt[1].to_owned(), Macro { params, body })
+            })
+            .collect();
+        Self(macros)
+    }
+
+    /// Expands all macros in `s`, returning the expanded text and a map back to `s`
+    pub fn expand(&self, s: &str) -> (String, MacroMap) {
+        let mut map = MacroMap::default();
+        let expanded = self.expand_inner(s, &mut vec![], Some(&mut map));
+        (expanded, map)
+    }
+
+    fn expand_inner(
+        &self,
+        s: &str,
+        disabled: &mut Vec<String>,
+        mut map: Option<&mut MacroMap>,
+    ) -> String {
+        let chars = s.chars().collect::<Vec<_>>();
+        let mut out = String::with_capacity(s.len());
+        let mut out_len = 0;
+        let mut pos = (1, 1);
+        let mut i = 0;
+        let advance = |pos: &mut (usize, usize), c: char| {
+            if c == '\n' {
+                *pos = (pos.0 + 1, 1);
+            } else {
+                pos.1 += 1;
+            }
+        };
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_ascii_digit() || !(c.is_ascii_alphabetic() || c == '_') {
+                // Numbers are consumed as a whole, so `0x1F` does not contain the identifier `x1F`
+                let len = if c.is_ascii_digit() {
+                    chars[i..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                        .count()
+                } else {
+                    1
+                };
+                for &c in &chars[i..i + len] {
+                    out.push(c);
+                    advance(&mut pos, c);
+                }
+                out_len += len;
+                i += len;
+                continue;
+            }
+            let len = chars[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                .count();
+            let ident = chars[i..i + len].iter().collect::<String>();
+            let replacement = self
+                .0
+                .get(&ident)
+                .filter(|_| !disabled.contains(&ident))
+                .and_then(|m| self.invoke(&ident, m, &chars[i + len..], disabled));
+            match replacement {
+                Some((text, consumed)) => {
+                    if let Some(map) = map.as_deref_mut() {
+                        map.push(out_len, pos, false);
+                    }
+                    out_len += text.chars().count();
+                    out.push_str(&text);
+                    for &c in &chars[i..i + len + consumed] {
+                        advance(&mut pos, c);
+                    }
+                    i += len + consumed;
+                    if let Some(map) = map.as_deref_mut() {
+                        map.push(out_len, pos, true);
+                    }
+                }
+                None => {
+                    out.push_str(&ident);
+                    out_len += len;
+                    pos.1 += len;
+                    i += len;
+                }
+            }
+        }
+        out
+    }
+
+    /// Replacement text of a macro invocation and the number of chars consumed after
+    /// the macro name, i.e. the argument list
+    fn invoke(
+        &self,
+        name: &str,
+        m: &Macro,
+        rest: &[char],
+        disabled: &mut Vec<String>,
+    ) -> Option<(String, usize)> {
+        let (body, consumed) = match &m.params {
+            None => (m.body.clone(), 0),
+            Some(params) => {
+                let (args, consumed) = parse_args(rest)?;
+                if args.len() != params.len() && !(params.is_empty() && args == [""]) {
+                    return None;
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.expand_inner(arg.trim(), disabled, None))
+                    .collect::<Vec<_>>();
+                let params = params
+                    .iter()
+                    .zip(args)
+                    .map(|(p, a)| (p.as_str(), a))
+                    .collect::<HashMap<_, _>>();
+                let ident = Regex::new(r"[A-Za-z_]\w*").unwrap();
+                let body = ident.replace_all(&m.body, |capt: &regex::Captures| {
+                    params
+                        .get(&capt[0])
+                        .cloned()
+                        .unwrap_or_else(|| capt[0].to_owned())
+                });
+                (body.into_owned(), consumed)
+            }
+        };
+        disabled.push(name.to_owned());
+        let expanded = self.expand_inner(&body, disabled, None);
+        disabled.pop();
+        Some((expanded, consumed))
+    }
+}
+
+/// Splits the parenthesised argument list at the start of `s` into its arguments
+fn parse_args(s: &[char]) -> Option<(Vec<String>, usize)> {
+    let start = s.iter().take_while(|c| c.is_whitespace()).count();
+    if s.get(start) != Some(&'(') {
+        return None;
+    }
+    let mut args = vec![String::new()];
+    let mut depth = 0;
+    for (i, &c) in s.iter().enumerate().skip(start + 1) {
+        match c {
+            ')' if depth == 0 => return Some((args, i + 1)),
+            ',' if depth == 0 => args.push(String::new()),
+            _ => {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => (),
+                }
+                args.last_mut().unwrap().push(c);
+            }
+        }
+    }
+    None
+}
+
+impl MacroMap {
+    fn push(&mut self, expanded: usize, original: (usize, usize), verbatim: bool) {
+        if self.segments.is_empty() {
+            self.segments.push(Segment {
+                expanded: 0,
+                original: (1, 1),
+                verbatim: true,
+            });
+        }
+        self.segments.push(Segment {
+            expanded,
+            original,
+            verbatim,
+        });
+    }
+
+    /// Maps a (1-based) line and column in the `expanded` text to the original text.
+    /// Positions inside a macro expansion are mapped to the start of its invocation.
+    pub fn original_pos(&self, expanded: &str, line: usize, col: usize) -> (usize, usize) {
+        let offset = expanded
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(|l| l.chars().count())
+            .sum::<usize>()
+            + col.saturating_sub(1);
+        let Some(segment) = self.segments.iter().rev().find(|s| s.expanded <= offset) else {
+            return (line, col);
+        };
+        if !segment.verbatim {
+            return segment.original;
+        }
+        expanded
+            .chars()
+            .skip(segment.expanded)
+            .take(offset - segment.expanded)
+            .fold(segment.original, |(line, col), c| {
+                if c == '\n' {
+                    (line + 1, 1)
+                } else {
+                    (line, col + 1)
+                }
+            })
+    }
+}
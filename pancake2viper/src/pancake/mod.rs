@@ -1,9 +1,21 @@
+mod cache;
+mod explore;
 mod expression;
+mod import;
+mod lexer;
+mod macros;
+mod native;
 mod parser;
 mod statement;
 mod toplevel;
 mod utils;
 
+#[cfg(test)]
+mod tests;
+
+pub use explore::ExploreRevision;
 pub use expression::*;
+pub use macros::MacroMap;
+pub use parser::{Frontend, ParseOptions};
 pub use statement::*;
 pub use toplevel::*;
@@ -0,0 +1,499 @@
+use anyhow::anyhow;
+use pest::iterators::{Pair, Pairs};
+use pest::pratt_parser::PrattParser;
+use pest::Parser;
+
+use super::lexer::AnnotationKind;
+use super::*;
+use crate::utils::{Shape, Span};
+
+/// Parser for Pancake's concrete syntax, producing the same AST as the
+/// translation of `cake`'s explore output
+#[derive(pest_derive::Parser)]
+#[grammar = "src/pancake/pancake.pest"]
+struct PancakeParser;
+
+lazy_static::lazy_static! {
+    static ref PRATT_PARSER: PrattParser<Rule> = {
+        use pest::pratt_parser::{Assoc::*, Op};
+        PrattParser::new()
+            .op(Op::infix(Rule::bool_or, Left))
+            .op(Op::infix(Rule::bool_and, Left))
+            .op(Op::infix(Rule::bit_or, Left))
+            .op(Op::infix(Rule::bit_xor, Left))
+            .op(Op::infix(Rule::bit_and, Left))
+            .op(Op::infix(Rule::eq, Left) | Op::infix(Rule::neq, Left))
+            .op(Op::infix(Rule::lt, Left)
+                | Op::infix(Rule::gt, Left)
+                | Op::infix(Rule::lte, Left)
+                | Op::infix(Rule::gte, Left)
+                | Op::infix(Rule::unsigned_lt, Left)
+                | Op::infix(Rule::unsigned_gt, Left)
+                | Op::infix(Rule::unsigned_lte, Left)
+                | Op::infix(Rule::unsigned_gte, Left))
+            .op(Op::postfix(Rule::shift))
+            .op(Op::infix(Rule::add, Left) | Op::infix(Rule::sub, Left))
+            .op(Op::infix(Rule::mul, Left))
+            .op(Op::prefix(Rule::minus) | Op::prefix(Rule::not) | Op::prefix(Rule::bit_not))
+            .op(Op::postfix(Rule::field_acc))
+    };
+}
+
+/// Parses the functions and globals of a Pancake program
+pub(super) fn parse_program(s: &str) -> anyhow::Result<(Vec<FnDec>, Vec<Global>)> {
+    let program = PancakeParser::parse(Rule::program, s)
+        .map_err(|e| anyhow!("Syntax error in Pancake program:\n{e}"))?
+        .next()
+        .unwrap();
+    let mut functions = vec![];
+    let mut globals = vec![];
+    // Contract annotations seen since the last declaration
+    let mut contract = vec![];
+    for pair in program.into_inner() {
+        match pair.as_rule() {
+            Rule::fun_decl => {
+                let mut f = FnDec::from_pest(pair)?;
+                f.contract = std::mem::take(&mut contract);
+                functions.push(f);
+            }
+            Rule::annotation => match Stmt::from_pest(pair)? {
+                Stmt::Annotation(annot)
+                    if AnnotationKind::classify(&annot.line) == AnnotationKind::Contract =>
+                {
+                    contract.push(annot)
+                }
+                _ => (),
+            },
+            // Globals and the end of the file can't take a contract
+            rule => {
+                if !contract.is_empty() {
+                    return Err(anyhow!("Contract annotations must precede a function"));
+                }
+                if rule == Rule::global_decl {
+                    globals.push(Global::from_pest(pair)?);
+                }
+            }
+        }
+    }
+    Ok((functions, globals))
+}
+
+#[cfg(test)]
+pub(super) fn parse_functions(s: &str) -> anyhow::Result<Vec<FnDec>> {
+    parse_program(s).map(|(functions, _)| functions)
+}
+
+/// Span of a parse tree node in the (preprocessed) source
+fn span_of(pair: &Pair<'_, Rule>) -> Span {
+    let span = pair.as_span();
+    Span::new(None, span.start_pos().line_col(), span.end_pos().line_col())
+}
+
+/// Attaches its location in the source to a subexpression
+fn spanned(expr: Expr, span: Span) -> Expr {
+    Expr::Spanned(SpannedExpr {
+        span,
+        expr: Box::new(expr),
+    })
+}
+
+fn parse_int(pair: Pair<'_, Rule>) -> anyhow::Result<i64> {
+    let s = pair.as_str();
+    Ok(match pair.as_rule() {
+        Rule::hex => u64::from_str_radix(&s[2..], 16)? as i64,
+        _ => s.parse::<u64>()? as i64,
+    })
+}
+
+impl FnDec {
+    fn from_pest(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        let span = span_of(&pair);
+        let mut inner = pair
+            .into_inner()
+            .filter(|p| p.as_rule() != Rule::export)
+            .peekable();
+        let rettyp = match inner.next_if(|p| p.as_rule() == Rule::shape) {
+            Some(shape) => Some(Shape::parse(shape.as_str())?),
+            None => None,
+        };
+        let fname = inner.next().unwrap().as_str().to_owned();
+        let args = inner
+            .next()
+            .unwrap()
+            .into_inner()
+            .map(Arg::from_pest)
+            .collect::<anyhow::Result<_>>()?;
+        let body = Stmt::parse_block(inner.next().unwrap())?;
+        Ok(Self {
+            fname,
+            args,
+            body,
+            rettyp,
+            contract: vec![],
+            span: Some(span),
+        })
+    }
+}
+
+impl Arg {
+    fn from_pest(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        let mut inner = pair.into_inner();
+        let shape = Shape::parse(inner.next().unwrap().as_str())?;
+        let name = inner.next().unwrap().as_str().to_owned();
+        Ok(Self { name, shape })
+    }
+}
+
+impl Global {
+    fn from_pest(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        let span = span_of(&pair);
+        let mut inner = pair.into_inner();
+        let shape = Shape::parse(inner.next().unwrap().as_str())?;
+        let name = inner.next().unwrap().as_str().to_owned();
+        let value = Expr::from_pest(inner.next().unwrap())?;
+        Ok(Self {
+            name,
+            shape,
+            value,
+            span: Some(span),
+        })
+    }
+}
+
+impl Stmt {
+    fn parse_block(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        Self::parse_stmts(pair.into_inner())
+    }
+
+    /// Parses a sequence of statements. Declarations scope over the remainder
+    /// of their block, as in `cake`'s `dec` construct.
+    fn parse_stmts(mut pairs: Pairs<'_, Rule>) -> anyhow::Result<Self> {
+        let mut stmts = vec![];
+        while let Some(pair) = pairs.next() {
+            if pair.as_rule() == Rule::declaration {
+                let span = span_of(&pair);
+                let mut inner = pair.into_inner().filter(|p| p.as_rule() != Rule::shape);
+                let lhs = inner.next().unwrap().as_str().to_owned();
+                let rhs = Expr::from_pest(inner.next().unwrap())?;
+                let decl = Self::Declaration(Declaration {
+                    lhs,
+                    rhs,
+                    scope: Box::new(Self::parse_stmts(pairs)?),
+                });
+                stmts.push(Self::Spanned(SpannedStmt {
+                    span,
+                    stmt: Box::new(decl),
+                }));
+                break;
+            }
+            let stmt = match pair.as_rule() {
+                Rule::annotation | Rule::block => Self::from_pest(pair)?,
+                _ => Self::Spanned(SpannedStmt {
+                    span: span_of(&pair),
+                    stmt: Box::new(Self::from_pest(pair)?),
+                }),
+            };
+            stmts.push(stmt);
+        }
+        Ok(Self::Seq(Seq { stmts }))
+    }
+
+    /// Wraps the call `call` in its exception handler, if it has one
+    fn with_handler(call: Self, handler: Option<Pair<'_, Rule>>) -> anyhow::Result<Self> {
+        let Some(handler) = handler else {
+            return Ok(call);
+        };
+        let mut inner = handler.into_inner();
+        Ok(Self::Handle(Handle {
+            call: Box::new(call),
+            error: inner.next().unwrap().as_str().to_owned(),
+            handler: Box::new(Self::parse_block(inner.next().unwrap())?),
+        }))
+    }
+
+    fn from_pest(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        let rule = pair.as_rule();
+        let mut inner = pair.into_inner();
+        Ok(match rule {
+            Rule::annotation => {
+                let body = inner.next().unwrap();
+                Self::Annotation(Annotation {
+                    line: body.as_str().to_owned(),
+                    span: Some(span_of(&body)),
+                    macros: MacroMap::default(),
+                })
+            }
+            Rule::assign => {
+                let lhs = inner.next().unwrap().as_str().to_owned();
+                let rhs = Expr::from_pest(inner.next().unwrap())?;
+                if inner.peek().is_some() && !matches!(rhs, Expr::Call(_)) {
+                    return Err(anyhow!("Only calls can have an exception handler"));
+                }
+                Self::with_handler(Self::Assign(Assign { lhs, rhs }), inner.next())?
+            }
+            Rule::call_stmt => {
+                let (fname, args) = Expr::parse_call(inner.next().unwrap())?;
+                Self::with_handler(Self::Call(Call { fname, args }), inner.next())?
+            }
+            Rule::ext_call => {
+                let fname = inner.next().unwrap().as_str().to_owned();
+                let args: [Expr; 4] = Expr::parse_args(inner.next().unwrap())?
+                    .try_into()
+                    .map_err(|args: Vec<_>| {
+                        anyhow!(
+                            "FFI call `@{fname}` expects 4 arguments, found {}",
+                            args.len()
+                        )
+                    })?;
+                Self::ExtCall(ExtCall { fname, args })
+            }
+            Rule::store => Self::Store(Store {
+                address: Expr::from_pest(inner.next().unwrap())?,
+                value: Expr::from_pest(inner.next().unwrap())?,
+            }),
+            Rule::store_bits => {
+                let size = MemOpBytes::from_pest(inner.next().unwrap());
+                Self::StoreBits(StoreBits {
+                    address: Expr::from_pest(inner.next().unwrap())?,
+                    value: Expr::from_pest(inner.next().unwrap())?,
+                    size,
+                })
+            }
+            Rule::shared_store => {
+                let op = inner.next().unwrap();
+                let mut args = inner.next().unwrap().into_inner();
+                let address = Expr::from_pest(args.next().unwrap())?;
+                let value = Expr::from_pest(args.next().unwrap())?;
+                match op.as_str() {
+                    "stw" => Self::SharedStore(SharedStore { address, value }),
+                    _ => Self::SharedStoreBits(SharedStoreBits {
+                        address,
+                        value,
+                        size: MemOpBytes::from_pest(op),
+                    }),
+                }
+            }
+            Rule::shared_load => {
+                let op = inner.next().unwrap();
+                let mut args = inner.next().unwrap().into_inner();
+                let dst = match Expr::from_pest(args.next().unwrap())? {
+                    dst @ Expr::Var(_) => dst,
+                    x => {
+                        return Err(anyhow!(
+                            "Destination of `!{}` must be a variable, found {:?}",
+                            op.as_str(),
+                            x
+                        ))
+                    }
+                };
+                let address = Expr::from_pest(args.next().unwrap())?;
+                match op.as_str() {
+                    "ldw" => Self::SharedLoad(SharedLoad { address, dst }),
+                    _ => Self::SharedLoadBits(SharedLoadBits {
+                        address,
+                        dst,
+                        size: MemOpBytes::from_pest(op),
+                    }),
+                }
+            }
+            Rule::if_stmt => Self::If(If {
+                cond: Expr::from_pest(inner.next().unwrap())?,
+                if_branch: Box::new(Self::parse_block(inner.next().unwrap())?),
+                else_branch: Box::new(match inner.next() {
+                    Some(branch) if branch.as_rule() == Rule::if_stmt => Self::from_pest(branch)?,
+                    Some(branch) => Self::parse_block(branch)?,
+                    None => Self::Skip,
+                }),
+            }),
+            Rule::while_stmt => Self::While(While {
+                cond: Expr::from_pest(inner.next().unwrap())?,
+                body: Box::new(Self::parse_block(inner.next().unwrap())?),
+            }),
+            Rule::break_stmt => Self::Break,
+            Rule::continue_stmt => Self::Continue,
+            Rule::skip_stmt => Self::Skip,
+            Rule::tick_stmt => Self::Tick,
+            Rule::raise_stmt => Self::Raise(Raise {
+                error: inner.next().unwrap().as_str().to_owned(),
+            }),
+            Rule::return_stmt => {
+                let value = inner.next().unwrap();
+                match Expr::from_pest(value)? {
+                    Expr::Call(call) => Self::TailCall(TailCall {
+                        fname: *call.fname,
+                        args: call.args,
+                    }),
+                    value => Self::Return(Return { value }),
+                }
+            }
+            Rule::block => Self::parse_stmts(inner)?,
+            _ => unreachable!("Unexpected rule {:?}", rule),
+        })
+    }
+}
+
+impl MemOpBytes {
+    fn from_pest(pair: Pair<'_, Rule>) -> Self {
+        match pair.as_str().trim_start_matches(['s', 't', 'l', 'd']) {
+            "8" => Self::Byte,
+            "16" => Self::QuarterWord,
+            "32" => Self::HalfWord,
+            x => unreachable!("Unexpected memory operation size {x}"),
+        }
+    }
+}
+
+impl Expr {
+    /// Parses the expression of a statement or global, which is located by its
+    /// statement instead of a span of its own
+    fn from_pest(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        Ok(match Self::parse_subexpr(pair)? {
+            Self::Spanned(s) => *s.expr,
+            expr => expr,
+        })
+    }
+
+    fn parse_subexpr(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        Self::parse_expr(pair.into_inner())
+    }
+
+    fn parse_expr(pairs: Pairs<'_, Rule>) -> anyhow::Result<Self> {
+        PRATT_PARSER
+            .map_primary(Self::parse_primary)
+            .map_infix(|lhs, op, rhs| {
+                let (lhs, rhs) = (lhs?, rhs?);
+                let (optype, operands) = match op.as_rule() {
+                    Rule::bool_or | Rule::bit_or => (OpType::Or, vec![lhs, rhs]),
+                    Rule::bool_and | Rule::bit_and => (OpType::And, vec![lhs, rhs]),
+                    Rule::bit_xor => (OpType::Xor, vec![lhs, rhs]),
+                    Rule::eq => (OpType::Equal, vec![lhs, rhs]),
+                    Rule::neq => (OpType::NotEqual, vec![lhs, rhs]),
+                    Rule::lt => (OpType::Less, vec![lhs, rhs]),
+                    Rule::gt => (OpType::Less, vec![rhs, lhs]),
+                    Rule::gte => (OpType::NotLess, vec![lhs, rhs]),
+                    Rule::lte => (OpType::NotLess, vec![rhs, lhs]),
+                    Rule::unsigned_lt => (OpType::Lower, vec![lhs, rhs]),
+                    Rule::unsigned_gt => (OpType::Lower, vec![rhs, lhs]),
+                    Rule::unsigned_gte => (OpType::NotLower, vec![lhs, rhs]),
+                    Rule::unsigned_lte => (OpType::NotLower, vec![rhs, lhs]),
+                    Rule::add => (OpType::Add, vec![lhs, rhs]),
+                    Rule::sub => (OpType::Sub, vec![lhs, rhs]),
+                    Rule::mul => (OpType::Mul, vec![lhs, rhs]),
+                    _ => unreachable!("Unexpected infix operator {:?}", op),
+                };
+                Ok(Self::Op(Op { optype, operands }))
+            })
+            .map_prefix(|op, rhs| match op.as_rule() {
+                Rule::minus => Ok(Self::Op(Op {
+                    optype: OpType::Sub,
+                    operands: vec![rhs?],
+                })),
+                Rule::not => Ok(Self::Op(Op {
+                    optype: OpType::Equal,
+                    operands: vec![rhs?, Self::Const(0)],
+                })),
+                Rule::bit_not => Ok(Self::Op(Op {
+                    optype: OpType::BitNot,
+                    operands: vec![rhs?],
+                })),
+                _ => unreachable!("Unexpected prefix operator {:?}", op),
+            })
+            .map_postfix(|lhs, op| match op.as_rule() {
+                Rule::field_acc => {
+                    let lhs = lhs?;
+                    let span = match &lhs {
+                        Self::Spanned(s) => s.span.join(&span_of(&op)),
+                        _ => span_of(&op),
+                    };
+                    let field = Self::Field(Field {
+                        field_idx: op.into_inner().next().unwrap().as_str().parse()?,
+                        obj: Box::new(lhs),
+                    });
+                    Ok(spanned(field, span))
+                }
+                Rule::shift => {
+                    let mut inner = op.into_inner();
+                    let shifttype = match inner.next().unwrap().as_rule() {
+                        Rule::lsl => ShiftType::Lsl,
+                        Rule::asr => ShiftType::Asr,
+                        Rule::lsr => ShiftType::Lsr,
+                        _ => unreachable!(),
+                    };
+                    Ok(Self::Shift(Shift {
+                        shifttype,
+                        value: Box::new(lhs?),
+                        amount: Box::new(Self::parse_primary(inner.next().unwrap())?),
+                    }))
+                }
+                _ => unreachable!("Unexpected postfix operator {:?}", op),
+            })
+            .parse(pairs)
+    }
+
+    /// Parses a primary expression. Variables, calls and loads carry their location,
+    /// s.t. errors about them point at the subexpression.
+    fn parse_primary(pair: Pair<'_, Rule>) -> anyhow::Result<Self> {
+        let span = span_of(&pair);
+        Ok(match pair.as_rule() {
+            Rule::expr | Rule::struct_elem => Self::parse_expr(pair.into_inner())?,
+            Rule::integer | Rule::hex => Self::Const(parse_int(pair)?),
+            Rule::true_lit => Self::Const(1),
+            Rule::false_lit => Self::Const(0),
+            Rule::base => Self::BaseAddr,
+            Rule::biw => Self::BytesInWord,
+            Rule::var => spanned(Self::Var(pair.as_str().to_owned()), span),
+            Rule::label => Self::Label(pair.into_inner().next().unwrap().as_str().to_owned()),
+            Rule::call => {
+                let (fname, args) = Self::parse_call(pair)?;
+                let call = Self::Call(ExprCall {
+                    fname: Box::new(fname),
+                    args,
+                });
+                spanned(call, span)
+            }
+            Rule::struc => Self::Struct(Struct::new(
+                pair.into_inner()
+                    .map(Self::parse_subexpr)
+                    .collect::<anyhow::Result<_>>()?,
+            )),
+            Rule::load => {
+                let mut inner = pair.into_inner();
+                let load = Self::Load(Load {
+                    shape: Shape::parse(inner.next().unwrap().as_str())?,
+                    address: Box::new(Self::parse_subexpr(inner.next().unwrap())?),
+                    assert: true,
+                });
+                spanned(load, span)
+            }
+            Rule::load_word => {
+                let load = Self::Load(Load {
+                    shape: Shape::Simple,
+                    address: Box::new(Self::parse_subexpr(pair.into_inner().next().unwrap())?),
+                    assert: true,
+                });
+                spanned(load, span)
+            }
+            Rule::load_bits => {
+                let mut inner = pair.into_inner();
+                let size = MemOpBytes::from_pest(inner.next().unwrap());
+                let load = Self::LoadBits(LoadBits {
+                    address: Box::new(Self::parse_subexpr(inner.next().unwrap())?),
+                    size,
+                });
+                spanned(load, span)
+            }
+            x => unreachable!("Unexpected primary expression {:?}", x),
+        })
+    }
+
+    fn parse_call(pair: Pair<'_, Rule>) -> anyhow::Result<(Self, Vec<Self>)> {
+        let mut inner = pair.into_inner();
+        let fname = Self::Label(inner.next().unwrap().as_str().to_owned());
+        let args = Self::parse_args(inner.next().unwrap())?;
+        Ok((fname, args))
+    }
+
+    fn parse_args(pair: Pair<'_, Rule>) -> anyhow::Result<Vec<Self>> {
+        pair.into_inner().map(Self::parse_subexpr).collect()
+    }
+}
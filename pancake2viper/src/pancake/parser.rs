@@ -4,19 +4,33 @@ use pest_derive::Parser;
 use regex::Regex;
 use sexpr_parser::{Parser, SexprFactory};
 use std::{
+    collections::HashMap,
+    fmt::Display,
     fs,
     io::Write,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     str::FromStr,
+    sync::Arc,
 };
 
-use super::*;
-use crate::{pancake, utils::Shape};
+use super::{
+    cache::{cake_version, split_sexprs, ExploreCache},
+    explore::ExploreRevision,
+    import::Importer,
+    lexer::{lex_annotations, AnnotationKind, LexedAnnotation},
+    macros::Macros,
+    *,
+};
+use crate::{
+    pancake,
+    utils::{FrontendError, LexError, Shape, Span},
+};
 use SExpr::*;
 
 /// S-expression definition for parsing of `cake`'s explore output
 #[derive(Debug, PartialEq)]
-enum SExpr {
+pub(super) enum SExpr {
     Int(u64),
     Float(f64),
     Symbol(String),
@@ -29,6 +43,42 @@ struct SExprParser;
 
 impl SExprParser {}
 
+impl Display for SExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Int(i) => write!(f, "{}", i),
+            Float(x) => write!(f, "{}", x),
+            Symbol(s) => write!(f, "{}", s),
+            SString(s) => write!(f, "{:?}", s),
+            Pair(p) => write!(f, "({} . {})", p.0, p.1),
+            List(l) => write!(f, "({})", l.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(" ")),
+        }
+    }
+}
+
+impl FrontendError {
+    fn unsupported<'a>(
+        construct: &'static str,
+        sexpr: impl IntoIterator<Item = &'a SExpr>,
+    ) -> Self {
+        Self::Unsupported {
+            construct,
+            sexpr: format!(
+                "({})",
+                sexpr
+                    .into_iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+}
+
+fn parse_shape(s: &str) -> Result<Shape, FrontendError> {
+    Shape::parse(s).map_err(|_| FrontendError::InvalidShape(s.to_owned()))
+}
+
 impl SexprFactory for SExprParser {
     type Sexpr = SExpr;
     type Integer = i64;
@@ -60,10 +110,14 @@ impl SexprFactory for SExprParser {
 }
 
 impl Expr {
-    fn parse(s: &[SExpr]) -> anyhow::Result<Self> {
+    fn parse(s: &[SExpr]) -> Result<Self, FrontendError> {
         match s {
             [Symbol(cons), Symbol(word)] if cons == "Const" && word.starts_with("0x") => {
-                Ok(Self::Const(u64::from_str_radix(&word[2..], 16)? as i64))
+                Ok(Self::Const(
+                    u64::from_str_radix(&word[2..], 16)
+                        .map_err(|_| FrontendError::InvalidConstant(word.clone()))?
+                        as i64,
+                ))
             }
             [Symbol(var), Symbol(name)] if var == "Var" => Ok(Self::Var(name.clone())),
             [Symbol(label), Symbol(name)] if label == "Label" => Ok(Self::Label(name.clone())),
@@ -74,30 +128,37 @@ impl Expr {
                 field_idx: *idx as usize,
                 obj: Box::new(Self::parse(exp)?),
             })),
-            [Symbol(memloadbyte), List(exp)] if memloadbyte == "MemLoadByte" => {
+            [Symbol(memload), List(exp)] if memload.starts_with("MemLoad") => {
+                let size = match &memload["MemLoad".len()..] {
+                    "Byte" => MemOpBytes::Byte,
+                    "16" => MemOpBytes::QuarterWord,
+                    "32" => MemOpBytes::HalfWord,
+                    _ => return Err(FrontendError::unsupported("expression", s)),
+                };
                 Ok(Self::LoadBits(LoadBits {
                     address: Box::new(Self::parse(exp)?),
-                    size: MemOpBytes::Byte,
+                    size,
                 }))
             }
             [Symbol(memload), Symbol(shape), List(exp)] if memload == "MemLoad" => {
                 Ok(Self::Load(Load {
-                    shape: Shape::parse(shape)?,
+                    shape: parse_shape(shape)?,
                     address: Box::new(Self::parse(exp)?),
                     assert: true,
                 }))
             }
             [Symbol(memload), Int(shape), List(exp)] if memload == "MemLoad" => {
                 Ok(Self::Load(Load {
-                    shape: Shape::parse(&shape.to_string())?,
+                    shape: parse_shape(&shape.to_string())?,
                     address: Box::new(Self::parse(exp)?),
                     assert: true,
                 }))
             }
             [Symbol(shift), List(exp), Int(num)] => Ok(Self::Shift(Shift {
-                shifttype: ShiftType::from_str(shift)?,
+                shifttype: ShiftType::from_str(shift)
+                    .map_err(|_| FrontendError::UnknownOperator(shift.clone()))?,
                 value: Box::new(Self::parse(exp)?),
-                amount: *num,
+                amount: Box::new(Self::Const(*num as i64)),
             })),
             [Symbol(base)] if base == "BaseAddr" => Ok(Self::BaseAddr),
             [Symbol(bytes)] if bytes == "BytesInWord" => Ok(Self::BytesInWord),
@@ -114,19 +175,43 @@ impl Expr {
                 }))
             }
             [Symbol(op), exps @ ..] => Ok(Self::Op(Op {
-                optype: OpType::from_str(op)?,
+                optype: OpType::from_str(op)
+                    .map_err(|_| FrontendError::UnknownOperator(op.clone()))?,
                 operands: Self::parse_slice(exps)?,
             })),
-            x => panic!("Could not parse expr: {:?}", x),
+            x => Err(FrontendError::unsupported("expression", x)),
+        }
+    }
+
+    /// Calls `f` on this expression and all of its subexpressions
+    fn for_each_mut(&mut self, f: &mut impl FnMut(&mut Self)) {
+        f(self);
+        let children: Vec<&mut Self> = match self {
+            Self::Struct(s) => s.elements.iter_mut().collect(),
+            Self::Field(field) => vec![&mut field.obj],
+            Self::Load(load) => vec![&mut load.address],
+            Self::LoadBits(load) => vec![&mut load.address],
+            Self::Op(op) => op.operands.iter_mut().collect(),
+            Self::Shift(shift) => vec![&mut shift.value, &mut shift.amount],
+            Self::Call(call) => std::iter::once(&mut *call.fname)
+                .chain(call.args.iter_mut())
+                .collect(),
+            Self::Spanned(s) => vec![&mut s.expr],
+            Self::Const(_) | Self::Var(_) | Self::Label(_) | Self::BaseAddr | Self::BytesInWord => {
+                vec![]
+            }
+        };
+        for child in children {
+            child.for_each_mut(f);
         }
     }
 
-    fn parse_slice(s: &[SExpr]) -> anyhow::Result<Vec<Self>> {
+    fn parse_slice(s: &[SExpr]) -> Result<Vec<Self>, FrontendError> {
         let mut l = vec![];
         for exp in s {
             match exp {
                 List(exp) => l.push(Self::parse(exp)?),
-                _ => return Err(anyhow!("Error whilst parsing expr list")),
+                x => return Err(FrontendError::unsupported("expression", [x])),
             }
         }
         Ok(l)
@@ -134,11 +219,13 @@ impl Expr {
 }
 
 impl Stmt {
-    fn parse(s: Vec<&SExpr>) -> anyhow::Result<Self> {
+    fn parse(s: Vec<&SExpr>) -> Result<Self, FrontendError> {
         match &s[..] {
             [Symbol(op), SString(at), SString(annot)] if op == "annot" && at == "@" => {
                 Ok(Self::Annotation(Annotation {
                     line: annot.to_owned(),
+                    span: None,
+                    macros: MacroMap::default(),
                 }))
             }
             [Symbol(op)] => Self::parse_symbol(op),
@@ -160,13 +247,25 @@ impl Stmt {
                     value: Expr::parse(exp)?,
                 }))
             }
-            [Symbol(op), List(addr), Symbol(eq), Symbol(byte), List(exp)]
-                if op == "mem" && eq == ":=" && byte == "byte" =>
+            [Symbol(op), List(addr), Symbol(eq), Symbol(size), List(exp)]
+                if op == "mem" && eq == ":=" =>
             {
+                let size = match size.as_str() {
+                    "word" => {
+                        return Ok(Self::Store(Store {
+                            address: Expr::parse(addr)?,
+                            value: Expr::parse(exp)?,
+                        }))
+                    }
+                    "byte" => MemOpBytes::Byte,
+                    "word16" => MemOpBytes::QuarterWord,
+                    "word32" => MemOpBytes::HalfWord,
+                    _ => return Err(FrontendError::unsupported("statement", s.iter().copied())),
+                };
                 Ok(Self::StoreBits(StoreBits {
                     address: Expr::parse(addr)?,
                     value: Expr::parse(exp)?,
-                    size: MemOpBytes::Byte,
+                    size,
                 }))
             }
 
@@ -297,6 +396,9 @@ impl Stmt {
             [Symbol(op), List(exp)] if op == "return" => Ok(Self::Return(Return {
                 value: Expr::parse(exp)?,
             })),
+            [Symbol(op), Symbol(error)] if op == "raise" => Ok(Self::Raise(Raise {
+                error: error.clone(),
+            })),
             [Symbol(op), Symbol(name), List(arg0), List(arg1), List(arg2), List(arg3)]
                 if op == "ext_call" =>
             {
@@ -317,11 +419,14 @@ impl Stmt {
                 }))
             }
             [List(stmt)] => Self::parse(stmt.iter().collect::<Vec<_>>()),
-            x => panic!("Could not parse stmt: {:?}", x),
+            x => Err(FrontendError::unsupported("statement", x.iter().copied())),
         }
     }
 
-    fn parse_dec(decl: Vec<&SExpr>, scope: Option<Vec<&SExpr>>) -> anyhow::Result<Self> {
+    fn parse_dec(
+        decl: Vec<&SExpr>,
+        scope: Option<Vec<&SExpr>>,
+    ) -> Result<Self, FrontendError> {
         let scope = match scope {
             Some(stmts) => Self::parse(stmts)?,
             None => Self::Skip,
@@ -334,18 +439,24 @@ impl Stmt {
                     scope: Box::new(scope),
                 }))
             }
-            _ => Err(anyhow!("Not a valid declaration")),
+            x => Err(FrontendError::unsupported("declaration", x.iter().copied())),
         }
     }
 
-    fn parse_seq(s: &[&SExpr]) -> anyhow::Result<Self> {
+    fn parse_seq(s: &[&SExpr]) -> Result<Self, FrontendError> {
         if let [List(pos), pstmt] = s {
             match &pos[..] {
-                [Symbol(annot), SString(loc), SString(_position)]
+                [Symbol(annot), SString(loc), SString(position)]
                     if annot == "annot" && loc == "location" =>
                 {
-                    // TODO: return wrapper
-                    return Self::parse(vec![pstmt]);
+                    let stmt = Self::parse(vec![pstmt])?;
+                    return Ok(match parse_location(position) {
+                        Some(span) => Self::Spanned(SpannedStmt {
+                            span,
+                            stmt: Box::new(stmt),
+                        }),
+                        None => stmt,
+                    });
                 }
                 _ => (),
             }
@@ -355,58 +466,129 @@ impl Stmt {
             match stmt {
                 List(stmt) => stmts.push(Self::parse(stmt.iter().collect())?),
                 Symbol(stmt) => stmts.push(Self::parse_symbol(stmt)?),
-                _ => return Err(anyhow!("Error whilst parsing sequence")),
+                x => return Err(FrontendError::unsupported("statement", [*x])),
             }
         }
         Ok(Self::Seq(Seq { stmts }))
     }
 
-    fn parse_symbol(symbol: &str) -> anyhow::Result<Self> {
+    /// Calls `f` on this statement and all statements nested in it
+    pub(super) fn for_each_mut(&mut self, f: &mut impl FnMut(&mut Self)) {
+        f(self);
+        match self {
+            Self::Spanned(s) => s.stmt.for_each_mut(f),
+            Self::Declaration(decl) => decl.scope.for_each_mut(f),
+            Self::Seq(seq) => seq.stmts.iter_mut().for_each(|s| s.for_each_mut(f)),
+            Self::If(i) => {
+                i.if_branch.for_each_mut(f);
+                i.else_branch.for_each_mut(f)
+            }
+            Self::While(w) => w.body.for_each_mut(f),
+            Self::Handle(h) => {
+                h.call.for_each_mut(f);
+                h.handler.for_each_mut(f)
+            }
+            _ => (),
+        }
+    }
+
+    /// Expressions of this statement, excluding those of nested statements
+    fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Self::Declaration(decl) => vec![&mut decl.rhs],
+            Self::Assign(ass) => vec![&mut ass.rhs],
+            Self::Store(store) => vec![&mut store.address, &mut store.value],
+            Self::StoreBits(store) => vec![&mut store.address, &mut store.value],
+            Self::SharedStore(store) => vec![&mut store.address, &mut store.value],
+            Self::SharedStoreBits(store) => vec![&mut store.address, &mut store.value],
+            Self::SharedLoad(load) => vec![&mut load.address, &mut load.dst],
+            Self::SharedLoadBits(load) => vec![&mut load.address, &mut load.dst],
+            Self::If(i) => vec![&mut i.cond],
+            Self::While(w) => vec![&mut w.cond],
+            Self::Call(call) => std::iter::once(&mut call.fname)
+                .chain(call.args.iter_mut())
+                .collect(),
+            Self::TailCall(call) => std::iter::once(&mut call.fname)
+                .chain(call.args.iter_mut())
+                .collect(),
+            Self::ExtCall(call) => call.args.iter_mut().collect(),
+            Self::Return(ret) => vec![&mut ret.value],
+            _ => vec![],
+        }
+    }
+
+    fn parse_symbol(symbol: &str) -> Result<Self, FrontendError> {
         match symbol {
             "break" => Ok(Self::Break),
             "continue" => Ok(Self::Continue),
             "skip" => Ok(Self::Skip),
             "tick" => Ok(Self::Tick),
-            x => Err(anyhow!("Failed to parse stmt symbol: {}", x)),
+            x => Err(FrontendError::Unsupported {
+                construct: "statement",
+                sexpr: x.to_owned(),
+            }),
         }
     }
 }
 
 impl Arg {
-    fn parse(s: &SExpr) -> anyhow::Result<Self> {
+    fn parse(s: &SExpr) -> Result<Self, FrontendError> {
         match s {
             List(args) => match &args[..] {
                 [Symbol(name), Symbol(colon), Symbol(shape)] if colon == ":" => Ok(Self {
                     name: name.clone(),
-                    shape: Shape::parse(shape)?,
+                    shape: parse_shape(shape)?,
                 }),
                 [Symbol(name), Symbol(colon), Int(_)] if colon == ":" => Ok(Self {
                     name: name.clone(),
                     shape: Shape::Simple,
                 }),
-                _ => Err(anyhow!("Could not parse argument")),
+                x => Err(FrontendError::unsupported("argument", x)),
             },
-            _ => Err(anyhow!("Could not parse argument")),
+            x => Err(FrontendError::unsupported("argument", [x])),
         }
     }
 }
 
 impl FnDec {
-    fn parse(s: SExpr) -> anyhow::Result<Self> {
+    fn parse(s: SExpr) -> Result<Self, FrontendError> {
         match s {
             List(l) => match &l[..] {
-                [Symbol(fun_dec), Symbol(name), List(args), List(body)] if fun_dec == "func" => {
-                    let args = args.iter().map(Arg::parse).collect::<anyhow::Result<_>>()?;
+                [Symbol(fun_dec), Symbol(name), List(args), ret @ .., List(body)]
+                    if fun_dec == "func" && ret.len() <= 1 =>
+                {
+                    let in_function =
+                        |e| FrontendError::InFunction(name.clone(), Box::new(e));
+                    let args = args
+                        .iter()
+                        .map(Arg::parse)
+                        .collect::<Result<_, _>>()
+                        .map_err(in_function)?;
+                    // The declared return shape, if any, is printed between arguments and body
+                    let rettyp = match ret {
+                        [] => None,
+                        [Int(shape)] => Some(parse_shape(&shape.to_string())),
+                        [Symbol(shape)] => Some(parse_shape(shape)),
+                        x => Some(Err(FrontendError::unsupported("return shape", x))),
+                    };
+                    let body = Stmt::parse(body.iter().collect()).map_err(in_function)?;
+                    // `cake` does not locate the header, only the statements of the body
+                    let span = match &body {
+                        Stmt::Spanned(s) => Some(s.span.clone()),
+                        _ => None,
+                    };
                     Ok(Self {
                         fname: name.clone(),
                         args,
-                        body: Stmt::parse(body.iter().collect())?,
-                        rettyp: None,
+                        body,
+                        rettyp: rettyp.transpose().map_err(in_function)?,
+                        contract: vec![],
+                        span,
                     })
                 }
-                _ => Err(anyhow!("Shape of SExpr::List does not match")),
+                x => Err(FrontendError::unsupported("function declaration", x)),
             },
-            _ => Err(anyhow!("SExpr is not a list")),
+            x => Err(FrontendError::unsupported("function declaration", [&x])),
         }
     }
 }
@@ -450,52 +632,189 @@ impl Shape {
     }
 }
 
+/// Front end used to turn Pancake source code into a [`pancake::Program`]
+#[derive(Debug, Clone, Default)]
+pub enum Frontend {
+    /// Built-in parser for Pancake's concrete syntax
+    #[default]
+    Native,
+    /// Parse the output of `cake --pancake --explore`, using the given `cake` binary.
+    /// The output is cached in `cache_dir`, if set. The layout of the output is probed
+    /// from `cake --version`, unless `revision` is set.
+    Cake {
+        path: String,
+        cache_dir: Option<PathBuf>,
+        revision: Option<ExploreRevision>,
+    },
+}
+
+/// Options controlling how Pancake source code is preprocessed and parsed
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub frontend: Frontend,
+    /// Macro definitions passed to `cpp`, either `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    /// Directories searched by `cpp` for `#include`d files
+    pub include_dirs: Vec<String>,
+}
+
+impl ParseOptions {
+    /// Arguments shared by every `cpp` invocation, s.t. code and annotations see the same
+    /// macros. System specific macros (e.g. `linux`) are not predefined, as they might
+    /// clash with identifiers.
+    pub(super) fn cpp_args(&self) -> Vec<String> {
+        let defines = self.defines.iter().map(|d| format!("-D{d}"));
+        let includes = self.include_dirs.iter().map(|i| format!("-I{i}"));
+        std::iter::once("-undef".to_owned())
+            .chain(defines)
+            .chain(includes)
+            .collect()
+    }
+}
+
 impl Program {
-    pub fn parse_file(path: &str, cake_path: &str) -> anyhow::Result<Self> {
-        Self::parse_str(fs::read_to_string(path)?, cake_path)
+    pub fn parse_file(path: &str, options: &ParseOptions) -> anyhow::Result<Self> {
+        Self::parse_str(fs::read_to_string(path)?, Some(path), options)
+    }
+
+    /// Parses a Pancake program together with the modules it imports. Imports are
+    /// resolved relative to `path`, which is also used to report source locations.
+    pub fn parse_str(s: String, path: Option<&str>, options: &ParseOptions) -> anyhow::Result<Self> {
+        Importer::new(options).load(s, path)
     }
 
-    pub fn parse_str(s: String, cake_path: &str) -> anyhow::Result<Self> {
-        let predicates = Self::get_toplevel_annotations(&s, "predicate")
-            .into_iter()
-            .map(Predicate::new)
+    /// Parses a single Pancake file, without resolving its imports
+    pub(super) fn parse_module(
+        s: String,
+        path: Option<&str>,
+        options: &ParseOptions,
+    ) -> anyhow::Result<Self> {
+        let macros = Macros::from_cpp(&s, options)?;
+        let annotations = lex_annotations(&s, path)?;
+        let toplevel = |kind| {
+            annotations
+                .iter()
+                .filter(move |annot| annot.kind == kind)
+                .map(|annot| (macros.expand(&annot.text).0, annot.span.clone()))
+        };
+        let text = |kind| toplevel(kind).map(|(text, _)| text).collect::<Vec<_>>();
+        let predicates = toplevel(AnnotationKind::Predicate)
+            .map(|(text, span)| Predicate::new(text, span))
+            .collect();
+        let viper_functions = toplevel(AnnotationKind::Function)
+            .map(|(text, span)| Function::new(text, span))
             .collect();
-        let viper_functions = Self::get_toplevel_annotations(&s, "function")
-            .into_iter()
-            .map(Function::new)
+        let methods = toplevel(AnnotationKind::Method)
+            .map(|(text, span)| Method::new(text, span))
             .collect();
-        let methods = Self::get_toplevel_annotations(&s, "method")
-            .into_iter()
-            .map(Method::new)
+        let lemmas = toplevel(AnnotationKind::Lemma)
+            .map(|(text, span)| Lemma::new(text, span))
             .collect();
-        let shared = Self::get_toplevel_annotations(&s, "shared")
-            .into_iter()
-            .map(Shared::new)
+        let domains = toplevel(AnnotationKind::Domain)
+            .map(|(text, span)| Domain::new(text, span))
+            .collect();
+        let shared = toplevel(AnnotationKind::Shared)
+            .map(|(text, span)| Shared::new(text, span))
+            .collect();
+        let fnptrs = toplevel(AnnotationKind::FnPtr)
+            .map(|(text, span)| FnPtr::new(text, span))
             .collect();
 
-        let model_predicates = Self::get_toplevel_annotations(&s, "model predicate");
-        let model_fields = Self::get_toplevel_annotations(&s, "model field");
-
-        let extern_predicates = Self::get_toplevel_annotations(&s, "extern predicate");
-        let extern_fields = Self::get_toplevel_annotations(&s, "extern field");
-        let extern_consts = Self::get_toplevel_annotations(&s, "extern const");
-        let extern_methods = Self::get_toplevel_annotations(&s, "ffi");
-
-        let functions = get_sexprs(s, cake_path)?
-            .iter()
-            .map(|s| {
-                SExprParser
-                    .parse(s)
-                    .map_err(|e| anyhow!("{e}"))
-                    .and_then(FnDec::parse)
-            })
-            .collect::<anyhow::Result<_>>()?;
+        let model_predicates = text(AnnotationKind::ModelPredicate);
+        let model_fields = text(AnnotationKind::ModelField);
+
+        let extern_predicates = text(AnnotationKind::ExternPredicate);
+        let extern_fields = text(AnnotationKind::ExternField);
+        let extern_consts = text(AnnotationKind::ExternConst);
+        let extern_methods = text(AnnotationKind::Ffi);
+
+        let preprocessed = Preprocessed::new(&preprocess(s, options)?, path);
+        // `cake`'s explore output does not list globals
+        let (mut functions, mut globals) = match &options.frontend {
+            Frontend::Native => super::native::parse_program(&preprocessed.text)?,
+            Frontend::Cake {
+                path,
+                cache_dir,
+                revision,
+            } => {
+                let revision =
+                    revision.unwrap_or_else(|| ExploreRevision::probe(&cake_version(path)));
+                let sexprs = get_sexprs(preprocessed.text.clone(), path, cache_dir.as_deref())?;
+                let mut functions = parse_explore(&sexprs, revision)?;
+                let mut contracts = header_contracts(&preprocessed.text).map_err(|e| match e {
+                    LexError::DanglingContract(span) => {
+                        LexError::DanglingContract(preprocessed.remap(&span))
+                    }
+                    e => e,
+                })?;
+                for f in functions.iter_mut() {
+                    f.contract = contracts.remove(&f.fname).unwrap_or_default();
+                }
+                (functions, vec![])
+            }
+        };
+        let mut by_line: HashMap<usize, Vec<&LexedAnnotation>> = HashMap::new();
+        for annot in &annotations {
+            by_line
+                .entry(annot.span.start_line)
+                .or_default()
+                .push(annot);
+        }
+        // `cpp` already expanded the macros in annotations, losing track of their positions.
+        // Annotations of this file are thus taken as written and expanded with a map back.
+        let fix_annotation = |annot: &mut Annotation| {
+            let Some(span) = annot.span.as_ref() else {
+                return;
+            };
+            let remapped = preprocessed.remap(span);
+            let written = (remapped.file.as_deref() == path)
+                .then(|| by_line.get(&remapped.start_line))
+                .flatten()
+                .and_then(|annots| annots.get(preprocessed.annotation_index(span)));
+            match written {
+                Some(written) => {
+                    let (line, col) = (written.span.start_line, written.span.start_col + 2);
+                    let (end_line, end_col) = (written.span.end_line, written.span.end_col - 2);
+                    let text = &written.text[2..written.text.len() - 2];
+                    (annot.line, annot.macros) = macros.expand(text);
+                    annot.span = Some(Span::new(remapped.file, (line, col), (end_line, end_col)));
+                }
+                None => annot.span = Some(remapped),
+            }
+        };
+        let mut fix_expr = |expr: &mut Expr| {
+            if let Expr::Spanned(s) = expr {
+                s.span = preprocessed.remap(&s.span);
+            }
+        };
+        for f in functions.iter_mut() {
+            f.span = f.span.as_ref().map(|span| preprocessed.remap(span));
+            f.contract.iter_mut().for_each(&fix_annotation);
+            f.body.for_each_mut(&mut |stmt| {
+                for expr in stmt.exprs_mut() {
+                    expr.for_each_mut(&mut fix_expr);
+                }
+                match stmt {
+                    Stmt::Spanned(s) => s.span = preprocessed.remap(&s.span),
+                    Stmt::Annotation(annot) => fix_annotation(annot),
+                    _ => (),
+                }
+            });
+        }
+        for global in globals.iter_mut() {
+            global.span = global.span.as_ref().map(|span| preprocessed.remap(span));
+            global.value.for_each_mut(&mut fix_expr);
+        }
         Ok(pancake::Program {
             functions,
+            globals,
             predicates,
             viper_functions,
             methods,
+            lemmas,
+            domains,
             shared,
+            fnptrs,
             model_predicates,
             model_fields,
             extern_predicates,
@@ -505,39 +824,182 @@ impl Program {
         })
     }
 
-    fn get_toplevel_annotations(s: &str, toplevel_str: &str) -> Vec<String> {
-        let re = Regex::new(&format!("(?s)/@\\s*{}\\s*(.*?)\\s*@/", toplevel_str)).unwrap();
-        re.captures_iter(s)
-            .map(|capt| capt.get(0).unwrap().as_str().to_owned())
-            .collect()
+}
+
+/// Parses the S-expressions of the functions in `cake`'s explore output
+pub(super) fn parse_explore(
+    sexprs: &[String],
+    revision: ExploreRevision,
+) -> Result<Vec<FnDec>, FrontendError> {
+    sexprs
+        .iter()
+        .map(|s| {
+            SExprParser
+                .parse(s)
+                .map_err(|e| FrontendError::MalformedSExpr(e.to_string()))
+                .map(|s| revision.adapt(s))
+                .and_then(FnDec::parse)
+        })
+        .collect()
+}
+
+/// Contract annotations placed directly before a `fun` header, by function name.
+/// `cake` drops them from its explore output, so they are recovered from the source.
+/// Like the native parser, contracts followed by a global or the end of the file are
+/// rejected.
+pub(super) fn header_contracts(s: &str) -> Result<HashMap<String, Vec<Annotation>>, LexError> {
+    let header = Regex::new(r"^\s*(?:export\s+)?fun\s*(?:\d+|\{[^(]*\})?\s*(\w+)\s*\(").unwrap();
+    let blank = Regex::new(r"^(?:\s|//[^\n]*|/\*(?s:.)*?\*/)*$").unwrap();
+    let annotations = lex_annotations(s, None)?
+        .into_iter()
+        .filter(|annot| annot.toplevel)
+        .collect::<Vec<_>>();
+    let mut contracts = HashMap::new();
+    let mut contract = vec![];
+    for (i, annot) in annotations.iter().enumerate() {
+        if annot.kind == AnnotationKind::Contract {
+            // Like the native parser, only keep the text between the delimiters
+            let span = &annot.span;
+            contract.push(Annotation {
+                line: annot.text[2..annot.text.len() - 2].to_owned(),
+                span: Some(Span::new(
+                    None,
+                    (span.start_line, span.start_col + 2),
+                    (span.end_line, span.end_col - 2),
+                )),
+                macros: MacroMap::default(),
+            });
+        }
+        let end = annotations.get(i + 1).map_or(s.len(), |next| next.range.start);
+        if let Some(capt) = header.captures(&s[annot.range.end..]) {
+            if !contract.is_empty() {
+                contracts.insert(capt[1].to_owned(), std::mem::take(&mut contract));
+            }
+        } else if !contract.is_empty() && !blank.is_match(&s[annot.range.end..end]) {
+            return Err(LexError::DanglingContract(annot.span.clone()));
+        }
+    }
+    match contract.pop().and_then(|annot| annot.span) {
+        Some(span) => Err(LexError::DanglingContract(span)),
+        None => Ok(contracts),
+    }
+}
+
+/// Parses `cake`'s source locations of the form `(line:col line:col)`
+fn parse_location(position: &str) -> Option<Span> {
+    let re = Regex::new(r"(\d+):(\d+)\D+(\d+):(\d+)").unwrap();
+    let capt = re.captures(position)?;
+    let num = |i| capt.get(i).unwrap().as_str().parse::<usize>().ok();
+    Some(Span::new(None, (num(1)?, num(2)?), (num(3)?, num(4)?)))
+}
+
+/// Output of the C preprocessor. Line markers are blanked out, but used to map
+/// positions back to the original source.
+struct Preprocessed {
+    text: String,
+    origins: Vec<(Option<Arc<str>>, usize)>,
+}
+
+impl Preprocessed {
+    fn new(output: &str, path: Option<&str>) -> Self {
+        let marker = Regex::new(r#"^#\s*(\d+)\s+"(.*)""#).unwrap();
+        let mut file: Option<Arc<str>> = path.map(Arc::from);
+        let mut line = 1;
+        let mut text = String::with_capacity(output.len());
+        let mut origins = vec![];
+        for l in output.lines() {
+            if let Some(capt) = marker.captures(l) {
+                line = capt[1].parse().unwrap_or(1);
+                file = match &capt[2] {
+                    "<stdin>" => path.map(Arc::from),
+                    f => Some(Arc::from(f)),
+                };
+                origins.push((None, 0));
+            } else {
+                origins.push((file.clone(), line));
+                text.push_str(l);
+                line += 1;
+            }
+            text.push('\n');
+        }
+        Self { text, origins }
+    }
+
+    /// Index of the annotation starting at `span` among the annotations starting on its line
+    fn annotation_index(&self, span: &Span) -> usize {
+        let line = self.text.lines().nth(span.start_line.saturating_sub(1));
+        let before = line
+            .unwrap_or_default()
+            .chars()
+            .take(span.start_col.saturating_sub(1))
+            .collect::<String>();
+        before.matches("/@").count().saturating_sub(1)
+    }
+
+    /// Maps a span in the preprocessed text back to the original source
+    fn remap(&self, span: &Span) -> Span {
+        let origin = |line: usize| {
+            self.origins
+                .get(line.saturating_sub(1))
+                .cloned()
+                .unwrap_or((None, line))
+        };
+        let (file, start_line) = origin(span.start_line);
+        let (_, end_line) = origin(span.end_line);
+        Span {
+            file,
+            start_line,
+            end_line,
+            ..span.clone()
+        }
     }
 }
 
-fn get_sexprs(lines: String, cake_path: &str) -> anyhow::Result<Vec<String>> {
+/// Runs the C preprocessor on the Pancake source, keeping comments (and thus annotations)
+fn preprocess(lines: String, options: &ParseOptions) -> anyhow::Result<String> {
     let mut preprocess = Command::new("cpp")
-        .arg("-P")
         .arg("-C")
+        .args(options.cpp_args())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()?;
 
-    let explore = Command::new(cake_path)
+    let mut stdin = preprocess
+        .stdin
+        .take()
+        .ok_or(anyhow!("Could not take stdin"))?;
+
+    std::thread::spawn(move || stdin.write_all(lines.as_bytes()));
+
+    Ok(String::from_utf8(preprocess.wait_with_output()?.stdout)?)
+}
+
+fn get_sexprs(
+    lines: String,
+    cake_path: &str,
+    cache_dir: Option<&Path>,
+) -> anyhow::Result<Vec<String>> {
+    let cache = cache_dir.map(|dir| ExploreCache::new(dir, cake_path, &lines));
+    if let Some(sexprs) = cache.as_ref().and_then(ExploreCache::get) {
+        return Ok(sexprs);
+    }
+
+    let mut explore = Command::new(cake_path)
         .arg("--pancake")
         .arg("--explore")
-        .stdin(Stdio::from(
-            preprocess.stdout.ok_or(anyhow!("Could not pipe command"))?,
-        ))
+        .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()?;
 
-    let mut stdin = preprocess
+    let mut stdin = explore
         .stdin
         .take()
         .ok_or(anyhow!("Could not take stdin"))?;
 
     std::thread::spawn(move || stdin.write_all(lines.as_bytes()));
 
-    let output_str = String::from_utf8(explore.wait_with_output()?.stdout)?;
+    let output = explore.wait_with_output()?;
+    let output_str = String::from_utf8(output.stdout)?;
 
     let pancake_lines = output_str
         .split('\n')
@@ -546,8 +1008,9 @@ fn get_sexprs(lines: String, cake_path: &str) -> anyhow::Result<Vec<String>> {
         .collect::<Vec<_>>()
         .join("\n");
 
-    Ok(pancake_lines
-        .split("\n\n")
-        .map(|s| s.to_owned())
-        .collect::<Vec<_>>())
+    let sexprs = split_sexprs(&pancake_lines);
+    if let Some(cache) = cache.filter(|_| output.status.success()) {
+        cache.insert(&sexprs);
+    }
+    Ok(sexprs)
 }
@@ -1,4 +1,6 @@
-use super::Expr;
+use crate::utils::Span;
+
+use super::{Expr, MacroMap};
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
@@ -21,8 +23,17 @@ pub enum Stmt {
     TailCall(TailCall),
     ExtCall(ExtCall),
     Raise(Raise),
+    Handle(Handle),
     Return(Return),
     Tick,
+    Spanned(SpannedStmt),
+}
+
+/// Statement annotated with its location in the source
+#[derive(Debug, Clone)]
+pub struct SpannedStmt {
+    pub span: Span,
+    pub stmt: Box<Stmt>,
 }
 
 #[derive(Debug, Clone)]
@@ -137,7 +148,14 @@ pub struct ExtCall {
 #[derive(Debug, Clone)]
 pub struct Raise {
     pub error: String,
-    pub idk: Expr,
+}
+
+/// Call whose exception `error` is caught by `handler`
+#[derive(Debug, Clone)]
+pub struct Handle {
+    pub call: Box<Stmt>,
+    pub error: String,
+    pub handler: Box<Stmt>,
 }
 
 #[derive(Debug, Clone)]
@@ -148,4 +166,7 @@ pub struct Return {
 #[derive(Debug, Clone)]
 pub struct Annotation {
     pub line: String,
+    pub span: Option<Span>,
+    /// Maps positions in `line` back to the source, if macros were expanded in it
+    pub macros: MacroMap,
 }
@@ -0,0 +1,908 @@
+use std::fs;
+
+use super::cache::{split_sexprs, ExploreCache};
+use super::lexer::{lex_annotations, AnnotationKind};
+use super::macros::Macros;
+use super::native::parse_functions;
+use super::parser::{header_contracts, parse_explore};
+use super::*;
+use crate::utils::{ImportError, LexError, TranslationError};
+
+#[test]
+fn fun_decl() {
+    let f = parse_functions("fun main(1 x, {1,2} y) { return x; }").unwrap();
+    println!("{:?}", f);
+    assert_eq!(f.len(), 1);
+    assert_eq!(f[0].fname, "main");
+    assert_eq!(f[0].args.len(), 2);
+}
+
+#[test]
+fn declaration_scope() {
+    let f = parse_functions("fun main() { var 1 x = 1; x = x + 1; return x; }").unwrap();
+    println!("{:?}", f);
+    match &f[0].body {
+        Stmt::Seq(Seq { stmts }) => match &stmts[..] {
+            [Stmt::Spanned(SpannedStmt { stmt, .. })] => match stmt.as_ref() {
+                Stmt::Declaration(Declaration { scope, .. }) => match scope.as_ref() {
+                    Stmt::Seq(Seq { stmts }) => assert_eq!(stmts.len(), 2),
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            },
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn keyword_prefix() {
+    let f = parse_functions("fun main() { var variable = 0; state = 1; return 0; }").unwrap();
+    println!("{:?}", f);
+}
+
+#[test]
+fn precedence() {
+    let f = parse_functions("fun main() { return 1 + 2 * 3 < 4; }").unwrap();
+    println!("{:?}", f);
+    match &f[0].body {
+        Stmt::Seq(Seq { stmts }) => match &stmts[..] {
+            [Stmt::Spanned(SpannedStmt { stmt, .. })] => match stmt.as_ref() {
+                Stmt::Return(Return {
+                    value:
+                        Expr::Op(Op {
+                            optype: OpType::Less,
+                            ..
+                        }),
+                }) => (),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        },
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn nested_struct() {
+    let f = parse_functions("fun main() { var o = < 1, < 3, 4 >, <5, 7> >; return (o.2).1; }");
+    println!("{:?}", f.unwrap());
+}
+
+#[test]
+fn memory_ops() {
+    let f = parse_functions(
+        "fun main() {
+            var x = lds {1,1} @base + 8;
+            st @base, ld8 @base + 1;
+            st8 @base, 1 << 3;
+            !st8 @base, 17;
+            !ldw x, @base;
+            return 0;
+        }",
+    );
+    println!("{:?}", f.unwrap());
+}
+
+#[test]
+fn tick() {
+    let program = Program::parse_file("./tests/pass/tick.pnk", &ParseOptions::default()).unwrap();
+    crate::ir::Program::try_from(program).unwrap();
+}
+
+#[test]
+fn sub_word_memory_ops() {
+    let f = parse_functions(
+        "fun main() {
+            st16 @base, ld16 @base + 2;
+            st32 @base + 4, ld32 @base;
+            return 0;
+        }",
+    )
+    .unwrap();
+    let body = format!("{:?}", f[0].body);
+    println!("{}", body);
+    assert_eq!(body.matches("QuarterWord").count(), 2);
+    assert_eq!(body.matches("HalfWord").count(), 2);
+}
+
+#[test]
+fn annotations() {
+    let f = parse_functions(
+        "/@ predicate p() @/
+        fun main() {
+            /@ requires acc(heap[0]) @/
+            // not an annotation
+            /* neither is this */
+            /@ assert x < 1 &&
+                 y > 2 @/
+            return 0;
+        }",
+    )
+    .unwrap();
+    match &f[0].body {
+        Stmt::Seq(Seq { stmts }) => assert_eq!(stmts.len(), 3),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn calls() {
+    let f = parse_functions(
+        "fun main() {
+            foo(2);
+            @print_int(0, 0, 0, 1);
+            var 1 x = foo(2);
+            return foo(x);
+        }",
+    );
+    println!("{:?}", f.unwrap());
+}
+
+#[test]
+fn ffi_arity() {
+    assert!(parse_functions("fun main() { @print_int(0, 0); return 0; }").is_err());
+}
+
+#[test]
+fn spans() {
+    let program = Program::parse_str(
+        "#define ONE 1
+/@ predicate p() @/
+fun main() {
+    var x = ONE;
+    /@ assert x == 1 @/
+    return x;
+}"
+        .into(),
+        Some("main.pnk"),
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let Stmt::Seq(Seq { stmts }) = &program.functions[0].body else {
+        panic!()
+    };
+    let [Stmt::Spanned(SpannedStmt { span, stmt })] = &stmts[..] else {
+        panic!()
+    };
+    assert_eq!(span.to_string(), "main.pnk:4:5");
+    let Stmt::Declaration(Declaration { scope, .. }) = stmt.as_ref() else {
+        panic!()
+    };
+    let Stmt::Seq(Seq { stmts }) = scope.as_ref() else {
+        panic!()
+    };
+    match &stmts[..] {
+        [Stmt::Annotation(Annotation {
+            span: Some(annot), ..
+        }), Stmt::Spanned(SpannedStmt { span, .. })] => {
+            assert_eq!(annot.to_string(), "main.pnk:5:7");
+            assert_eq!(span.to_string(), "main.pnk:6:5");
+        }
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn expression_spans() {
+    use crate::utils::{Mangleable, Mangler};
+    let mangle = |source: &str| {
+        let program = Program::parse_str(source.into(), Some("main.pnk"), &ParseOptions::default());
+        let mut program = crate::ir::Program::try_from(program.unwrap()).unwrap();
+        program
+            .mangle(&mut Mangler::default())
+            .unwrap_err()
+            .to_string()
+    };
+    // Errors point at the offending subexpression, in code and in annotations
+    let err = mangle("fun main() {\n    var x = 1;\n    return x + y;\n}");
+    assert!(err.starts_with("main.pnk:3:16: "), "{err}");
+    let err = mangle("fun main() {\n    var x = 1;\n    /@ assert x == z @/\n    return x;\n}");
+    assert!(err.starts_with("main.pnk:3:20: "), "{err}");
+    let err = mangle("/@ function f(x: Int): Int {\n  x + w } @/\nfun main() { return 0; }");
+    assert!(err.starts_with("main.pnk:2:7: "), "{err}");
+}
+
+#[test]
+fn explore_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ExploreCache::new(dir.path(), "no-such-cake", "fun main() { return 0; }");
+    assert_eq!(cache.get(), None);
+    let sexprs = vec![
+        "(func main () (return (Const 0x0)))".to_owned(),
+        "(func f () skip)".to_owned(),
+    ];
+    cache.insert(&sexprs);
+    assert_eq!(cache.get(), Some(sexprs));
+    let other = ExploreCache::new(dir.path(), "no-such-cake", "fun main() { return 1; }");
+    assert_eq!(other.get(), None);
+}
+
+#[test]
+fn imports() {
+    let program = Program::parse_file("./tests/pass/import.pnk", &ParseOptions::default()).unwrap();
+    let mut fnames = program
+        .functions
+        .iter()
+        .map(|f| f.fname.as_str())
+        .collect::<Vec<_>>();
+    fnames.sort();
+    assert_eq!(fnames, ["add_one", "add_two", "main"]);
+    assert_eq!(program.predicates.len(), 1);
+
+    let err =
+        Program::parse_file("./tests/fail/import_cycle.pnk", &ParseOptions::default()).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ImportError>(),
+        Some(ImportError::Cycle(_))
+    ));
+
+    let path = "./tests/fail/import_duplicate.pnk";
+    let program = Program::parse_file(path, &ParseOptions::default()).unwrap();
+    let err = crate::ir::Program::try_from(program).unwrap_err();
+    let TranslationError::DuplicateDefinition(name, first, second) = err else {
+        panic!("{err}")
+    };
+    assert_eq!(name, "helper");
+    assert_eq!([first, second], [path, "./tests/fail/modules/helper.pnk"]);
+}
+
+#[test]
+fn macro_expansion() {
+    let options = ParseOptions {
+        defines: vec!["SIZE=8".into()],
+        ..Default::default()
+    };
+    let source = "#define BASE 0x1000\n#define AT(i) (BASE + (i) * SIZE)\n";
+    let macros = Macros::from_cpp(source, &options).unwrap();
+    let (expanded, _) = macros.expand("acc(heap[AT(2)]) && BASE_X == 0x1F && linux");
+    assert_eq!(
+        expanded,
+        "acc(heap[(0x1000 + (2) * 8)]) && BASE_X == 0x1F && linux"
+    );
+
+    let (expanded, map) = macros.expand("BASE <\n  AT(1) ^^ x");
+    assert_eq!(expanded, "0x1000 <\n  (0x1000 + (1) * 8) ^^ x");
+    // inside an expansion: start of the invocation
+    assert_eq!(map.original_pos(&expanded, 1, 3), (1, 1));
+    assert_eq!(map.original_pos(&expanded, 1, 8), (1, 6));
+    // after an expansion: shifted back to the written text
+    assert_eq!(map.original_pos(&expanded, 2, 22), (2, 9));
+}
+
+#[test]
+fn macros_in_annotations() {
+    let program = Program::parse_str(
+        "#define UART_BASE 0x1000
+/@ shared rw u32 uart[UART_BASE] @/
+fun main() {
+    /@ assert UART_BASE == 4096 @/
+    return 0;
+}"
+        .into(),
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(program.shared[0].text, "/@ shared rw u32 uart[0x1000] @/");
+    let Stmt::Seq(Seq { stmts }) = &program.functions[0].body else {
+        panic!()
+    };
+    match &stmts[0] {
+        Stmt::Annotation(annot) => assert_eq!(annot.line.trim(), "assert 0x1000 == 4096"),
+        _ => panic!(),
+    }
+
+    // Code and annotations are preprocessed without system specific macros
+    let source = "fun main() { var linux = 1; /@ assert linux == 1 @/ return linux; }";
+    let program = Program::parse_str(source.into(), None, &ParseOptions::default()).unwrap();
+    let body = format!("{:?}", program.functions[0].body);
+    assert!(body.contains("lhs: \"linux\""), "{}", body);
+    assert!(body.contains("assert linux == 1"), "{}", body);
+
+    // Annotations are expanded once, as written, even though `cpp` also expands them
+    let source = "#define x (x + 1)
+fun main() {
+    var  y  = x; /@ assert x  ==  1 @/ /@ assert y > x @/
+    return 0;
+}";
+    let mut program = Program::parse_str(source.into(), None, &ParseOptions::default()).unwrap();
+    let mut annots = vec![];
+    program.functions[0].body.for_each_mut(&mut |stmt| {
+        if let Stmt::Annotation(annot) = stmt {
+            let span = annot.span.clone().unwrap();
+            annots.push((annot.line.clone(), (span.start_line, span.start_col)));
+        }
+    });
+    assert_eq!(
+        annots,
+        [
+            (" assert (x + 1)  ==  1 ".to_owned(), (3, 20)),
+            (" assert y > (x + 1) ".to_owned(), (3, 42))
+        ]
+    );
+}
+
+#[test]
+fn fn_ptrs() {
+    let parse = |path| {
+        let program = Program::parse_file(path, &ParseOptions::default()).unwrap();
+        crate::ir::Program::try_from(program)
+    };
+    let program = parse("./tests/pass/fnptr.pnk").unwrap();
+    let fnames = program
+        .functions
+        .iter()
+        .map(|f| f.fname.as_str())
+        .collect::<Vec<_>>();
+    assert!(fnames.contains(&"call_unop_t"));
+    assert!(fnames.contains(&"succ_implements_unop_t"));
+    assert!(fnames.contains(&"equal_implements_binop_t"));
+    let viper_functions = program
+        .viper_functions
+        .iter()
+        .map(|f| f.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        viper_functions,
+        [
+            "double_addr",
+            "equal_addr",
+            "succ_addr",
+            "unop_t",
+            "binop_t"
+        ]
+    );
+
+    let err = crate::ir::Program::try_from(
+        Program::parse_str(
+            "fun main() { var f = &main; var r = f(1, 2); return r; }".into(),
+            None,
+            &ParseOptions::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        crate::utils::TranslationError::Spanned(_, e)
+            if matches!(*e, crate::utils::TranslationError::NoFnPtrType(_, 2))
+    ));
+
+    // Abstract methods are called directly, also in programs with function pointers
+    let program = crate::ir::Program::try_from(
+        Program::parse_str(
+            "/@ fnptr unop_t(1 x) @/
+/@ method read(a: Int): (r: Int) @/
+fun main() { var 1 r = read(1); return r; }"
+                .into(),
+            None,
+            &ParseOptions::default(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let body = format!("{:?}", program.functions[0].body);
+    assert!(body.contains("fname: \"read\""), "{}", body);
+    assert!(!body.contains("call_unop_t"), "{}", body);
+}
+
+#[test]
+fn exceptions() {
+    let program =
+        Program::parse_file("./tests/pass/exceptions.pnk", &ParseOptions::default()).unwrap();
+    let program = crate::ir::Program::try_from(program).unwrap();
+    assert!(program.functions.iter().all(|f| f.raises));
+    let posts = program.functions[0]
+        .posts
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    // `raised(Overflow)` is a check of the exception id, other postconditions are guarded
+    assert!(posts[0].starts_with("((_exception == 1) ==> "));
+    assert_eq!(posts[1], "((_exception == 0) ==> (retval == (a + b)))");
+    assert!(posts[2].starts_with("((_exception == 0) ==> "));
+
+    let program = Program::parse_str(
+        "fun main() { return 0; }".into(),
+        None,
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    let program = crate::ir::Program::try_from(program).unwrap();
+    assert!(!program.functions[0].raises);
+
+    // `exception` is an ordinary name
+    use crate::utils::{Mangleable, Mangler};
+    let source = "fun f(1 exception) { /@ ensures retval == exception @/ raise E; }";
+    let program = Program::parse_str(source.into(), None, &ParseOptions::default()).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    let unmangled = program.model.unmangled_names().collect();
+    program.mangle(&mut Mangler::new(unmangled)).unwrap();
+}
+
+#[test]
+fn bit_not_and_shifts() {
+    let program = "fun f(1 x, 1 n) { var y = ~x; return (y << n) >>> (n + 1) >> 0x2; }";
+    let program = Program::parse_str(program.into(), None, &ParseOptions::default()).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    program.functions[0].body.visit_exprs_mut(&mut |e| {
+        if let crate::ir::Expr::Spanned(s) = e {
+            *e = *s.expr.clone();
+        }
+    });
+    let body = format!("{:?}", program.functions[0].body);
+    assert!(body.contains("UnOp(UnOp { optype: BitNot, right: Var(\"x\") })"));
+    assert!(body.contains("shifttype: Lsl, value: Var(\"y\"), amount: Var(\"n\")"));
+    assert!(body.contains("shifttype: Lsr"));
+    assert!(body.contains("shifttype: Asr"));
+    assert!(body.contains("amount: Const(2)"));
+}
+
+fn resolve_types(path: &str) -> Result<crate::utils::TypeContext, crate::utils::TranslationError> {
+    use crate::utils::{Mangleable, Mangler};
+    let program = Program::parse_file(path, &ParseOptions::default()).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    let unmangled = program.model.unmangled_names().collect();
+    program.mangle(&mut Mangler::new(unmangled)).unwrap();
+    program.resolve_types()
+}
+
+#[test]
+fn return_shapes() {
+    use crate::{
+        ir::Type,
+        utils::{Mangleable, Mangler, Shape, TranslationError},
+    };
+    let ctx = resolve_types("./tests/pass/return_shapes.pnk").unwrap();
+    let pair = Type::Struct(vec![Shape::Simple, Shape::Simple]);
+    for fname in ["pair", "forward", "abstract_pair"] {
+        assert_eq!(
+            ctx.get_function_type(&format!("f_{}", fname)).unwrap(),
+            pair
+        );
+    }
+
+    let err = resolve_types("./tests/fail/return_shape_mismatch.pnk").unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with("Function 'choose' returns values of different shapes: `{1, 1}` and `1`"));
+
+    // Only functions that just return recursive calls are assumed to return a word
+    let program = "fun ping(1 a) { return pong(a); } fun pong(1 a) { return ping(a); }
+        fun stuck(1 a) { skip; } fun forward(1 a) { return stuck(a); }";
+    let program = Program::parse_str(program.into(), None, &ParseOptions::default()).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    program.mangle(&mut Mangler::default()).unwrap();
+    let ctx = program.resolve_types().unwrap();
+    assert_eq!(ctx.get_function_type("f_ping").unwrap(), Type::Int);
+    assert_eq!(ctx.get_function_type("f_pong").unwrap(), Type::Int);
+    for fname in ["f_stuck", "f_forward"] {
+        let err = ctx.get_function_type(fname).unwrap_err();
+        assert!(matches!(err, TranslationError::UnknownReturnType(f) if f == fname));
+    }
+}
+
+#[test]
+fn method_returns() {
+    use crate::{
+        ir::Type,
+        utils::{Mangleable, Mangler, Shape},
+    };
+    let ctx = resolve_types("./tests/pass/method_returns.pnk").unwrap();
+    // Several returns form a struct with one field per return
+    let pair = Shape::Nested(vec![Shape::Simple, Shape::Simple]);
+    let read = Type::Struct(vec![Shape::Simple, Shape::Simple]);
+    assert_eq!(ctx.get_function_type("f_device_read").unwrap(), read);
+    let typ = ctx.get_function_type("f_device_pair").unwrap();
+    assert_eq!(typ, Type::Struct(vec![Shape::Simple, pair]));
+    let returns = ctx.get_method_returns("f_device_read").unwrap();
+    assert_eq!(returns, [Type::Int, Type::Int]);
+
+    let program = "/@ method m(): (a: Int, b: Bool) @/ fun main() { return 0; }";
+    let program = Program::parse_str(program.into(), None, &ParseOptions::default()).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    program.mangle(&mut Mangler::default()).unwrap();
+    let err = program.resolve_types().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Method 'm' has several returns, which must be words or structs, got `Bool`"
+    );
+}
+
+#[test]
+fn globals() {
+    use crate::{ir::Type, utils::Shape};
+    let program = Program::parse_file("./tests/pass/globals.pnk", &ParseOptions::default());
+    let program = crate::ir::Program::try_from(program.unwrap()).unwrap();
+    let constant = |name| program.model.global(name).unwrap().constant;
+    assert!(!constant("counter"));
+    assert!(constant("primes"));
+
+    let ctx = resolve_types("./tests/pass/globals.pnk").unwrap();
+    assert_eq!(ctx.get_type_no_mangle("counter").unwrap(), Type::Int);
+    assert_eq!(
+        ctx.get_type_no_mangle("primes").unwrap(),
+        Type::Struct(vec![Shape::Simple; 3])
+    );
+
+    let err = resolve_types("./tests/fail/global_shape_mismatch.pnk").unwrap_err();
+    assert!(err.to_string().ends_with(
+        "Global 'pair' of shape `{1, 1}` is initialised with a value of shape `{1, 1, 1}`"
+    ));
+
+    let program = Program::parse_str("var 1 x = y;".into(), None, &ParseOptions::default());
+    let err = crate::ir::Program::try_from(program.unwrap()).unwrap_err();
+    assert!(matches!(err, crate::utils::TranslationError::NonConstantGlobal(x) if x == "x"));
+}
+
+#[test]
+fn ghosts() {
+    use crate::utils::{MangleError, Mangleable, Mangler, TranslationError};
+    let mangle = |path| {
+        let program = Program::parse_file(path, &ParseOptions::default()).unwrap();
+        let mut program = crate::ir::Program::try_from(program).unwrap();
+        let unmangled = program.model.unmangled_names().collect();
+        program.mangle(&mut Mangler::new(unmangled))
+    };
+    mangle("./tests/pass/ghost.pnk").unwrap();
+    resolve_types("./tests/pass/ghost.pnk").unwrap();
+    let err = mangle("./tests/fail/ghost_in_code.pnk").unwrap_err();
+    let TranslationError::Spanned(_, err) = err else {
+        panic!("{err}")
+    };
+    assert!(matches!(*err, TranslationError::MangleError(MangleError::GhostInCode(g)) if g == "g"));
+}
+
+#[test]
+fn let_bindings() {
+    use crate::{
+        ir::{Expr, Let},
+        utils::{Mangleable, Mangler},
+    };
+    let path = "./tests/pass/let.pnk";
+    let program = Program::parse_file(path, &ParseOptions::default()).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    program.mangle(&mut Mangler::default()).unwrap();
+    let mut lets = vec![];
+    program.functions[0].body.visit_exprs(&mut |e| {
+        if let Expr::Let(l) = e {
+            lets.push(l.clone())
+        }
+    });
+    // The value refers to the variable `x`, the body to the binding that shadows it
+    let Let { var, value, body } = &lets[0];
+    let Expr::BinOp(value) = &**value else {
+        panic!()
+    };
+    let Expr::Var(x) = value.left.unspanned() else {
+        panic!()
+    };
+    assert!(var.starts_with("x_") && x.starts_with("x_") && var != x);
+    assert!(body.to_string().starts_with(&format!("({var} == ")));
+    let ctx = program.resolve_types().unwrap();
+    assert_eq!(ctx.get_type_no_mangle(var).unwrap(), crate::ir::Type::Int);
+}
+
+#[test]
+fn lemmas() {
+    use crate::utils::TranslationError;
+    let to_ir = |program: anyhow::Result<Program>| crate::ir::Program::try_from(program.unwrap());
+    let program = to_ir(Program::parse_file(
+        "./tests/pass/lemma.pnk",
+        &ParseOptions::default(),
+    ));
+    let program = program.unwrap();
+    assert_eq!(program.lemmas.len(), 2);
+    assert_eq!(
+        program.lemmas[1].decreases.as_ref().unwrap().to_string(),
+        "(b - a)"
+    );
+    // Recursive applications check the measure first
+    let mut exprs = vec![];
+    program.lemmas[0]
+        .body
+        .visit_exprs(&mut |e| exprs.push(e.to_string()));
+    assert!(exprs.contains(&"((0 <= (n - 1)) && ((n - 1) < old(n)))".to_owned()));
+    resolve_types("./tests/pass/lemma.pnk").unwrap();
+
+    let path = "./tests/fail/lemma_no_measure.pnk";
+    let err = to_ir(Program::parse_file(path, &ParseOptions::default())).unwrap_err();
+    assert!(matches!(err, TranslationError::MissingMeasure(l) if l == "even"));
+    let program = "fun f() { /@ apply nope() @/ return 0; }";
+    let err = to_ir(Program::parse_str(
+        program.into(),
+        None,
+        &ParseOptions::default(),
+    ));
+    assert!(matches!(err.unwrap_err(), TranslationError::UnknownLemma(l) if l == "nope"));
+}
+
+#[test]
+fn termination() {
+    let to_ir = |program: anyhow::Result<Program>| crate::ir::Program::try_from(program.unwrap());
+    let path = "./tests/pass/termination.pnk";
+    let program = to_ir(Program::parse_file(path, &ParseOptions::default())).unwrap();
+    let f = |name: &str| program.functions.iter().find(|f| f.fname == name).unwrap();
+    assert!(f("count").recursive && f("is_even").recursive && f("is_odd").recursive);
+    assert!(!f("count_down").recursive);
+    assert_eq!(f("count").decreases.as_ref().unwrap().to_string(), "n");
+    // Recursive calls check the measure against the one saved on entry
+    let mut exprs = vec![];
+    f("is_even")
+        .body
+        .visit_exprs(&mut |e| exprs.push(e.to_string()));
+    let check = "((0 <= (n - 1)) && ((n - 1) < fr_";
+    assert!(exprs.iter().any(|e| e.starts_with(check)));
+    resolve_types(path).unwrap();
+
+    let program = "fun f(1 n) { return f(n); }";
+    let program = Program::parse_str(program.into(), None, &ParseOptions::default());
+    let f = &to_ir(program).unwrap().functions[0];
+    assert!(f.recursive && f.decreases.is_none());
+}
+
+#[test]
+fn domains() {
+    use crate::{ir::Type, utils::TranslationError};
+    let to_ir = |program: anyhow::Result<Program>| crate::ir::Program::try_from(program.unwrap());
+    let path = "./tests/pass/domain.pnk";
+    let program = to_ir(Program::parse_file(path, &ParseOptions::default())).unwrap();
+    let [domain] = &program.domains[..] else {
+        panic!()
+    };
+    assert_eq!(domain.functions.len(), 3);
+    let list = Type::Domain("IntList".into());
+    assert_eq!(domain.functions[1].args[1].typ, list);
+    // Unnamed axioms are numbered
+    assert_eq!(domain.axioms[2].name, "IntList_axiom_2");
+    let ctx = resolve_types(path).unwrap();
+    assert_eq!(ctx.get_function_type("f_len").unwrap(), Type::Int);
+    assert_eq!(ctx.get_function_domain("f_cons"), Some("IntList"));
+    assert_eq!(ctx.get_function_domain("f_singleton"), None);
+
+    let program = "/@ domain D { function f(): Int } @/ /@ function f(): Int @/";
+    let err = to_ir(Program::parse_str(
+        program.into(),
+        None,
+        &ParseOptions::default(),
+    ));
+    assert!(matches!(err.unwrap_err(), TranslationError::DuplicateDefinition(f, ..) if f == "f"));
+}
+
+#[test]
+fn header_contract() {
+    let path = "./tests/pass/header_contract.pnk";
+    let source = fs::read_to_string(path).unwrap();
+    let program = Program::parse_file(path, &ParseOptions::default()).unwrap();
+    let contracts = header_contracts(&source).unwrap();
+    fn lines(annots: &[Annotation]) -> Vec<&str> {
+        annots.iter().map(|a| a.line.as_str()).collect()
+    }
+    for f in &program.functions {
+        let expected = contracts.get(&f.fname).map_or(vec![], |c| lines(c));
+        assert_eq!(lines(&f.contract), expected, "{}", f.fname);
+    }
+    assert_eq!(program.functions[1].contract.len(), 2);
+    assert!(program.functions[2].contract.is_empty());
+    for annot in [&program.functions[1].contract[1], &contracts["succ"][1]] {
+        let span = annot.span.as_ref().unwrap();
+        assert_eq!((span.start_line, span.start_col), (12, 3));
+    }
+
+    let program = crate::ir::Program::try_from(program).unwrap();
+    assert_eq!(program.functions[0].pres.len(), 1);
+    assert_eq!(program.functions[0].posts.len(), 1);
+    assert_eq!(program.functions[2].posts.len(), 1);
+    let ctx = resolve_types(path).unwrap();
+    let succ = ctx.get_function_type("f_succ").unwrap();
+    assert_eq!(succ, crate::ir::Type::Int);
+
+    let program = Program::parse_file("./tests/fail/duplicate_contract.pnk", &Default::default());
+    let err = crate::ir::Program::try_from(program.unwrap()).unwrap_err();
+    assert!(matches!(err, crate::utils::TranslationError::DuplicateContract(f) if f == "incr"));
+    let err = parse_functions("/@ requires true @/ var 1 x = 0;").unwrap_err();
+    assert!(err.to_string().contains("must precede a function"));
+    for source in [
+        "/@ requires true @/ var 1 x = 0;",
+        "fun f() {}\n/@ requires true @/\n",
+    ] {
+        let err = header_contracts(source).unwrap_err();
+        assert!(matches!(err, LexError::DanglingContract(_)), "{}", source);
+    }
+    let source = "/@ requires true @/\n// f\n/@ predicate p() @/\n/@ ensures true @/ fun f() {}";
+    assert_eq!(header_contracts(source).unwrap()["f"].len(), 2);
+}
+
+#[test]
+fn annotation_lexer() {
+    let source = r#"// /@ predicate commented() @/
+/* /@ predicate block_comment() @/ */
+/@ model predicate state_invariant(state: Ref) @/
+/@ predicate p(x: Int) {
+    x > 0 // "not a string
+} @/
+/@ requires true @/
+fun main() {
+    /@ assert "/@ predicate in_string() @/" == 0 */
+    return 0;
+}
+"#;
+    let annots = lex_annotations(source, Some("main.pnk")).unwrap();
+    let kinds = annots.iter().map(|a| a.kind).collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            AnnotationKind::ModelPredicate,
+            AnnotationKind::Predicate,
+            AnnotationKind::Contract,
+            AnnotationKind::Statement
+        ]
+    );
+    assert_eq!(annots[1].span.to_string(), "main.pnk:4:1");
+    assert_eq!((annots[1].span.end_line, annots[1].span.end_col), (6, 5));
+    assert!(annots[1].text.starts_with("/@ predicate p") && annots[1].text.ends_with("} @/"));
+    assert!(!annots[3].toplevel);
+
+    let err = |source| lex_annotations(source, None).unwrap_err().to_string();
+    assert_eq!(
+        err("/@ assert true @/ fun main() { return 0; }"),
+        "line 1:1: Unknown top-level annotation `assert`"
+    );
+    assert_eq!(
+        err("fun main() {\n  /@ predicate p() @/\n  return 0;\n}"),
+        "line 2:3: `predicate` annotations are only allowed at the top level"
+    );
+    assert_eq!(
+        err("fun main() { /@ assert true "),
+        "line 1:14: Unterminated annotation"
+    );
+}
+
+#[test]
+fn explore_revisions() {
+    use clap::ValueEnum;
+
+    let parse = |revision: ExploreRevision, file: &str| {
+        let name = revision.to_possible_value().unwrap().get_name().to_owned();
+        let path = format!("./tests/explore/{}/{}", name, file);
+        let sexprs = split_sexprs(fs::read_to_string(&path).unwrap().trim());
+        match parse_explore(&sexprs, revision) {
+            Ok(functions) => functions,
+            Err(e) => panic!("Failed to parse {}: {}", path, e),
+        }
+    };
+    // Every revision has to produce the same program as the pinned one
+    for entry in fs::read_dir("./tests/explore/d8b47adc").unwrap() {
+        let file = entry.unwrap().file_name().into_string().unwrap();
+        let expected = format!("{:?}", parse(ExploreRevision::D8b47adc, &file));
+        for &revision in ExploreRevision::value_variants() {
+            let functions = format!("{:?}", parse(revision, &file));
+            assert_eq!(functions, expected, "{:?} {}", revision, file);
+        }
+    }
+
+    // The pinned programs, as the statements of their bodies in order
+    fn stmts(stmt: &Stmt) -> Vec<&Stmt> {
+        match stmt {
+            Stmt::Seq(seq) => seq.stmts.iter().flat_map(stmts).collect(),
+            Stmt::Spanned(s) => stmts(&s.stmt),
+            Stmt::Declaration(dec) => [stmt].into_iter().chain(stmts(&dec.scope)).collect(),
+            _ => vec![stmt],
+        }
+    }
+    let is_var = |e: &Expr, name: &str| matches!(e, Expr::Var(v) if v == name);
+    let calls = parse(ExploreRevision::D8b47adc, "calls.sexp");
+    let fnames = calls.iter().map(|f| f.fname.as_str()).collect::<Vec<_>>();
+    assert_eq!(fnames, ["inc", "main"]);
+    match stmts(&calls[0].body)[..] {
+        [Stmt::Annotation(annot), Stmt::Return(ret)] => {
+            assert_eq!(annot.line, " ensures retval == x + 1 ");
+            assert!(matches!(&ret.value, Expr::Op(op) if matches!(op.optype, OpType::Add)));
+        }
+        ref other => panic!("{:?}", other),
+    }
+    let is_inc = |e: &Expr| matches!(e, Expr::Label(f) if f == "inc");
+    match stmts(&calls[1].body)[..] {
+        [Stmt::Declaration(dec), Stmt::Call(call), Stmt::Return(ret)] => {
+            assert!(dec.lhs == "y" && matches!(&dec.rhs, Expr::Call(c) if is_inc(&c.fname)));
+            assert!(is_inc(&call.fname) && is_var(&call.args[0], "y"));
+            assert!(is_var(&ret.value, "y"));
+        }
+        ref other => panic!("{:?}", other),
+    }
+
+    let bits = [8, 16, 32];
+    let heap = parse(ExploreRevision::D8b47adc, "heap.sexp");
+    let heap = stmts(&heap[0].body);
+    let [Stmt::Store(store), stores @ .., Stmt::Return(_)] = &heap[..] else {
+        panic!("{:?}", heap)
+    };
+    assert!(is_var(&store.address, "addr") && stores.len() == 3);
+    for (store, bits) in stores.iter().zip(bits) {
+        let Stmt::StoreBits(store) = store else {
+            panic!("{:?}", store)
+        };
+        assert_eq!(store.size.bits(), bits);
+        assert!(matches!(&store.value, Expr::LoadBits(l) if l.size.bits() == bits));
+    }
+
+    let shared = parse(ExploreRevision::D8b47adc, "shared_memory.sexp");
+    let shared = stmts(&shared[0].body);
+    let [Stmt::Declaration(_), Stmt::SharedLoad(load), rest @ .., Stmt::Return(_)] = &shared[..]
+    else {
+        panic!("{:?}", shared)
+    };
+    let [l1, l2, l3, Stmt::SharedStore(store), s1, s2, s3] = rest else {
+        panic!("{:?}", rest)
+    };
+    assert!(is_var(&load.dst, "v") && is_var(&store.value, "v"));
+    for ((load, store), bits) in [l1, l2, l3].into_iter().zip([s1, s2, s3]).zip(bits) {
+        assert!(matches!(load, Stmt::SharedLoadBits(l) if l.size.bits() == bits));
+        assert!(matches!(store, Stmt::SharedStoreBits(s) if s.size.bits() == bits));
+    }
+
+    assert_eq!(
+        ExploreRevision::probe("The CakeML compiler\nGit commit: d8b47adc"),
+        ExploreRevision::D8b47adc
+    );
+    assert_eq!(ExploreRevision::probe(""), ExploreRevision::D8b47adc);
+    assert_eq!(
+        ExploreRevision::probe("The CakeML compiler\nGit commit: 0123abcd"),
+        ExploreRevision::DecCall
+    );
+}
+
+#[test]
+fn explore_return_shapes() {
+    use crate::utils::{Mangleable, Mangler, Shape};
+    let explore = "(func pair () {1,1}
+ (return (Struct (Const 0x1) (Const 0x2))))
+
+(func one ((x : 1)) 1
+ (return (Var x)))
+
+(func inferred ((x : 1))
+ (return (Var x)))";
+    let functions = parse_explore(&split_sexprs(explore), ExploreRevision::D8b47adc).unwrap();
+    let shapes = functions.iter().map(|f| f.rettyp.clone());
+    let shapes = shapes.collect::<Vec<_>>();
+    let pair = Shape::Nested(vec![Shape::Simple, Shape::Simple]);
+    assert_eq!(shapes, vec![Some(pair), Some(Shape::Simple), None]);
+
+    // Declared shapes are checked against the returned values like in the native frontend
+    let mismatch = "(func choose ((x : 1)) {1,1}\n (return (Var x)))";
+    let mut program = Program::parse_str("".into(), None, &ParseOptions::default()).unwrap();
+    program.functions = parse_explore(&split_sexprs(mismatch), ExploreRevision::D8b47adc).unwrap();
+    let mut program = crate::ir::Program::try_from(program).unwrap();
+    let unmangled = program.model.unmangled_names().collect();
+    program.mangle(&mut Mangler::new(unmangled)).unwrap();
+    let err = program.resolve_types().unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with("Function 'choose' returns values of different shapes: `{1, 1}` and `1`"));
+}
+
+#[test]
+fn test_suite() {
+    let dirs = [
+        "./tests/pass",
+        "./tests/fail",
+        "./tests/shared/pass",
+        "./tests/shared/fail",
+    ];
+    for dir in dirs {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.is_file() {
+                continue;
+            }
+            let program = fs::read_to_string(&path).unwrap();
+            let options = ParseOptions::default();
+            if let Err(e) = Program::parse_module(program, path.to_str(), &options) {
+                panic!("Failed to parse {:?}: {}", path, e);
+            }
+        }
+    }
+}
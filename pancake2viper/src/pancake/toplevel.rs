@@ -1,6 +1,6 @@
-use crate::utils::Shape;
+use crate::utils::{Shape, Span};
 
-use super::Stmt;
+use super::{Annotation, Expr, Stmt};
 
 #[derive(Debug, Clone)]
 pub struct FnDec {
@@ -8,6 +8,10 @@ pub struct FnDec {
     pub args: Vec<Arg>,
     pub body: Stmt,
     pub rettyp: Option<Shape>,
+    /// Contract annotations placed before the `fun` header
+    pub contract: Vec<Annotation>,
+    /// Location of the declaration, if known
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, Clone)]
@@ -16,33 +20,69 @@ pub struct Arg {
     pub shape: Shape,
 }
 
+/// Module-level variable. Globals that are never assigned hold static data.
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: String,
+    pub shape: Shape,
+    pub value: Expr,
+    /// Location of the declaration, if known
+    pub span: Option<Span>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Predicate {
     pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Function {
     pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Method {
     pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lemma {
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Domain {
+    pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Shared {
     pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct FnPtr {
+    pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
 pub struct Program {
     pub functions: Vec<FnDec>,
+    pub globals: Vec<Global>,
     pub predicates: Vec<Predicate>,
     pub viper_functions: Vec<Function>,
     pub methods: Vec<Method>,
+    pub lemmas: Vec<Lemma>,
+    pub domains: Vec<Domain>,
     pub shared: Vec<Shared>,
+    pub fnptrs: Vec<FnPtr>,
     pub model_predicates: Vec<String>,
     pub model_fields: Vec<String>,
     pub extern_predicates: Vec<String>,
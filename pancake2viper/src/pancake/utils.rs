@@ -1,6 +1,9 @@
-use crate::utils::TranslationError;
+use crate::utils::{Span, TranslationError};
 
-use super::{Expr, Function, Method, Predicate, Shared, Struct};
+use super::{
+    lexer::AnnotationKind, Annotation, Domain, Expr, FnPtr, Function, Lemma, Method, Predicate,
+    Shared, Struct,
+};
 
 impl Struct {
     pub fn new(elements: Vec<Expr>) -> Self {
@@ -19,37 +22,60 @@ impl Struct {
 }
 
 impl Predicate {
-    pub fn new(text: String) -> Self {
-        Self { text }
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
     }
 }
 
 impl Function {
-    pub fn new(text: String) -> Self {
-        Self { text }
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
     }
 }
 
 impl Method {
-    pub fn new(text: String) -> Self {
-        Self { text }
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
+    }
+}
+
+impl Lemma {
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
+    }
+}
+
+impl Domain {
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
     }
 }
 
 impl Shared {
-    pub fn new(text: String) -> Self {
-        Self { text }
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
+    }
+}
+
+impl FnPtr {
+    pub fn new(text: String, span: Span) -> Self {
+        Self { text, span }
+    }
+}
+
+impl Annotation {
+    /// Whether the annotation declares or assigns a ghost variable
+    pub fn is_ghost(&self) -> bool {
+        AnnotationKind::classify(&self.line) == AnnotationKind::Ghost
     }
 }
 
 impl Expr {
-    // TODO: this could well be a function pointer. If we stick to only using
-    // valid function addresses (no unholy pointer arithmetic) we can encode
-    // this as a switch statement checking the expression against all possible
-    // function addresses.
+    /// Name of the called function or, for an indirect call, of the variable holding
+    /// the function pointer. Calls through variables are resolved when lowering to IR.
     pub fn get_label(&self) -> Result<String, TranslationError> {
         match self {
-            Self::Label(label) => Ok(label.to_owned()),
+            Self::Label(label) | Self::Var(label) => Ok(label.to_owned()),
             x => Err(TranslationError::InvalidLabel(x.clone())),
         }
     }
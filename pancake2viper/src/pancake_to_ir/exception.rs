@@ -0,0 +1,206 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    ir::{self, BinOpType, Expr, FnDec, Stmt},
+    utils::EXCEPTION_VAR,
+};
+
+/// Annotation function holding if the function exited by raising the given exception
+const RAISED: &str = "raised";
+
+/// Encodes Pancake exceptions.
+///
+/// Every exception gets a non-zero id, which a method returns in `_exception` if it exits
+/// by raising it. `raise E` sets the id and jumps to the return label. After a call, an
+/// exception raised by the callee is either caught by the call's handler or propagated
+/// by returning. In annotations `raised(E)` holds if the function raised `E`.
+/// Postconditions which do not mention `raised` only have to hold on a normal return.
+/// Programs without exceptions are left unchanged.
+pub(super) fn lower_exceptions(functions: &mut [FnDec]) {
+    let mut names = BTreeSet::new();
+    for f in functions.iter() {
+        collect_exceptions(&f.body, &mut names);
+        let mut collect = |e: &Expr| {
+            if let Some(name) = raised_arg(e) {
+                names.insert(name.to_owned());
+            }
+        };
+        f.body.visit_exprs(&mut collect);
+        f.posts.iter().for_each(|e| e.visit(&mut collect));
+    }
+    if names.is_empty() {
+        return;
+    }
+
+    let lowering = Exceptions {
+        ids: names.into_iter().zip(1..).collect(),
+    };
+    for f in functions.iter_mut() {
+        let body = lowering.stmt(std::mem::replace(&mut f.body, Stmt::Skip));
+        f.body = Stmt::Seq(ir::Seq {
+            stmts: vec![set_exception(0), body],
+        });
+        for post in f.posts.iter_mut() {
+            if !lowering.expr(post) {
+                *post = Expr::BinOp(ir::BinOp {
+                    optype: BinOpType::Imp,
+                    left: Box::new(exception_eq(0)),
+                    right: Box::new(post.clone()),
+                });
+            }
+        }
+        f.raises = true;
+    }
+}
+
+fn collect_exceptions(stmt: &Stmt, names: &mut BTreeSet<String>) {
+    match stmt {
+        Stmt::Raise(raise) => {
+            names.insert(raise.exception.clone());
+        }
+        Stmt::Handle(h) => {
+            names.insert(h.exception.clone());
+            collect_exceptions(&h.handler, names);
+        }
+        Stmt::Definition(def) => collect_exceptions(&def.scope, names),
+        Stmt::Ghost(ghost) => collect_exceptions(&ghost.scope, names),
+        Stmt::Seq(seq) => seq.stmts.iter().for_each(|s| collect_exceptions(s, names)),
+        Stmt::If(i) => {
+            collect_exceptions(&i.if_branch, names);
+            collect_exceptions(&i.else_branch, names);
+        }
+        Stmt::While(w) => collect_exceptions(&w.body, names),
+        Stmt::Spanned(s) => collect_exceptions(&s.stmt, names),
+        _ => (),
+    }
+}
+
+/// Exception `E` of an occurrence of `raised(E)`
+fn raised_arg(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::FunctionCall(call) if call.fname == RAISED => match &call.args[..] {
+            [arg] => match arg.unspanned() {
+                Expr::Var(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn exception_eq(id: i64) -> Expr {
+    Expr::BinOp(ir::BinOp {
+        optype: BinOpType::PancakeEqual,
+        left: Box::new(Expr::Var(EXCEPTION_VAR.into())),
+        right: Box::new(Expr::Const(id)),
+    })
+}
+
+fn set_exception(id: i64) -> Stmt {
+    Stmt::Assign(ir::Assign {
+        lhs: EXCEPTION_VAR.into(),
+        rhs: Expr::Const(id),
+    })
+}
+
+/// Returns if the callee raised an exception
+fn propagate() -> Stmt {
+    Stmt::If(ir::If {
+        cond: Expr::BinOp(ir::BinOp {
+            optype: BinOpType::PancakeNotEqual,
+            left: Box::new(Expr::Var(EXCEPTION_VAR.into())),
+            right: Box::new(Expr::Const(0)),
+        }),
+        if_branch: Box::new(Stmt::Return),
+        else_branch: Box::new(Stmt::Skip),
+    })
+}
+
+fn is_call(expr: &Expr) -> bool {
+    matches!(expr, Expr::MethodCall(_))
+}
+
+fn seq(stmts: Vec<Stmt>) -> Stmt {
+    Stmt::Seq(ir::Seq { stmts })
+}
+
+struct Exceptions {
+    ids: HashMap<String, i64>,
+}
+
+impl Exceptions {
+    fn stmt(&self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Raise(raise) => seq(vec![
+                set_exception(self.ids[&raise.exception]),
+                Stmt::Return,
+            ]),
+            Stmt::Handle(h) => {
+                let caught = Stmt::If(ir::If {
+                    cond: exception_eq(self.ids[&h.exception]),
+                    if_branch: Box::new(seq(vec![set_exception(0), self.stmt(*h.handler)])),
+                    else_branch: Box::new(Stmt::Skip),
+                });
+                seq(vec![*h.call, caught, propagate()])
+            }
+            call @ Stmt::Call(_) => seq(vec![call, propagate()]),
+            Stmt::Assign(ass) if is_call(&ass.rhs) => seq(vec![Stmt::Assign(ass), propagate()]),
+            Stmt::Definition(def) => {
+                let scope = self.stmt(*def.scope);
+                let scope = if is_call(&def.rhs) {
+                    seq(vec![propagate(), scope])
+                } else {
+                    scope
+                };
+                Stmt::Definition(ir::Definition {
+                    lhs: def.lhs,
+                    rhs: def.rhs,
+                    scope: Box::new(scope),
+                })
+            }
+            Stmt::Annotation(mut annot) => {
+                self.expr(&mut annot.expr);
+                Stmt::Annotation(annot)
+            }
+            Stmt::Ghost(mut ghost) => {
+                if let Some(rhs) = &mut ghost.rhs {
+                    self.expr(rhs);
+                }
+                ghost.scope = Box::new(self.stmt(*ghost.scope));
+                Stmt::Ghost(ghost)
+            }
+            Stmt::GhostAssign(mut ass) => {
+                self.expr(&mut ass.rhs);
+                Stmt::GhostAssign(ass)
+            }
+            Stmt::Seq(s) => seq(s.stmts.into_iter().map(|s| self.stmt(s)).collect()),
+            Stmt::If(i) => Stmt::If(ir::If {
+                cond: i.cond,
+                if_branch: Box::new(self.stmt(*i.if_branch)),
+                else_branch: Box::new(self.stmt(*i.else_branch)),
+            }),
+            Stmt::While(w) => Stmt::While(ir::While {
+                cond: w.cond,
+                body: Box::new(self.stmt(*w.body)),
+            }),
+            Stmt::Spanned(s) => Stmt::Spanned(ir::SpannedStmt {
+                stmt: Box::new(self.stmt(*s.stmt)),
+                span: s.span,
+            }),
+            x => x,
+        }
+    }
+
+    /// Replaces `raised(E)` by a check of the exception id, returns if `raised` occurred
+    fn expr(&self, expr: &mut Expr) -> bool {
+        let mut raised = false;
+        expr.visit_mut(&mut |e| {
+            if let Some(&id) = raised_arg(e).and_then(|name| self.ids.get(name)) {
+                *e = exception_eq(id);
+                raised = true;
+            }
+        });
+        raised
+    }
+}
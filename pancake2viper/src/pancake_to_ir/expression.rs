@@ -1,6 +1,6 @@
 use crate::{
     ir, pancake,
-    utils::{TranslationError, TryToIR, TryToIRGeneric},
+    utils::{TranslationError, TryToIR, TryToIRGeneric, WithSpan},
 };
 
 impl TryToIR for pancake::Struct {
@@ -51,6 +51,7 @@ impl TryToIRGeneric<ir::UnOpType> for pancake::OpType {
     fn to_ir(self) -> Result<ir::UnOpType, TranslationError> {
         match self {
             pancake::OpType::Sub => Ok(ir::UnOpType::Minus),
+            pancake::OpType::BitNot => Ok(ir::UnOpType::BitNot),
             _ => panic!("Can't convert Pancake operator '{:?}' to UnOpType", self),
         }
     }
@@ -73,6 +74,7 @@ impl TryToIRGeneric<ir::BinOpType> for pancake::OpType {
             And => BinOpType::BitAnd,
             Or => BinOpType::BitOr,
             Xor => BinOpType::BitXor,
+            BitNot => panic!("Can't convert Pancake operator '{:?}' to BinOpType", self),
         })
     }
 }
@@ -126,7 +128,7 @@ impl TryToIR for pancake::Shift {
         Ok(Self::Output {
             shifttype: self.shifttype.to_ir()?,
             value: Box::new(self.value.to_ir()?),
-            amount: self.amount,
+            amount: Box::new(self.amount.to_ir()?),
         })
     }
 }
@@ -162,6 +164,10 @@ impl TryToIR for pancake::Expr {
             BaseAddr => Self::Output::BaseAddr,
             BytesInWord => Self::Output::BytesInWord,
             Call(call) => Self::Output::MethodCall(call.to_ir()?),
+            Spanned(s) => Self::Output::Spanned(ir::SpannedExpr {
+                expr: Box::new(s.expr.to_ir().with_span(&s.span)?),
+                span: s.span,
+            }),
         })
     }
 }
@@ -0,0 +1,341 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::{
+    ir::{self, Arg, BinOpType, Expr, FnDec, FnPtr, Stmt, Type},
+    utils::{TranslationError, WithSpan},
+};
+
+/// Name of the argument holding the function pointer
+const FNPTR_ARG: &str = "fnptr";
+
+/// Encodes the function pointers of a program.
+///
+/// Every function whose address is taken gets an abstract address `<f>_addr()`. A
+/// `/@ fnptr F(...) @/` type becomes the Viper function `F(fnptr)`, which holds for the
+/// addresses of the functions implementing `F`, and the bodiless method `call_F` with the
+/// contract of `F`. Indirect calls are encoded as calls to `call_F`. If multiple types take
+/// the same number of arguments, the call distinguishes between them at runtime.
+/// For every `/@ implements F @/` annotation the method `<f>_implements_F` checks that the
+/// function satisfies the contract of `F`.
+pub(super) fn lower_fn_ptrs(
+    mut functions: Vec<FnDec>,
+    methods: &[ir::AbstractMethod],
+    fnptrs: &[FnPtr],
+) -> Result<(Vec<FnDec>, Vec<ir::Function>), TranslationError> {
+    let fnames = functions
+        .iter()
+        .map(|f| f.fname.clone())
+        .collect::<HashSet<_>>();
+    // Abstract methods are called directly, like Pancake functions
+    let callees = fnames
+        .iter()
+        .cloned()
+        .chain(methods.iter().map(|m| m.name.clone()))
+        .collect::<HashSet<_>>();
+    let lowering = IndirectCalls {
+        fnptrs,
+        fnames: &callees,
+    };
+    for f in functions.iter_mut() {
+        f.body = lowering.stmt(std::mem::replace(&mut f.body, Stmt::Skip))?;
+    }
+
+    let mut members: HashMap<&str, Vec<String>> = fnptrs
+        .iter()
+        .map(|fnptr| (fnptr.name.as_str(), vec![]))
+        .collect();
+    let mut checks = vec![];
+    let mut labels = BTreeSet::new();
+    for f in &functions {
+        for name in &f.implements {
+            let fnptr = fnptrs
+                .iter()
+                .find(|fnptr| &fnptr.name == name)
+                .ok_or_else(|| TranslationError::UnknownFnPtr(name.clone()))?;
+            if fnptr.args.len() != f.args.len() {
+                return Err(TranslationError::FnPtrArity(
+                    f.fname.clone(),
+                    fnptr.name.clone(),
+                ));
+            }
+            members
+                .get_mut(name.as_str())
+                .unwrap()
+                .push(f.fname.clone());
+            checks.push(implementation_check(f, fnptr));
+            labels.insert(f.fname.clone());
+        }
+        let mut collect = |e: &Expr| {
+            if let Expr::Label(label) = e {
+                labels.insert(label.clone());
+            }
+        };
+        f.body.visit_exprs(&mut collect);
+        f.pres
+            .iter()
+            .chain(&f.posts)
+            .for_each(|e| e.visit(&mut collect));
+    }
+    if let Some(label) = labels.iter().find(|l| !fnames.contains(*l)) {
+        return Err(TranslationError::UnknownFunction(label.clone()));
+    }
+
+    let mut viper_functions = labels.into_iter().map(address_function).collect::<Vec<_>>();
+    viper_functions.extend(
+        fnptrs
+            .iter()
+            .map(|fnptr| membership_function(fnptr, &members[fnptr.name.as_str()])),
+    );
+    functions.extend(fnptrs.iter().map(call_method));
+    functions.extend(checks);
+    Ok((functions, viper_functions))
+}
+
+/// Abstract address of a function, i.e. the value of `&f`
+fn address_function(fname: String) -> ir::Function {
+    ir::Function {
+        name: format!("{}_addr", fname),
+        args: vec![],
+        typ: Type::Int,
+        pres: vec![],
+        posts: vec![Expr::FunctionCall(ir::FunctionCall {
+            fname: "bounded".into(),
+            args: vec![Expr::Var("result".into())],
+        })],
+        body: None,
+    }
+}
+
+fn membership_function(fnptr: &FnPtr, members: &[String]) -> ir::Function {
+    let body = members
+        .iter()
+        .map(|fname| {
+            Expr::BinOp(ir::BinOp {
+                optype: BinOpType::ViperEqual,
+                left: Box::new(Expr::Var(FNPTR_ARG.into())),
+                right: Box::new(Expr::Label(fname.clone())),
+            })
+        })
+        .reduce(|acc, e| {
+            Expr::BinOp(ir::BinOp {
+                optype: BinOpType::BoolOr,
+                left: Box::new(acc),
+                right: Box::new(e),
+            })
+        })
+        .unwrap_or(Expr::BoolLit(false));
+    ir::Function {
+        name: fnptr.name.clone(),
+        args: vec![Arg {
+            name: FNPTR_ARG.into(),
+            typ: Type::Int,
+        }],
+        typ: Type::Bool,
+        pres: vec![],
+        posts: vec![],
+        body: Some(body),
+    }
+}
+
+fn call_method(fnptr: &FnPtr) -> FnDec {
+    let mut args = vec![Arg {
+        name: FNPTR_ARG.into(),
+        typ: Type::Int,
+    }];
+    args.extend(fnptr.args.iter().cloned());
+    let mut pres = vec![Expr::FunctionCall(ir::FunctionCall {
+        fname: fnptr.name.clone(),
+        args: vec![Expr::Var(FNPTR_ARG.into())],
+    })];
+    pres.extend(fnptr.pres.iter().cloned());
+    FnDec {
+        fname: format!("call_{}", fnptr.name),
+        args,
+        pres,
+        posts: fnptr.posts.clone(),
+        body: Stmt::Skip,
+        retvar: "retval".into(),
+        rettyp: Some(Type::Int),
+        trusted: true,
+        implements: vec![],
+        decreases: None,
+        recursive: false,
+        raises: false,
+    }
+}
+
+/// Method checking that the contract of `f` implies the one of the function pointer type
+fn implementation_check(f: &FnDec, fnptr: &FnPtr) -> FnDec {
+    let call = Expr::MethodCall(ir::MethodCall {
+        fname: f.fname.clone(),
+        args: fnptr.args.iter().cloned().map(Expr::from).collect(),
+    });
+    FnDec {
+        fname: format!("{}_implements_{}", f.fname, fnptr.name),
+        args: fnptr.args.clone(),
+        pres: fnptr.pres.clone(),
+        posts: fnptr.posts.clone(),
+        body: Stmt::Seq(ir::Seq {
+            stmts: vec![
+                Stmt::Assign(ir::Assign {
+                    lhs: "retval".into(),
+                    rhs: call,
+                }),
+                Stmt::Return,
+            ],
+        }),
+        retvar: "retval".into(),
+        rettyp: None,
+        trusted: false,
+        implements: vec![],
+        decreases: None,
+        recursive: false,
+        raises: false,
+    }
+}
+
+/// Replaces calls through function pointers with calls to the `call_F` methods
+struct IndirectCalls<'a> {
+    fnptrs: &'a [FnPtr],
+    /// Functions and abstract methods, which are called directly
+    fnames: &'a HashSet<String>,
+}
+
+impl IndirectCalls<'_> {
+    fn stmt(&self, stmt: Stmt) -> Result<Stmt, TranslationError> {
+        Ok(match stmt {
+            Stmt::Call(call) => self.call(call.call, |call| Stmt::Call(ir::Call { call }))?,
+            Stmt::Assign(ass) => self.call(ass.rhs, |rhs| {
+                Stmt::Assign(ir::Assign {
+                    lhs: ass.lhs.clone(),
+                    rhs,
+                })
+            })?,
+            Stmt::Definition(def) => {
+                let scope = self.stmt(*def.scope)?;
+                match self.candidates(&def.rhs)? {
+                    // The call becomes a case distinction, so the variable is assigned in each case
+                    Some(candidates) if candidates.len() > 1 => {
+                        let assign = self.call(def.rhs, |rhs| {
+                            Stmt::Assign(ir::Assign {
+                                lhs: def.lhs.clone(),
+                                rhs,
+                            })
+                        })?;
+                        Stmt::Definition(ir::Definition {
+                            lhs: def.lhs,
+                            rhs: Expr::Const(0),
+                            scope: Box::new(Stmt::Seq(ir::Seq {
+                                stmts: vec![assign, scope],
+                            })),
+                        })
+                    }
+                    candidates => Stmt::Definition(ir::Definition {
+                        rhs: match (candidates, def.rhs) {
+                            (Some(candidates), Expr::MethodCall(call)) => {
+                                call_via(candidates[0], &call)
+                            }
+                            (_, rhs) => rhs,
+                        },
+                        lhs: def.lhs,
+                        scope: Box::new(scope),
+                    }),
+                }
+            }
+            Stmt::Seq(seq) => Stmt::Seq(ir::Seq {
+                stmts: seq
+                    .stmts
+                    .into_iter()
+                    .map(|s| self.stmt(s))
+                    .collect::<Result<_, _>>()?,
+            }),
+            Stmt::If(i) => Stmt::If(ir::If {
+                cond: i.cond,
+                if_branch: Box::new(self.stmt(*i.if_branch)?),
+                else_branch: Box::new(self.stmt(*i.else_branch)?),
+            }),
+            Stmt::While(w) => Stmt::While(ir::While {
+                cond: w.cond,
+                body: Box::new(self.stmt(*w.body)?),
+            }),
+            Stmt::Ghost(mut ghost) => {
+                ghost.scope = Box::new(self.stmt(*ghost.scope)?);
+                Stmt::Ghost(ghost)
+            }
+            Stmt::Handle(h) => Stmt::Handle(ir::Handle {
+                call: Box::new(self.stmt(*h.call)?),
+                exception: h.exception,
+                handler: Box::new(self.stmt(*h.handler)?),
+            }),
+            Stmt::Spanned(s) => Stmt::Spanned(ir::SpannedStmt {
+                stmt: Box::new(self.stmt(*s.stmt).with_span(&s.span)?),
+                span: s.span,
+            }),
+            x => x,
+        })
+    }
+
+    /// Function pointer types matching an indirect call, `None` if `expr` is not one
+    fn candidates(&self, expr: &Expr) -> Result<Option<Vec<&FnPtr>>, TranslationError> {
+        let Expr::MethodCall(call) = expr else {
+            return Ok(None);
+        };
+        if self.fnames.contains(&call.fname) {
+            return Ok(None);
+        }
+        let candidates = self
+            .fnptrs
+            .iter()
+            .filter(|fnptr| fnptr.args.len() == call.args.len())
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(TranslationError::NoFnPtrType(
+                call.fname.clone(),
+                call.args.len(),
+            ));
+        }
+        Ok(Some(candidates))
+    }
+
+    /// Builds the statement performing the call `expr` using `build`. Indirect calls with
+    /// several candidate types check which type the pointer belongs to.
+    fn call(&self, expr: Expr, build: impl Fn(Expr) -> Stmt) -> Result<Stmt, TranslationError> {
+        let Some(candidates) = self.candidates(&expr)? else {
+            return Ok(build(expr));
+        };
+        let Expr::MethodCall(call) = expr else {
+            unreachable!()
+        };
+        if let [fnptr] = candidates[..] {
+            return Ok(build(call_via(fnptr, &call)));
+        }
+        let unreachable = Stmt::Annotation(ir::Annotation {
+            typ: ir::AnnotationType::Assertion,
+            expr: Expr::BoolLit(false),
+            span: None,
+        });
+        Ok(candidates
+            .into_iter()
+            .rev()
+            .fold(unreachable, |else_branch, fnptr| {
+                Stmt::If(ir::If {
+                    cond: Expr::FunctionCall(ir::FunctionCall {
+                        fname: fnptr.name.clone(),
+                        args: vec![Expr::Var(call.fname.clone())],
+                    }),
+                    if_branch: Box::new(build(call_via(fnptr, &call))),
+                    else_branch: Box::new(else_branch),
+                })
+            }))
+    }
+}
+
+/// Call of the `call_F` method for a call through the variable `call.fname`
+fn call_via(fnptr: &FnPtr, call: &ir::MethodCall) -> Expr {
+    let mut args = vec![Expr::Var(call.fname.clone())];
+    args.extend(call.args.iter().cloned());
+    Expr::MethodCall(ir::MethodCall {
+        fname: format!("call_{}", fnptr.name),
+        args,
+    })
+}
@@ -0,0 +1,141 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::termination::{decrease_check, instantiate};
+use crate::{
+    ir::{self, call_graph::CallGraph, AnnotationType, Arg, Expr, FnDec, Lemma, Stmt},
+    utils::{TranslationError, WithSpan},
+};
+
+/// Checks the `apply` statements of a program and makes recursive lemmas terminate.
+///
+/// Lemmas that apply themselves, directly or through other lemmas, need a `decreases`
+/// measure. Before each such application the measure of the applied lemma has to be
+/// non-negative and smaller than the measure of the applying lemma on entry.
+pub(super) fn lower_lemmas(
+    lemmas: &mut [Lemma],
+    functions: &[FnDec],
+) -> Result<(), TranslationError> {
+    let measures = lemmas
+        .iter()
+        .map(|l| (l.name.clone(), (l.args.clone(), l.decreases.clone())))
+        .collect::<HashMap<_, _>>();
+    let known = |call: &ir::FunctionCall| {
+        if measures.contains_key(&call.fname) {
+            Ok(())
+        } else {
+            Err(TranslationError::UnknownLemma(call.fname.clone()))
+        }
+    };
+    for f in functions {
+        applies(&f.body, &mut |call| known(call))?;
+    }
+
+    let mut graph = CallGraph::default();
+    for lemma in lemmas.iter() {
+        graph.add_node(&lemma.name);
+        applies(&lemma.body, &mut |call| {
+            known(call)?;
+            graph.add_call(&lemma.name, &call.fname);
+            Ok(())
+        })?;
+    }
+    let components = graph.recursive_components();
+    for name in components.iter().flatten() {
+        if measures[name].1.is_none() {
+            return Err(TranslationError::MissingMeasure(name.clone()));
+        }
+    }
+
+    for lemma in lemmas.iter_mut() {
+        let Some(component) = components.iter().find(|c| c.contains(&lemma.name)) else {
+            continue;
+        };
+        let termination = Termination {
+            measures: &measures,
+            component,
+            bound: Expr::Old(ir::Old {
+                expr: Box::new(lemma.decreases.clone().unwrap()),
+            }),
+        };
+        lemma.body = termination.stmt(std::mem::replace(&mut lemma.body, Stmt::Skip));
+    }
+    Ok(())
+}
+
+/// Calls `f` on the lemma calls of all `apply` statements in `stmt`
+fn applies(
+    stmt: &Stmt,
+    f: &mut impl FnMut(&ir::FunctionCall) -> Result<(), TranslationError>,
+) -> Result<(), TranslationError> {
+    match stmt {
+        Stmt::Annotation(ir::Annotation {
+            typ: AnnotationType::Apply,
+            expr: Expr::FunctionCall(call),
+            ..
+        }) => f(call),
+        Stmt::Definition(def) => applies(&def.scope, f),
+        Stmt::Ghost(ghost) => applies(&ghost.scope, f),
+        Stmt::Seq(seq) => seq.stmts.iter().try_for_each(|s| applies(s, f)),
+        Stmt::If(i) => {
+            applies(&i.if_branch, f)?;
+            applies(&i.else_branch, f)
+        }
+        Stmt::While(w) => applies(&w.body, f),
+        Stmt::Handle(h) => {
+            applies(&h.call, f)?;
+            applies(&h.handler, f)
+        }
+        Stmt::Spanned(s) => applies(&s.stmt, f).with_span(&s.span),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the measure before every recursive application in a lemma body
+struct Termination<'a> {
+    measures: &'a HashMap<String, (Vec<Arg>, Option<Expr>)>,
+    /// Lemmas that are mutually recursive with the lemma
+    component: &'a BTreeSet<String>,
+    /// Measure of the lemma on entry
+    bound: Expr,
+}
+
+impl Termination<'_> {
+    fn stmt(&self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Annotation(ir::Annotation {
+                typ: AnnotationType::Apply,
+                expr: Expr::FunctionCall(call),
+                span,
+            }) if self.component.contains(&call.fname) => {
+                let check = decrease_check(self.measure(&call), self.bound.clone(), span.clone());
+                let apply = Stmt::Annotation(ir::Annotation {
+                    typ: AnnotationType::Apply,
+                    expr: Expr::FunctionCall(call),
+                    span,
+                });
+                Stmt::Seq(ir::Seq {
+                    stmts: vec![check, apply],
+                })
+            }
+            Stmt::Ghost(mut ghost) => {
+                ghost.scope = Box::new(self.stmt(*ghost.scope));
+                Stmt::Ghost(ghost)
+            }
+            Stmt::Seq(seq) => Stmt::Seq(ir::Seq {
+                stmts: seq.stmts.into_iter().map(|s| self.stmt(s)).collect(),
+            }),
+            Stmt::If(i) => Stmt::If(ir::If {
+                cond: i.cond,
+                if_branch: Box::new(self.stmt(*i.if_branch)),
+                else_branch: Box::new(self.stmt(*i.else_branch)),
+            }),
+            x => x,
+        }
+    }
+
+    /// Measure of the lemma applied by `call`, in terms of the call's arguments
+    fn measure(&self, call: &ir::FunctionCall) -> Expr {
+        let (args, measure) = &self.measures[&call.fname];
+        instantiate(args, measure.as_ref().unwrap(), &call.args)
+    }
+}
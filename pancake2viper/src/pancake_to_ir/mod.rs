@@ -1,4 +1,8 @@
+mod exception;
 mod expression;
+mod fnptr;
+mod lemma;
 mod statement;
+mod termination;
 mod toplevel;
 mod utils;
@@ -1,14 +1,62 @@
 use crate::{
-    annotation::parse_annot,
+    annotation::{parse_annot, parse_ghost},
     ir, pancake,
-    utils::{TranslationError, TryToIR},
+    utils::{Span, TranslationError, TryToIR, WithSpan},
 };
+use pest::{error::LineColLocation, RuleType};
+
+use super::utils::locate_expr;
+
+impl pancake::Annotation {
+    /// Locates a part of the annotation, given relative to its text, in the source
+    fn locate(&self, inner: &Span) -> Option<Span> {
+        let span = self.span.as_ref()?;
+        let start = self
+            .macros
+            .original_pos(&self.line, inner.start_line, inner.start_col);
+        let end = self
+            .macros
+            .original_pos(&self.line, inner.end_line, inner.end_col);
+        Some(span.relocate(&Span::new(None, start, end)))
+    }
+
+    /// Locates an error raised while parsing the annotation in the source
+    fn parse_error<R: RuleType>(&self, err: pest::error::Error<R>) -> TranslationError {
+        let parse_err = TranslationError::ParsingError(err.to_string());
+        match (&self.span, err.line_col) {
+            (Some(span), LineColLocation::Pos((line, col)))
+            | (Some(span), LineColLocation::Span((line, col), _)) => {
+                let (line, col) = self.macros.original_pos(&self.line, line, col);
+                parse_err.with_span(&span.offset(line, col))
+            }
+            _ => parse_err,
+        }
+    }
+
+    /// Translates a ghost variable declaration or assignment
+    fn ghost_to_ir(self) -> Result<ir::Stmt, TranslationError> {
+        let mut ghost = parse_ghost(&self.line).map_err(|err| self.parse_error(*err))?;
+        ghost.visit_exprs_mut(&mut |e| locate_expr(e, |span| self.locate(span)));
+        Ok(match self.span {
+            Some(span) => ir::Stmt::Spanned(ir::SpannedStmt {
+                span,
+                stmt: Box::new(ghost),
+            }),
+            None => ghost,
+        })
+    }
+}
 
 impl TryToIR for pancake::Annotation {
     type Output = ir::Annotation;
 
     fn to_ir(self) -> Result<Self::Output, TranslationError> {
-        parse_annot(&self.line, true).map_err(|err| TranslationError::ParsingError(err.to_string()))
+        let mut annot = parse_annot(&self.line, true).map_err(|err| self.parse_error(*err))?;
+        annot
+            .expr
+            .visit_mut(&mut |e| locate_expr(e, |span| self.locate(span)));
+        annot.span = self.span;
+        Ok(annot)
     }
 }
 
@@ -122,7 +170,21 @@ impl TryToIR for pancake::Seq {
     type Output = ir::Seq;
 
     fn to_ir(self) -> Result<Self::Output, TranslationError> {
-        let stmts = self.stmts.to_ir()?;
+        let mut stmts = vec![];
+        let mut rest = self.stmts.into_iter();
+        while let Some(stmt) = rest.next() {
+            let mut stmt = stmt.to_ir()?;
+            // Ghost variables scope over the remainder of their block
+            if let Some(ghost) = stmt.as_ghost_mut() {
+                let scope = pancake::Seq {
+                    stmts: rest.collect(),
+                };
+                *ghost.scope = ir::Stmt::Seq(scope.to_ir()?);
+                stmts.push(stmt);
+                break;
+            }
+            stmts.push(stmt);
+        }
         Ok(Self::Output { stmts })
     }
 }
@@ -212,6 +274,28 @@ impl TryToIR for pancake::ExtCall {
     }
 }
 
+impl TryToIR for pancake::Raise {
+    type Output = ir::Raise;
+
+    fn to_ir(self) -> Result<Self::Output, TranslationError> {
+        Ok(Self::Output {
+            exception: self.error,
+        })
+    }
+}
+
+impl TryToIR for pancake::Handle {
+    type Output = ir::Handle;
+
+    fn to_ir(self) -> Result<Self::Output, TranslationError> {
+        Ok(Self::Output {
+            call: Box::new(self.call.to_ir()?),
+            exception: self.error,
+            handler: Box::new(self.handler.to_ir()?),
+        })
+    }
+}
+
 impl TryToIR for pancake::Stmt {
     type Output = ir::Stmt;
 
@@ -219,6 +303,7 @@ impl TryToIR for pancake::Stmt {
         use pancake::Stmt::*;
         Ok(match self {
             Skip => Self::Output::Skip,
+            Annotation(annot) if annot.is_ghost() => annot.ghost_to_ir()?,
             Annotation(annot) => Self::Output::Annotation(annot.to_ir()?),
             Declaration(decl) => Self::Output::Definition(decl.to_ir()?),
             Assign(ass) => Self::Output::Assign(ass.to_ir()?),
@@ -237,7 +322,14 @@ impl TryToIR for pancake::Stmt {
             Call(call) => Self::Output::Call(call.to_ir()?),
             TailCall(call) => Self::Output::Seq(call.to_ir()?),
             ExtCall(call) => Self::Output::ExtCall(call.to_ir()?),
-            Raise(_) | Tick => todo!("Raise and Tick are not implemented in Pancake"),
+            Raise(raise) => Self::Output::Raise(raise.to_ir()?),
+            Handle(handle) => Self::Output::Handle(handle.to_ir()?),
+            // Ticks only count clock cycles, which are not modelled
+            Tick => Self::Output::Skip,
+            Spanned(s) => Self::Output::Spanned(ir::SpannedStmt {
+                stmt: Box::new(s.stmt.to_ir().with_span(&s.span)?),
+                span: s.span,
+            }),
         })
     }
 }
@@ -0,0 +1,162 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    ir::{
+        self, call_graph::CallGraph, AnnotationType, Arg, BinOpType, Decl, Expr, FnDec, Stmt, Type,
+    },
+    utils::{ExprSubstitution, Mangler, Span},
+};
+
+/// Makes recursive functions check their `decreases` measure.
+///
+/// Functions that call themselves, directly or through other functions, are marked as
+/// recursive. If they have a measure, it is saved in a ghost variable on entry and every
+/// recursive call has to make the measure of the callee non-negative and smaller.
+pub(super) fn lower_recursion(functions: &mut [FnDec]) {
+    let mut graph = CallGraph::default();
+    for f in functions.iter() {
+        graph.add_node(&f.fname);
+        f.body.visit_exprs(&mut |e| {
+            if let Expr::MethodCall(call) = e {
+                graph.add_call(&f.fname, &call.fname);
+            }
+        });
+    }
+    let components = graph.recursive_components();
+
+    let measures = functions
+        .iter()
+        .filter_map(|f| Some((f.fname.clone(), (f.args.clone(), f.decreases.clone()?))))
+        .collect::<HashMap<_, _>>();
+    for f in functions.iter_mut() {
+        let Some(component) = components.iter().find(|c| c.contains(&f.fname)) else {
+            continue;
+        };
+        f.recursive = true;
+        let Some(measure) = f.decreases.clone() else {
+            continue;
+        };
+        let entry = Mangler::fresh_varname();
+        let calls = RecursiveCalls {
+            measures: &measures,
+            component,
+            bound: Expr::Var(entry.clone()),
+        };
+        let body = calls.stmt(std::mem::replace(&mut f.body, Stmt::Skip));
+        f.body = Stmt::Ghost(ir::Ghost {
+            decl: Decl {
+                name: entry,
+                typ: Type::Int,
+            },
+            rhs: Some(measure),
+            scope: Box::new(body),
+        });
+    }
+}
+
+/// Checks the measure before every recursive call in a function body
+struct RecursiveCalls<'a> {
+    measures: &'a HashMap<String, (Vec<Arg>, Expr)>,
+    /// Functions that are mutually recursive with the function
+    component: &'a BTreeSet<String>,
+    /// Measure of the function on entry
+    bound: Expr,
+}
+
+impl RecursiveCalls<'_> {
+    fn stmt(&self, stmt: Stmt) -> Stmt {
+        match stmt {
+            Stmt::Call(ir::Call {
+                call: Expr::MethodCall(ref call),
+            })
+            | Stmt::Assign(ir::Assign {
+                rhs: Expr::MethodCall(ref call),
+                ..
+            }) => self.checked(call, stmt.clone()),
+            Stmt::Definition(mut def) => {
+                *def.scope = self.stmt(std::mem::replace(&mut *def.scope, Stmt::Skip));
+                match &def.rhs {
+                    Expr::MethodCall(call) => self.checked(&call.clone(), Stmt::Definition(def)),
+                    _ => Stmt::Definition(def),
+                }
+            }
+            Stmt::Ghost(mut ghost) => {
+                *ghost.scope = self.stmt(std::mem::replace(&mut *ghost.scope, Stmt::Skip));
+                Stmt::Ghost(ghost)
+            }
+            Stmt::Seq(seq) => Stmt::Seq(ir::Seq {
+                stmts: seq.stmts.into_iter().map(|s| self.stmt(s)).collect(),
+            }),
+            Stmt::If(i) => Stmt::If(ir::If {
+                cond: i.cond,
+                if_branch: Box::new(self.stmt(*i.if_branch)),
+                else_branch: Box::new(self.stmt(*i.else_branch)),
+            }),
+            Stmt::While(w) => Stmt::While(ir::While {
+                cond: w.cond,
+                body: Box::new(self.stmt(*w.body)),
+            }),
+            Stmt::Handle(h) => Stmt::Handle(ir::Handle {
+                call: Box::new(self.stmt(*h.call)),
+                handler: Box::new(self.stmt(*h.handler)),
+                ..h
+            }),
+            Stmt::Spanned(s) => Stmt::Spanned(ir::SpannedStmt {
+                stmt: Box::new(self.stmt(*s.stmt)),
+                span: s.span,
+            }),
+            x => x,
+        }
+    }
+
+    /// Prefixes `stmt` with the measure check if `call` is recursive
+    fn checked(&self, call: &ir::MethodCall, stmt: Stmt) -> Stmt {
+        match self.measures.get(&call.fname) {
+            Some((args, measure)) if self.component.contains(&call.fname) => {
+                let measure = instantiate(args, measure, &call.args);
+                Stmt::Seq(ir::Seq {
+                    stmts: vec![decrease_check(measure, self.bound.clone(), None), stmt],
+                })
+            }
+            _ => stmt,
+        }
+    }
+}
+
+/// Measure of a function or lemma with parameters `args`, in terms of the call's arguments
+pub(super) fn instantiate(args: &[Arg], measure: &Expr, values: &[Expr]) -> Expr {
+    let mut measure = measure.clone();
+    // Go through fresh names, as the arguments may mention the parameters
+    let fresh = args
+        .iter()
+        .map(|_| Expr::Var(Mangler::fresh_varname()))
+        .collect::<Vec<_>>();
+    for (arg, fresh) in args.iter().zip(&fresh) {
+        measure.substitute(&Expr::Var(arg.name.clone()), fresh);
+    }
+    for (fresh, value) in fresh.iter().zip(values) {
+        measure.substitute(fresh, value);
+    }
+    measure
+}
+
+/// Asserts that `measure` is non-negative and smaller than `bound`
+pub(super) fn decrease_check(measure: Expr, bound: Expr, span: Option<Span>) -> Stmt {
+    Stmt::Annotation(ir::Annotation {
+        typ: AnnotationType::Assertion,
+        expr: binop(
+            BinOpType::BoolAnd,
+            binop(BinOpType::SignedLte, Expr::Const(0), measure.clone()),
+            binop(BinOpType::SignedLt, measure, bound),
+        ),
+        span,
+    })
+}
+
+fn binop(optype: BinOpType, left: Expr, right: Expr) -> Expr {
+    Expr::BinOp(ir::BinOp {
+        optype,
+        left: Box::new(left),
+        right: Box::new(right),
+    })
+}
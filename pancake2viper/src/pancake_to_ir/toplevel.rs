@@ -1,14 +1,24 @@
+use std::collections::HashMap;
+
+use pest::{error::LineColLocation, RuleType};
+
 use crate::{
     annotation::{
-        parse_extern_ffi, parse_extern_field, parse_extern_const, parse_extern_predicate, parse_function, parse_method,
+        parse_domain, parse_extern_ffi, parse_extern_field, parse_extern_const, parse_extern_predicate, parse_fnptr, parse_function, parse_lemma, parse_method,
         parse_model_field, parse_model_predicate, parse_predicate, parse_shared,
     },
     ir::{self, Model},
     pancake,
-    utils::{ToType, TranslationError, TryToIR},
+    utils::{Span, ToType, TranslationError, TryToIR, WithSpan},
 };
 
-use super::utils::stmt_annotation_push;
+use super::{
+    exception::lower_exceptions,
+    fnptr::lower_fn_ptrs,
+    lemma::lower_lemmas,
+    termination::lower_recursion,
+    utils::{locate_expr, stmt_annotation_push},
+};
 
 impl TryToIR for pancake::Arg {
     type Output = ir::Arg;
@@ -33,24 +43,90 @@ impl TryToIR for pancake::FnDec {
                 scope: Box::new(scope),
             })
         });
-        let ((pres, posts), trusted) = stmt_annotation_push(&mut body);
+        let contract = stmt_annotation_push(&mut body);
+        let header = self
+            .contract
+            .to_ir()?
+            .into_iter()
+            .map(ir::Stmt::Annotation)
+            .collect();
+        let header = stmt_annotation_push(&mut ir::Stmt::Seq(ir::Seq { stmts: header }));
+        let contract = match (header.is_empty(), contract.is_empty()) {
+            (false, false) => return Err(TranslationError::DuplicateContract(self.fname)),
+            (false, true) => header,
+            _ => contract,
+        };
         Ok(Self::Output {
             fname: self.fname,
             args,
             body,
-            pres,
-            posts,
+            pres: contract.pres,
+            posts: contract.posts,
             retvar: "retval".into(),
-            trusted,
+            rettyp: self.rettyp.map(|shape| shape.to_type(false)),
+            trusted: contract.trusted,
+            implements: contract.implements,
+            decreases: contract.decreases,
+            recursive: false,
+            raises: false,
         })
     }
 }
 
+impl TryToIR for pancake::Global {
+    type Output = ir::Global;
+
+    fn to_ir(self) -> Result<Self::Output, TranslationError> {
+        let value = self.value.to_ir()?;
+        let mut is_const = true;
+        value.visit(&mut |e| {
+            is_const &= matches!(
+                e,
+                ir::Expr::Const(_)
+                    | ir::Expr::BoolLit(_)
+                    | ir::Expr::Struct(_)
+                    | ir::Expr::Field(_)
+                    | ir::Expr::BinOp(_)
+                    | ir::Expr::UnOp(_)
+                    | ir::Expr::Shift(_)
+                    | ir::Expr::BaseAddr
+                    | ir::Expr::BytesInWord
+                    | ir::Expr::Spanned(_)
+            )
+        });
+        if !is_const {
+            return Err(TranslationError::NonConstantGlobal(self.name));
+        }
+        Ok(Self::Output {
+            name: self.name,
+            typ: self.shape.to_type(false),
+            value,
+            constant: true,
+        })
+    }
+}
+
+/// Locates an error in parsing a top-level annotation in the source
+fn toplevel_error<R: RuleType>(err: pest::error::Error<R>, span: &Span) -> TranslationError {
+    let (LineColLocation::Pos((line, col)) | LineColLocation::Span((line, col), _)) = err.line_col;
+    TranslationError::ParsingError(err.to_string()).with_span(&span.offset(line, col))
+}
+
+/// Locates the expressions of a top-level annotation starting at `span` in the source
+fn locate_exprs<'a>(exprs: impl IntoIterator<Item = &'a mut ir::Expr>, span: &Span) {
+    for expr in exprs {
+        expr.visit_mut(&mut |e| locate_expr(e, |inner| Some(span.relocate(inner))));
+    }
+}
+
 impl TryToIR for pancake::Predicate {
     type Output = ir::Predicate;
 
     fn to_ir(self) -> Result<Self::Output, TranslationError> {
-        parse_predicate(&self.text).map_err(|err| TranslationError::ParsingError(err.to_string()))
+        let mut pred =
+            parse_predicate(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        locate_exprs(&mut pred.body, &self.span);
+        Ok(pred)
     }
 }
 
@@ -58,7 +134,12 @@ impl TryToIR for pancake::Function {
     type Output = ir::Function;
 
     fn to_ir(self) -> Result<Self::Output, TranslationError> {
-        parse_function(&self.text).map_err(|err| TranslationError::ParsingError(err.to_string()))
+        let mut f = parse_function(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        locate_exprs(
+            f.pres.iter_mut().chain(&mut f.posts).chain(&mut f.body),
+            &self.span,
+        );
+        Ok(f)
     }
 }
 
@@ -66,7 +147,34 @@ impl TryToIR for pancake::Method {
     type Output = ir::AbstractMethod;
 
     fn to_ir(self) -> Result<Self::Output, TranslationError> {
-        parse_method(&self.text).map_err(|err| TranslationError::ParsingError(err.to_string()))
+        let mut m = parse_method(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        locate_exprs(m.pres.iter_mut().chain(&mut m.posts), &self.span);
+        Ok(m)
+    }
+}
+
+impl TryToIR for pancake::Lemma {
+    type Output = ir::Lemma;
+
+    fn to_ir(self) -> Result<Self::Output, TranslationError> {
+        let mut lemma = parse_lemma(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        let exprs = lemma.pres.iter_mut().chain(&mut lemma.posts);
+        locate_exprs(exprs.chain(&mut lemma.decreases), &self.span);
+        lemma
+            .body
+            .visit_exprs_mut(&mut |e| locate_expr(e, |inner| Some(self.span.relocate(inner))));
+        Ok(lemma)
+    }
+}
+
+impl TryToIR for pancake::Domain {
+    type Output = ir::Domain;
+
+    fn to_ir(self) -> Result<Self::Output, TranslationError> {
+        let mut domain =
+            parse_domain(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        locate_exprs(domain.axioms.iter_mut().map(|a| &mut a.body), &self.span);
+        Ok(domain)
     }
 }
 
@@ -74,7 +182,21 @@ impl TryToIR for pancake::Shared {
     type Output = ir::Shared;
 
     fn to_ir(self) -> Result<Self::Output, TranslationError> {
-        parse_shared(&self.text).map_err(|err| TranslationError::ParsingError(err.to_string()))
+        let mut shared =
+            parse_shared(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        let exprs = [&mut shared.lower, &mut shared.upper, &mut shared.stride];
+        locate_exprs(exprs, &self.span);
+        Ok(shared)
+    }
+}
+
+impl TryToIR for pancake::FnPtr {
+    type Output = ir::FnPtr;
+
+    fn to_ir(self) -> Result<Self::Output, TranslationError> {
+        let mut fnptr = parse_fnptr(&self.text).map_err(|err| toplevel_error(*err, &self.span))?;
+        locate_exprs(fnptr.pres.iter_mut().chain(&mut fnptr.posts), &self.span);
+        Ok(fnptr)
     }
 }
 
@@ -82,11 +204,56 @@ impl TryFrom<pancake::Program> for ir::Program {
     type Error = TranslationError;
 
     fn try_from(value: pancake::Program) -> Result<Self, Self::Error> {
-        let viper_functions = value.viper_functions.to_ir()?;
+        // Files of the definitions, to report duplicates. Lowering appends generated ones.
+        let mut fn_files = files(value.functions.iter().map(|f| f.span.as_ref()));
+        let global_files = files(value.globals.iter().map(|g| g.span.as_ref()));
+        let predicate_files = files(value.predicates.iter().map(|p| Some(&p.span)));
+        let mut viper_fn_files = files(value.viper_functions.iter().map(|f| Some(&f.span)));
+        let method_files = files(value.methods.iter().map(|m| Some(&m.span)));
+        let lemma_files = files(value.lemmas.iter().map(|l| Some(&l.span)));
+        let domain_files = files(value.domains.iter().map(|d| Some(&d.span)));
+        let shared_files = files(value.shared.iter().map(|s| Some(&s.span)));
+
+        let mut viper_functions = value.viper_functions.to_ir()?;
         let predicates = value.predicates.to_ir()?;
         let functions = value.functions.to_ir()?;
+        let mut globals = value.globals.to_ir()?;
         let methods = value.methods.to_ir()?;
+        let mut lemmas = value.lemmas.to_ir()?;
+        let domains = value.domains.to_ir()?;
         let shared = value.shared.to_ir()?;
+        let fnptrs = value.fnptrs.to_ir()?;
+
+        let (mut functions, fnptr_functions) = lower_fn_ptrs(functions, &methods, &fnptrs)?;
+        viper_functions.extend(fnptr_functions);
+        lower_exceptions(&mut functions);
+        lower_lemmas(&mut lemmas, &functions)?;
+        lower_recursion(&mut functions);
+        fn_files.resize(functions.len(), GENERATED.to_owned());
+        viper_fn_files.resize(viper_functions.len(), GENERATED.to_owned());
+
+        // Once mangled, Pancake functions and Viper declarations share a namespace
+        let domain_fns = domains
+            .iter()
+            .zip(&domain_files)
+            .flat_map(|(d, file)| d.functions.iter().map(move |f| (&f.name, file)));
+        check_unique(
+            functions
+                .iter()
+                .map(|f| &f.fname)
+                .zip(&fn_files)
+                .chain(predicates.iter().map(|p| &p.name).zip(&predicate_files))
+                .chain(viper_functions.iter().map(|f| &f.name).zip(&viper_fn_files))
+                .chain(methods.iter().map(|m| &m.name).zip(&method_files))
+                .chain(lemmas.iter().map(|l| &l.name).zip(&lemma_files))
+                .chain(domain_fns),
+        )?;
+        check_unique(domains.iter().map(|d| &d.name).zip(&domain_files))?;
+        check_unique(shared.iter().map(|s| &s.name).zip(&shared_files))?;
+        check_unique(globals.iter().map(|g| &g.name).zip(&global_files))?;
+        for global in globals.iter_mut() {
+            global.constant = !functions.iter().any(|f| f.body.assigns(&global.name));
+        }
 
         let model_predicates = value
             .model_predicates
@@ -107,6 +274,7 @@ impl TryFrom<pancake::Program> for ir::Program {
         let model = Model {
             predicates: model_predicates,
             fields: model_fields,
+            globals,
         };
 
         let extern_predicates = value
@@ -148,6 +316,8 @@ impl TryFrom<pancake::Program> for ir::Program {
             predicates,
             viper_functions,
             methods,
+            lemmas,
+            domains,
             shared,
             extern_predicates,
             extern_fields,
@@ -157,3 +327,34 @@ impl TryFrom<pancake::Program> for ir::Program {
         })
     }
 }
+
+/// Stands in for the file of declarations generated by lowering
+const GENERATED: &str = "generated code";
+
+/// Names of the files containing `spans`, for error messages
+fn files<'a>(spans: impl Iterator<Item = Option<&'a Span>>) -> Vec<String> {
+    spans
+        .map(|span| {
+            span.and_then(|s| s.file.as_deref())
+                .unwrap_or("<input>")
+                .to_owned()
+        })
+        .collect()
+}
+
+/// Checks that no name is defined twice, given the names with their files
+fn check_unique<'a>(
+    names: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> Result<(), TranslationError> {
+    let mut seen = HashMap::new();
+    for (name, file) in names {
+        if let Some(first) = seen.insert(name, file) {
+            return Err(TranslationError::DuplicateDefinition(
+                name.clone(),
+                first.clone(),
+                file.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
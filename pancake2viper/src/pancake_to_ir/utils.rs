@@ -1,6 +1,6 @@
 use crate::{
-    ir::{Annotation, AnnotationType, Definition, Expr, Seq, Stmt, Type},
-    utils::{Shape, ToType, TryToIR},
+    ir::{Annotation, AnnotationType, Definition, Expr, Seq, SpannedStmt, Stmt, Type},
+    utils::{Shape, Span, ToType, TryToIR},
 };
 
 impl<T: TryToIR> TryToIR for Vec<T> {
@@ -28,49 +28,100 @@ impl ToType for Shape {
     }
 }
 
-fn stmt_annotation_helper(
-    body: &mut Stmt,
-    mut preposts: (Vec<Expr>, Vec<Expr>),
-    mut trusted: bool,
-) -> ((Vec<Expr>, Vec<Expr>), bool) {
+/// Contract of a function, given by the annotations before its header or at the start of its body
+#[derive(Debug, Default)]
+pub struct Contract {
+    pub pres: Vec<Expr>,
+    pub posts: Vec<Expr>,
+    pub trusted: bool,
+    pub implements: Vec<String>,
+    pub decreases: Option<Expr>,
+}
+
+impl Contract {
+    pub fn is_empty(&self) -> bool {
+        self.pres.is_empty()
+            && self.posts.is_empty()
+            && !self.trusted
+            && self.implements.is_empty()
+            && self.decreases.is_none()
+    }
+}
+
+fn stmt_annotation_helper(body: &mut Stmt, contract: &mut Contract) {
     match body {
         Stmt::Annotation(Annotation {
             typ: AnnotationType::Precondition,
             expr,
+            ..
         }) => {
-            preposts.0.push(expr.to_owned());
+            contract.pres.push(expr.to_owned());
             *body = Stmt::Skip;
         }
         Stmt::Annotation(Annotation {
             typ: AnnotationType::Postcondition,
             expr,
+            ..
         }) => {
-            preposts.1.push(expr.to_owned());
+            contract.posts.push(expr.to_owned());
             *body = Stmt::Skip;
         }
         Stmt::Annotation(Annotation {
             typ: AnnotationType::Trusted,
-            expr: _,
+            ..
         }) => {
-            trusted = true;
+            contract.trusted = true;
+            *body = Stmt::Skip;
+        }
+        Stmt::Annotation(Annotation {
+            typ: AnnotationType::Implements,
+            expr: Expr::Var(fnptr),
+            ..
+        }) => {
+            contract.implements.push(fnptr.to_owned());
+            *body = Stmt::Skip;
+        }
+        Stmt::Annotation(Annotation {
+            typ: AnnotationType::Decreases,
+            expr,
+            ..
+        }) => {
+            contract.decreases = Some(expr.to_owned());
             *body = Stmt::Skip;
         }
         Stmt::Seq(Seq { stmts }) => {
             for stmt in stmts {
-                (preposts, trusted) = stmt_annotation_helper(stmt, preposts, trusted);
+                stmt_annotation_helper(stmt, contract);
             }
         }
         Stmt::Definition(Definition {
             scope,
             lhs: _,
             rhs: _,
-        }) => (preposts, trusted) = stmt_annotation_helper(scope, preposts, trusted),
+        }) => stmt_annotation_helper(scope, contract),
+        Stmt::Ghost(ghost) => stmt_annotation_helper(&mut ghost.scope, contract),
+        Stmt::Spanned(SpannedStmt { stmt, .. }) => stmt_annotation_helper(stmt, contract),
         _ => (),
     }
-    (preposts, trusted)
 }
 
-/// Finds pre- and post-conditions in the statement and returns them by removing them.
-pub fn stmt_annotation_push(body: &mut Stmt) -> ((Vec<Expr>, Vec<Expr>), bool) {
-    stmt_annotation_helper(body, (vec![], vec![]), false)
+/// Finds the contract annotations in the statement and returns them by removing them.
+pub fn stmt_annotation_push(body: &mut Stmt) -> Contract {
+    let mut contract = Contract::default();
+    stmt_annotation_helper(body, &mut contract);
+    contract
+}
+
+/// Translates the location the annotation parser attached to `expr`, which is relative
+/// to the parsed text, into the source. Locations `locate` can't translate are dropped.
+pub(super) fn locate_expr(expr: &mut Expr, locate: impl Fn(&Span) -> Option<Span>) {
+    while let Expr::Spanned(s) = expr {
+        match locate(&s.span) {
+            Some(span) => {
+                s.span = span;
+                return;
+            }
+            None => *expr = std::mem::replace(&mut *s.expr, Expr::BaseAddr),
+        }
+    }
 }
@@ -12,16 +12,27 @@ lazy_static! {
 }
 
 fn verify_file(path: &str, incremental: bool) -> anyhow::Result<()> {
-    let mut app = App::new_verification(std::fs::read_to_string(path)?, true);
+    let mut app = App::new_verification(std::fs::read_to_string(path)?, Some(path.into()), true);
     app.options.incremental = incremental;
+    app.options.unbounded_annot_compare = true;
     app.run(&VIPER)
 }
 
 fn verify_file_model(path: &str, incremental: bool) -> anyhow::Result<()> {
-    let mut app = App::new_verification(std::fs::read_to_string(path)?, true);
+    let mut app = App::new_verification(std::fs::read_to_string(path)?, Some(path.into()), true);
     app.options.model = Some(std::fs::read_to_string("./tests/shared/model.vpr")?);
     app.options.incremental = incremental;
+    app.options.unbounded_annot_compare = true;
     app.run(&VIPER)
 }
 
 include!(concat!(env!("OUT_DIR"), "/generated_tests.rs"));
+
+#[test]
+fn signed_annot_compare() {
+    let path = "./tests/options/signed_annot_compare.pnk";
+    let source = std::fs::read_to_string(path).unwrap();
+    App::new_verification(source, Some(path.into()), true)
+        .run(&VIPER)
+        .unwrap();
+}
@@ -10,7 +10,7 @@ use crate::{
     viper_prelude::{utils::Utils, HeapHelper},
 };
 
-use super::{mangler::Mangler, TranslationError, RESERVED};
+use super::{mangler::Mangler, TranslationError, EXCEPTION_VAR, RESERVED};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub enum TranslationMode {
@@ -44,13 +44,17 @@ impl From<AnnotationType> for TranslationMode {
 pub struct TypeContext {
     type_map: HashMap<String, Type>,
     fields: Rc<HashMap<String, Type>>,
+    /// Domain of each domain function
+    domain_functions: HashMap<String, String>,
+    /// Return types of abstract methods with several returns
+    method_returns: HashMap<String, Vec<Type>>,
 }
 
 type Exprs = Vec<ir::Expr>;
 type Args = Vec<ir::Arg>;
 
 #[derive(Debug, Clone)]
-pub struct MethodContext(pub HashMap<String, (Exprs, Exprs, Args)>);
+pub struct MethodContext(pub HashMap<String, (Exprs, Exprs, Args, bool)>);
 
 impl MethodContext {
     pub fn new(functions: &[FnDec]) -> Self {
@@ -58,12 +62,17 @@ impl MethodContext {
         for f in functions {
             annot_ctx.insert(
                 f.fname.clone(),
-                (f.pres.clone(), f.posts.clone(), f.args.clone()),
+                (f.pres.clone(), f.posts.clone(), f.args.clone(), f.raises),
             );
         }
         Self(annot_ctx)
     }
 
+    /// Whether the method returns the exception it raised
+    pub fn raises(&self, name: &str) -> bool {
+        self.0.get(name).is_some_and(|m| m.3)
+    }
+
     pub fn get_pre(&self, name: &str) -> &[ir::Expr] {
         &self.0.get(name).unwrap().0
     }
@@ -83,7 +92,12 @@ impl TypeContext {
             .iter()
             .map(|(&s, t)| (s.to_owned(), t.clone()))
             .collect();
-        Self { type_map, fields }
+        Self {
+            type_map,
+            fields,
+            domain_functions: HashMap::new(),
+            method_returns: HashMap::new(),
+        }
     }
 
     pub fn child(&self) -> Self {
@@ -109,6 +123,24 @@ impl TypeContext {
         self.type_map.insert(var, typ);
     }
 
+    pub fn set_domain_function(&mut self, fname: String, domain: String) {
+        self.domain_functions.insert(fname, domain);
+    }
+
+    /// Domain declaring `fname`, if it is a domain function
+    pub fn get_function_domain(&self, fname: &str) -> Option<&str> {
+        self.domain_functions.get(fname).map(String::as_str)
+    }
+
+    pub fn set_method_returns(&mut self, method: String, typs: Vec<Type>) {
+        self.method_returns.insert(method, typs);
+    }
+
+    /// Return types of `method`, if it returns several values as a struct
+    pub fn get_method_returns(&self, method: &str) -> Option<&[Type]> {
+        self.method_returns.get(method).map(Vec::as_slice)
+    }
+
     pub fn size(&self) -> usize {
         self.type_map.len()
     }
@@ -141,6 +173,7 @@ pub struct ViperEncodeCtx<'a> {
     pub consume_stack: bool,
 
     pub invariants: Vec<viper::Expr<'a>>,
+    pub decreases: Vec<viper::Expr<'a>>,
     predicates: HashSet<String>,
     pub mangler: Mangler,
     pub shared: Rc<SharedContext>,
@@ -163,6 +196,8 @@ pub struct EncodeOptions {
     pub include_prelude: bool,
     pub allow_undefined_shared: bool,
     pub ignore_warnings: bool,
+    pub require_termination: bool,
+    pub unbounded_annot_compare: bool,
 }
 
 impl Default for EncodeOptions {
@@ -178,6 +213,8 @@ impl Default for EncodeOptions {
             include_prelude: true,
             allow_undefined_shared: false,
             ignore_warnings: false,
+            require_termination: false,
+            unbounded_annot_compare: false,
         }
     }
 }
@@ -195,7 +232,7 @@ impl<'a> ViperEncodeCtx<'a> {
         extern_consts: HashMap<String, Type>,
     ) -> Self {
         let heap = HeapHelper::new(ast);
-        let fields_set: HashSet<String> = model.fields.clone().into_iter().collect();
+        let fields_set: HashSet<String> = model.unmangled_names().collect();
         let consts_set: HashSet<String> = extern_consts.keys().cloned().collect();
         let mangler_set: HashSet<String> = fields_set.union(&consts_set).cloned().collect::<HashSet<String>>();
         Self {
@@ -211,6 +248,7 @@ impl<'a> ViperEncodeCtx<'a> {
             options,
             consume_stack: true,
             invariants: vec![],
+            decreases: vec![],
             predicates,
             mangler: Mangler::new(mangler_set),
             shared,
@@ -236,6 +274,7 @@ impl<'a> ViperEncodeCtx<'a> {
             options: self.options,
             consume_stack: self.consume_stack,
             invariants: vec![],
+            decreases: vec![],
             predicates: self.predicates.clone(),
             mangler: self.mangler.clone(),
             shared: self.shared.clone(),
@@ -275,6 +314,10 @@ impl<'a> ViperEncodeCtx<'a> {
         "retval"
     }
 
+    pub fn exception_var_name(&self) -> &'static str {
+        EXCEPTION_VAR
+    }
+
     pub fn new_while_ctx(&mut self) {
         self.while_counter += 1;
     }
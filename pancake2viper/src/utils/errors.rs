@@ -1,6 +1,6 @@
 use crate::{ir, pancake};
 
-use super::shape::Shape;
+use super::{shape::Shape, span::Span};
 
 #[derive(thiserror::Error, Debug)]
 pub enum ToViperError {
@@ -14,10 +14,20 @@ pub enum ToViperError {
     ConditionShape(Shape),
     #[error("Invalid fold/unfold statement: Expression should be predicate access, got {0:?}")]
     InvalidFold(ir::Expr),
+    #[error("Invalid apply statement: Expression should be a lemma call or magic wand, got {0:?}")]
+    InvalidApply(ir::Expr),
+    #[error("Invalid package statement: Expression should be a magic wand, got {0:?}")]
+    InvalidPackage(ir::Expr),
     #[error("Assignment shape mismatch: Lhs: {0:?}, Rhs: {1:?}")]
     MismatchedShapes(Shape, Shape),
     #[error("Can't specify pre-/post-conditions in this position, consider moving it up")]
     InvalidAnnotation,
+    #[error("Loop needs a `decreases` measure")]
+    MissingLoopMeasure,
+    #[error("Loop has more than one `decreases` measure")]
+    DuplicateLoopMeasure,
+    #[error("{0}: {1}")]
+    Spanned(Span, Box<ToViperError>),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -40,12 +50,38 @@ pub enum TranslationError {
     UnknownShape(String),
     #[error("Function {0} has no return type set")]
     UnknownReturnType(String),
+    #[error("Function '{0}' returns values of different shapes: `{1}` and `{2}`")]
+    ReturnShapeMismatch(String, Shape, Shape),
+    #[error("Function '{0}' has contract annotations both before its header and in its body")]
+    DuplicateContract(String),
+    #[error("Global '{0}' must be initialised with a constant expression")]
+    NonConstantGlobal(String),
+    #[error("Global '{0}' of shape `{1}` is initialised with a value of shape `{2}`")]
+    GlobalShapeMismatch(String, Shape, Shape),
     #[error("Invalid label, might be function pointer. Got {0:?}")]
     InvalidLabel(pancake::Expr),
     #[error("Viper field not found {0}")]
     UnknownField(String),
     #[error("Parsing error\n{0}")]
     ParsingError(String),
+    #[error("'{0}' is defined more than once, in {1} and {2}")]
+    DuplicateDefinition(String, String, String),
+    #[error("Function '{0}' is not defined")]
+    UnknownFunction(String),
+    #[error("Function pointer type '{0}' is not defined")]
+    UnknownFnPtr(String),
+    #[error("Function '{0}' does not take the same number of arguments as function pointer type '{1}'")]
+    FnPtrArity(String, String),
+    #[error("'{0}' is neither a function nor a pointer to a function taking {1} argument(s), consider declaring a `fnptr` type")]
+    NoFnPtrType(String, usize),
+    #[error("'{0}' is not a lemma")]
+    UnknownLemma(String),
+    #[error("'{0}' is recursive and needs a `decreases` measure")]
+    MissingMeasure(String),
+    #[error("Method '{0}' has several returns, which must be words or structs, got `{1}`")]
+    NonShapeReturn(String, ir::Type),
+    #[error("{0}: {1}")]
+    Spanned(Span, Box<TranslationError>),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -56,4 +92,136 @@ pub enum MangleError {
     DoubleDeclaration(String),
     #[error("Variable '{0}' has not been declared")]
     UndeclaredVar(String),
+    #[error("Ghost variable '{0}' can only be used in annotations")]
+    GhostInCode(String),
+    #[error("Variable '{0}' is not a ghost variable")]
+    NotGhost(String),
+    #[error("{0}: {1}")]
+    Spanned(Span, Box<MangleError>),
+}
+
+/// Errors raised while reading the output of `cake --pancake --explore`
+#[derive(thiserror::Error, Debug)]
+pub enum FrontendError {
+    #[error("Unsupported {construct}: {sexpr}")]
+    Unsupported {
+        construct: &'static str,
+        sexpr: String,
+    },
+    #[error("Unknown operator '{0}'")]
+    UnknownOperator(String),
+    #[error("Invalid constant '{0}'")]
+    InvalidConstant(String),
+    #[error("Invalid shape '{0}'")]
+    InvalidShape(String),
+    #[error("Malformed S-expression: {0}")]
+    MalformedSExpr(String),
+    #[error("In function '{0}': {1}")]
+    InFunction(String, Box<FrontendError>),
+}
+
+/// Errors raised while finding the annotations in a Pancake source file
+#[derive(thiserror::Error, Debug)]
+pub enum LexError {
+    #[error("{0}: Unterminated annotation")]
+    Unterminated(Span),
+    #[error("{0}: Unknown top-level annotation `{1}`")]
+    UnknownKind(Span, String),
+    #[error("{0}: `{1}` annotations are only allowed at the top level")]
+    NotToplevel(Span, String),
+    #[error("{0}: Contract annotations must precede a function")]
+    DanglingContract(Span),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("Could not read imported file '{0}': {1}")]
+    NotFound(String, std::io::Error),
+    #[error("Import cycle: {0}")]
+    Cycle(String),
+}
+
+/// Attaches the location of the offending Pancake source to an error. Errors
+/// that already carry a location keep the innermost one.
+pub trait WithSpan: Sized {
+    fn has_span(&self) -> bool;
+    fn spanned(self, span: Span) -> Self;
+
+    fn with_span(self, span: &Span) -> Self {
+        if self.has_span() {
+            self
+        } else {
+            self.spanned(span.clone())
+        }
+    }
+
+    fn with_opt_span(self, span: Option<&Span>) -> Self {
+        match span {
+            Some(span) => self.with_span(span),
+            None => self,
+        }
+    }
+}
+
+impl<T, E: WithSpan> WithSpan for Result<T, E> {
+    fn has_span(&self) -> bool {
+        self.as_ref().err().is_some_and(E::has_span)
+    }
+
+    fn spanned(self, span: Span) -> Self {
+        self.map_err(|e| e.spanned(span))
+    }
+
+    fn with_span(self, span: &Span) -> Self {
+        self.map_err(|e| e.with_span(span))
+    }
+}
+
+impl WithSpan for ToViperError {
+    fn has_span(&self) -> bool {
+        match self {
+            Self::Spanned(..) => true,
+            Self::TranslationError(e) => e.has_span(),
+            _ => false,
+        }
+    }
+
+    fn spanned(self, span: Span) -> Self {
+        Self::Spanned(span, Box::new(self))
+    }
+}
+
+impl WithSpan for TranslationError {
+    fn has_span(&self) -> bool {
+        match self {
+            Self::Spanned(..) => true,
+            Self::MangleError(e) => e.has_span(),
+            _ => false,
+        }
+    }
+
+    fn spanned(self, span: Span) -> Self {
+        Self::Spanned(span, Box::new(self))
+    }
+}
+
+impl WithSpan for MangleError {
+    fn has_span(&self) -> bool {
+        matches!(self, Self::Spanned(..))
+    }
+
+    fn spanned(self, span: Span) -> Self {
+        Self::Spanned(span, Box::new(self))
+    }
+}
+
+impl TranslationError {
+    /// Shape or return type that is not known yet, but might be resolved later on
+    pub fn is_unknown(&self) -> bool {
+        match self {
+            Self::UnknownShape(_) | Self::UnknownReturnType(_) => true,
+            Self::Spanned(_, e) => e.is_unknown(),
+            _ => false,
+        }
+    }
 }
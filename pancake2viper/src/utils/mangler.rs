@@ -21,12 +21,14 @@ pub struct Mangler {
     annot_map: HashMap<String, String>,
     var_map: HashMap<String, String>,
     arg_map: HashMap<String, String>,
+    ghost_map: HashMap<String, String>,
     ref_set: HashSet<String>,
 }
 
 pub enum VariableType {
     Variable,
     Argument,
+    Ghost,
 }
 
 impl Mangler {
@@ -44,6 +46,7 @@ impl Mangler {
             annot_map: self.annot_map.clone(),
             var_map: self.var_map.clone(),
             arg_map: self.arg_map.clone(),
+            ghost_map: self.ghost_map.clone(),
             ref_set: self.ref_set.clone(),
         }
     }
@@ -52,6 +55,7 @@ impl Mangler {
         self.annot_map.clear();
         self.var_map.clear();
         self.arg_map.clear();
+        self.ghost_map.clear();
     }
 
     pub fn new_mangled_var(
@@ -65,11 +69,23 @@ impl Mangler {
         if self.ref_set.contains(name.as_str()) {
             return Err(MangleError::DoubleDeclaration(name));
         }
+        // Ghost variables and Pancake variables share their names in annotations
+        let clashes = match (&typ, self.mode) {
+            (VariableType::Ghost, _) => {
+                self.var_map.contains_key(&name) || self.arg_map.contains_key(&name)
+            }
+            (_, TranslationMode::Normal) => self.ghost_map.contains_key(&name),
+            _ => false,
+        };
+        if clashes {
+            return Err(MangleError::DoubleDeclaration(name));
+        }
         let mangled = format!("{}_{}", &name, get_inc_counter());
         let map = match (&typ, self.mode) {
             (VariableType::Variable, TranslationMode::Normal) => &mut self.var_map,
             (VariableType::Variable, _) => &mut self.annot_map,
             (VariableType::Argument, TranslationMode::Normal) => &mut self.arg_map,
+            (VariableType::Ghost, _) => &mut self.ghost_map,
             _ => unreachable!(),
         };
         // Check if variable has already been declared. For normal variables
@@ -90,6 +106,51 @@ impl Mangler {
         self.annot_map.clear();
     }
 
+    /// Binds the `let` variable `name`, shadowing any other variable of that name.
+    /// Returns the mangled name and the binding it shadows.
+    pub fn new_let_var(&mut self, name: &str) -> Result<(String, Option<String>), MangleError> {
+        if RESERVED.contains_key(name) {
+            return Err(MangleError::ReservedKeyword(name.to_owned()));
+        }
+        if self.ref_set.contains(name) {
+            return Err(MangleError::DoubleDeclaration(name.to_owned()));
+        }
+        let mangled = format!("{}_{}", name, get_inc_counter());
+        let shadowed = self.let_map().insert(name.to_owned(), mangled.clone());
+        Ok((mangled, shadowed))
+    }
+
+    /// Ends the scope of the `let` variable `name` and restores the binding it shadowed
+    pub fn drop_let_var(&mut self, name: &str, shadowed: Option<String>) {
+        match shadowed {
+            Some(mangled) => self.let_map().insert(name.to_owned(), mangled),
+            None => self.let_map().remove(name),
+        };
+    }
+
+    /// Map that takes precedence when looking up variables in the current mode
+    fn let_map(&mut self) -> &mut HashMap<String, String> {
+        match self.mode {
+            TranslationMode::Normal | TranslationMode::WhileCond => &mut self.var_map,
+            TranslationMode::Assertion | TranslationMode::PrePost => &mut self.annot_map,
+        }
+    }
+
+    /// Ends the scope of the ghost variable `name`
+    pub fn drop_ghost(&mut self, name: &str) {
+        self.ghost_map.remove(name);
+    }
+
+    pub fn mangle_ghost<'a>(&'a self, var: &'a str) -> Result<&'a str, MangleError> {
+        self.ghost_map
+            .get(var)
+            .map(String::as_str)
+            .ok_or_else(|| match self.mangle_var(var) {
+                Ok(_) => MangleError::NotGhost(var.to_owned()),
+                Err(e) => e,
+            })
+    }
+
     pub fn fresh_varname() -> String {
         let fresh = format!("fr_{}", get_inc_counter());
         fresh
@@ -99,6 +160,10 @@ impl Mangler {
         self.mode = mode;
     }
 
+    pub fn get_mode(&self) -> TranslationMode {
+        self.mode
+    }
+
     pub fn mangle_var<'a>(&'a self, var: &'a str) -> Result<&'a str, MangleError> {
         if RESERVED.contains_key(var) {
             return Ok(var);
@@ -109,13 +174,17 @@ impl Mangler {
         let maybe_arg = self.arg_map.get(var);
         let maybe_annot = self.annot_map.get(var);
         let maybe_var = self.var_map.get(var);
+        let maybe_ghost = self.ghost_map.get(var);
         match self.mode {
             TranslationMode::Normal | TranslationMode::WhileCond => maybe_var.or(maybe_arg),
-            TranslationMode::Assertion => maybe_annot.or(maybe_var),
+            TranslationMode::Assertion => maybe_annot.or(maybe_ghost).or(maybe_var).or(maybe_arg),
             TranslationMode::PrePost => maybe_annot.or(maybe_arg),
         }
         .map(String::as_str)
-        .ok_or(MangleError::UndeclaredVar(var.to_owned()))
+        .ok_or_else(|| match maybe_ghost {
+            Some(_) if !self.mode.is_annot() => MangleError::GhostInCode(var.to_owned()),
+            _ => MangleError::UndeclaredVar(var.to_owned()),
+        })
     }
 
     pub fn switch_ctx(&mut self, fname: String) {
@@ -3,6 +3,7 @@ mod errors;
 mod mangler;
 mod misc;
 mod shape;
+mod span;
 mod traits;
 
 use std::collections::HashMap;
@@ -12,10 +13,15 @@ pub use errors::*;
 pub use mangler::{Mangler, VariableType};
 pub use misc::ViperHandle;
 pub use shape::Shape;
+pub use span::Span;
 pub use traits::*;
 
 use crate::ir::types::Type;
 
+/// Return variable holding the id of the raised exception, `0` on a normal return.
+/// Pancake identifiers cannot start with `_`, so it never clashes with a program's names.
+pub const EXCEPTION_VAR: &str = "_exception";
+
 lazy_static::lazy_static! {
     pub static ref RESERVED: HashMap<&'static str, Type> = HashMap::from([
         ("heap", Type::Seq(Box::new(Type::Ref))),
@@ -26,6 +32,7 @@ lazy_static::lazy_static! {
         ("alen", Type::Int),
         ("old", Type::Wildcard),
         ("result", Type::Wildcard),
+        (EXCEPTION_VAR, Type::Int),
         ("bounded", Type::Bool),
         ("bounded8", Type::Bool),
         ("bounded16", Type::Bool),
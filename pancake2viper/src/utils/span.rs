@@ -0,0 +1,71 @@
+use std::{fmt::Display, sync::Arc};
+
+/// Location of a statement, annotation or expression in the Pancake source. Lines and
+/// columns are 1-based, the end position is exclusive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: Option<Arc<str>>,
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl Span {
+    pub fn new(file: Option<Arc<str>>, start: (usize, usize), end: (usize, usize)) -> Self {
+        Self {
+            file,
+            start_line: start.0,
+            start_col: start.1,
+            end_line: end.0,
+            end_col: end.1,
+        }
+    }
+
+    /// Span covering a single position
+    pub fn point(file: Option<Arc<str>>, line: usize, col: usize) -> Self {
+        Self::new(file, (line, col), (line, col + 1))
+    }
+
+    /// Translates a position relative to the start of this span (e.g. as reported
+    /// by the annotation parser) into a position in the source file
+    pub fn offset(&self, line: usize, col: usize) -> Self {
+        let (line, col) = self.position(line, col);
+        Self::point(self.file.clone(), line, col)
+    }
+
+    /// Translates a span relative to the start of this span into the source file
+    pub fn relocate(&self, inner: &Span) -> Self {
+        Self::new(
+            self.file.clone(),
+            self.position(inner.start_line, inner.start_col),
+            self.position(inner.end_line, inner.end_col),
+        )
+    }
+
+    /// Span from the start of this span to the end of `other`
+    pub fn join(&self, other: &Span) -> Self {
+        Self::new(
+            self.file.clone(),
+            (self.start_line, self.start_col),
+            (other.end_line, other.end_col),
+        )
+    }
+
+    fn position(&self, line: usize, col: usize) -> (usize, usize) {
+        if line <= 1 {
+            (self.start_line, self.start_col + col.saturating_sub(1))
+        } else {
+            (self.start_line + line - 1, col)
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}", file, self.start_line, self.start_col),
+            None => write!(f, "line {}:{}", self.start_line, self.start_col),
+        }
+    }
+}
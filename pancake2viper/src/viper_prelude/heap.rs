@@ -1,4 +1,4 @@
-use viper::{AstFactory, Expr, Field, Predicate, Type};
+use viper::{AstFactory, Expr, Field, Function, Predicate, Type};
 
 use crate::utils::ViperUtils;
 
@@ -12,6 +12,16 @@ pub enum MemType {
     Shared,
 }
 
+impl MemType {
+    /// Name of the function returning the values of a region of the heap
+    fn slice_f(&self) -> &'static str {
+        match self {
+            Self::Pancake => "heap_slice_pan",
+            Self::Shared => "heap_slice_shared",
+        }
+    }
+}
+
 impl<'a> HeapHelper<'a> {
     pub fn new(ast: AstFactory<'a>) -> Self {
         Self { ast }
@@ -120,6 +130,74 @@ impl<'a> HeapHelper<'a> {
         ast.forall(&[j_decl], &[], ast.implies(guard, access))
     }
 
+    /// Encodes the values of the heap region `[lower, upper)` as a sequence
+    /// ```viper
+    /// heap_slice_pan(heap, lower, upper)
+    /// ```
+    pub fn slice(&self, heap: Expr, lower: Expr, upper: Expr, mem: MemType) -> Expr<'a> {
+        let ast = self.ast;
+        ast.func_app(
+            mem.slice_f(),
+            &[heap, lower, upper],
+            ast.seq_type(ast.int_type()),
+            ast.no_position(),
+        )
+    }
+
+    /// Encodes the following function for the values of a heap region
+    /// ```viper
+    /// function heap_slice_pan(heap: Seq[Ref], lower: Int, upper: Int): Seq[Int]
+    ///     requires forall i: Int, j: Int :: 0 <= i < |heap| && 0 <= j < |heap| && i != j ==> heap[i] != heap[j]
+    ///     requires 0 <= lower <= upper <= |heap|
+    ///     requires forall j: Int :: lower <= j < upper ==> acc(heap[j].pan, wildcard)
+    ///     ensures |result| == upper - lower
+    ///     ensures forall j: Int :: 0 <= j < upper - lower ==> result[j] == heap[lower + j].pan
+    /// ```
+    pub fn slice_def(&self, mem: MemType) -> Function<'a> {
+        let ast = self.ast;
+        let (heap_decl, heap) = ast.new_var("heap", self.get_type());
+        let (lower_decl, lower) = ast.new_var("lower", ast.int_type());
+        let (upper_decl, upper) = ast.new_var("upper", ast.int_type());
+        let (j_decl, j) = ast.new_var("j", ast.int_type());
+        let typ = ast.seq_type(ast.int_type());
+        let result = ast.result_with_pos(typ, ast.no_position());
+        let field = match mem {
+            MemType::Pancake => self.field_internal(),
+            MemType::Shared => self.field_shared(),
+        };
+
+        let bounds = ast.and(
+            ast.and(ast.le_cmp(ast.zero(), lower), ast.le_cmp(lower, upper)),
+            ast.le_cmp(upper, self.len_f(heap)),
+        );
+        let in_slice = ast.and(ast.le_cmp(lower, j), ast.lt_cmp(j, upper));
+        let cell = ast.field_access(self.idx_f(heap, j), field);
+        let access = ast.forall(
+            &[j_decl],
+            &[],
+            ast.implies(in_slice, ast.field_access_predicate(cell, ast.wildcard_perm())),
+        );
+
+        let length = ast.sub(upper, lower);
+        let in_result = ast.and(ast.le_cmp(ast.zero(), j), ast.lt_cmp(j, length));
+        let value = ast.eq_cmp(
+            ast.seq_index(result, j),
+            ast.field_access(self.idx_f(heap, ast.add(lower, j)), field),
+        );
+        ast.function(
+            mem.slice_f(),
+            &[heap_decl, lower_decl, upper_decl],
+            typ,
+            &[self.heap_injective(heap), bounds, access],
+            &[
+                ast.eq_cmp(self.len_f(result), length),
+                ast.forall(&[j_decl], &[], ast.implies(in_result, value)),
+            ],
+            ast.no_position(),
+            None,
+        )
+    }
+
     /// Sequence injective assumption on heap variable
     /// ```viper
     ///     forall i: Int, j: Int :: 
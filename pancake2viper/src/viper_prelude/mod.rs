@@ -5,6 +5,7 @@ pub mod shared_mem;
 pub mod utils;
 
 use bitvector::create_bv_domain;
+use heap::MemType;
 pub use heap::HeapHelper;
 use shared_mem::create_shared_mem_methods;
 use utils::{bound_bits_function, bound_function, Utils};
@@ -35,6 +36,7 @@ pub fn create_viper_prelude(
             .into_iter()
             .map(|bits| bound_bits_function(ast, bits))
             .chain(std::iter::once(bound_function(ast, &utils, options)))
+            .chain([MemType::Pancake, MemType::Shared].map(|mem| heap.slice_def(mem)))
             .collect(),
     )
 }